@@ -0,0 +1,53 @@
+/***************************************************************
+*
+* Mide la velocidad del generador de jugadas con perft
+*
+*     cargo run -p ajedrez --release --example perft [profundidad]
+*
+* Sin profundidad usa la 4, que en las posiciones de prueba son
+* entre doscientos mil y cuatro millones de nodos.
+*
+***************************************************************/
+
+extern crate ajedrez;
+
+use std::env;
+use std::time::Instant;
+
+use ajedrez::{Tablero, set_fen, perft};
+
+
+const POSICIONES: [(&str, &str); 6] = [
+    ("inicial", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+    ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+    ("posicion 3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+    ("posicion 4", "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"),
+    ("posicion 5", "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8"),
+    ("posicion 6", "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10"),
+];
+
+
+fn main() {
+    let profundidad: u32 = env::args().nth(1)
+                            .and_then(|p| p.parse().ok())
+                            .unwrap_or(4);
+    
+    let mut nodos_total: u64 = 0;
+    let mut segundos_total: f64 = 0.0;
+    
+    for &(nombre, fen) in POSICIONES.iter() {
+        let mut board = Tablero::init();
+        set_fen(fen, &mut board).unwrap();
+        
+        let inicio = Instant::now();
+        let nodos = perft(&mut board, profundidad);
+        let segundos = inicio.elapsed().as_secs_f64();
+        
+        println!("{:<12} perft({}) = {:>10}  {:>7.3} s  {:>6.2} Mnodos/s",
+                    nombre, profundidad, nodos, segundos, nodos as f64 / segundos / 1e6);
+        nodos_total += nodos;
+        segundos_total += segundos;
+    }
+    println!("{:<12}            {:>10}  {:>7.3} s  {:>6.2} Mnodos/s",
+                "total", nodos_total, segundos_total, nodos_total as f64 / segundos_total / 1e6);
+}
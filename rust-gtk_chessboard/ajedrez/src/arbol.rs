@@ -0,0 +1,541 @@
+/***************************************************************
+*
+* El arbol de jugadas de una partida
+*
+* Cada nodo guarda la jugada que lleva a el, su SAN, comentarios y NAGs,
+* y las flechas y circulos dibujados sobre su posicion, que en el PGN
+* van dentro del comentario con las ordenes [%cal Ge2e4] y [%csl Rd5].
+* Los hijos de un nodo son las distintas continuaciones: el primero es
+* la linea principal y el resto son variantes.
+* Los nodos se guardan en un Vec y se referencian por su indice;
+* el nodo 0 es la raiz (la posicion inicial, sin jugada).
+*
+***************************************************************/
+
+use std::fmt;
+
+use super::{Tablero, Movim, get_fen, set_fen, mueve_atras, mueve_adelante, crea_san};
+use super::tipos::Square;
+
+
+// ========= las marcas dibujadas sobre el tablero ==================
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ColorMarca {
+    Verde,
+    Rojo,
+    Amarillo,
+    Azul,
+}
+
+impl ColorMarca {
+    // la letra del color en las ordenes del PGN
+    pub fn letra(self) -> char {
+        match self {
+            ColorMarca::Verde => 'G',
+            ColorMarca::Rojo => 'R',
+            ColorMarca::Amarillo => 'Y',
+            ColorMarca::Azul => 'B',
+        }
+    }
+
+    pub fn de_letra(letra: char) -> Option<ColorMarca> {
+        match letra {
+            'G' => Some(ColorMarca::Verde),
+            'R' => Some(ColorMarca::Rojo),
+            'Y' => Some(ColorMarca::Amarillo),
+            'B' => Some(ColorMarca::Azul),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Flecha {
+    pub color: ColorMarca,
+    pub desde: Square,
+    pub hasta: Square,
+}
+
+impl Flecha {
+    // "Ge2e4"
+    pub fn lee(texto: &str) -> Option<Flecha> {
+        let color = ColorMarca::de_letra(texto.chars().next()?)?;
+        if texto.len() != 5 || !texto.is_ascii() {
+            return None;
+        }
+        Some(Flecha {
+            color,
+            desde: Square::from_algebraic(&texto[1..3]).ok()?,
+            hasta: Square::from_algebraic(&texto[3..5]).ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Flecha {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.color.letra(), self.desde.algebraic(), self.hasta.algebraic())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Circulo {
+    pub color: ColorMarca,
+    pub casilla: Square,
+}
+
+impl Circulo {
+    // "Rd5"
+    pub fn lee(texto: &str) -> Option<Circulo> {
+        let color = ColorMarca::de_letra(texto.chars().next()?)?;
+        if texto.len() != 3 || !texto.is_ascii() {
+            return None;
+        }
+        Some(Circulo {
+            color,
+            casilla: Square::from_algebraic(&texto[1..3]).ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Circulo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.color.letra(), self.casilla.algebraic())
+    }
+}
+
+
+// ========= un nodo del arbol ==================
+#[derive(Clone)]
+pub struct Nodo {
+    pub movim: Movim,               // la jugada que lleva a este nodo (vacia en la raiz)
+    pub san: String,                // la jugada en SAN
+    pub num_jugada: i16,            // numero de jugada completa de la jugada
+    pub comentario_previo: String,  // comentario antes de la jugada
+    pub comentario: String,         // comentario despues de la jugada
+    pub nags: Vec<u8>,              // anotaciones numericas ($1 = !, $2 = ?, ...)
+    pub flechas: Vec<Flecha>,       // las marcas de la posicion del nodo
+    pub circulos: Vec<Circulo>,
+    pub padre: usize,               // el nodo anterior (la raiz es su propio padre)
+    pub hijos: Vec<usize>,          // hijos[0] es la linea principal, el resto variantes
+}
+
+impl Nodo {
+    fn init(movim: Movim, san: String, num_jugada: i16, padre: usize) -> Nodo {
+        Nodo {
+            movim,
+            san,
+            num_jugada,
+            comentario_previo: "".to_string(),
+            comentario: "".to_string(),
+            nags: Vec::new(),
+            flechas: Vec::new(),
+            circulos: Vec::new(),
+            padre,
+            hijos: Vec::new(),
+        }
+    }
+
+    // true si la jugada la hacen las blancas
+    pub fn blancas(&self) -> bool {
+        self.movim.piece_moving > 0
+    }
+
+    // pone la flecha; si ya estaba del mismo color la quita y si era de
+    // otro color se lo cambia
+    pub fn cambia_flecha(&mut self, flecha: Flecha) {
+        match self.flechas.iter().position(|f| f.desde == flecha.desde && f.hasta == flecha.hasta) {
+            Some(i) if self.flechas[i].color == flecha.color => {
+                self.flechas.remove(i);
+            },
+            Some(i) => self.flechas[i].color = flecha.color,
+            None => self.flechas.push(flecha),
+        }
+    }
+
+    // lo mismo con los circulos
+    pub fn cambia_circulo(&mut self, circulo: Circulo) {
+        match self.circulos.iter().position(|c| c.casilla == circulo.casilla) {
+            Some(i) if self.circulos[i].color == circulo.color => {
+                self.circulos.remove(i);
+            },
+            Some(i) => self.circulos[i].color = circulo.color,
+            None => self.circulos.push(circulo),
+        }
+    }
+
+    // el comentario de despues de la jugada tal como va en el PGN:
+    // primero las ordenes de las marcas y luego el texto
+    pub fn comentario_pgn(&self) -> String {
+        let mut partes: Vec<String> = Vec::new();
+        if !self.circulos.is_empty() {
+            let circulos: Vec<String> = self.circulos.iter().map(|c| c.to_string()).collect();
+            partes.push(format!("[%csl {}]", circulos.join(",")));
+        }
+        if !self.flechas.is_empty() {
+            let flechas: Vec<String> = self.flechas.iter().map(|f| f.to_string()).collect();
+            partes.push(format!("[%cal {}]", flechas.join(",")));
+        }
+        if !self.comentario.is_empty() {
+            partes.push(self.comentario.clone());
+        }
+        partes.join(" ")
+    }
+}
+
+
+/*
+ *  Separa de un comentario del PGN las ordenes [%cal ...] y [%csl ...].
+ *  Las demas ordenes ([%clk], [%eval]...) se quedan en el texto, y las
+ *  marcas que no se entienden se ignoran
+ *
+ *  @return (String, Vec<Flecha>, Vec<Circulo>)  el texto que queda y las marcas
+*/
+pub fn extrae_marcas(comentario: &str) -> (String, Vec<Flecha>, Vec<Circulo>) {
+    let mut textos: Vec<&str> = Vec::new();
+    let mut flechas: Vec<Flecha> = Vec::new();
+    let mut circulos: Vec<Circulo> = Vec::new();
+    let mut resto = comentario;
+    let mut desde = 0;
+    while let Some(inicio) = resto[desde..].find("[%").map(|i| desde + i) {
+        let fin = match resto[inicio..].find(']') {
+            Some(fin) => inicio + fin,
+            None => break,
+        };
+        let mut partes = resto[inicio + 2..fin].split_whitespace();
+        let orden = partes.next();
+        let marcas = partes.flat_map(|p| p.split(',')).filter(|m| !m.is_empty());
+        match orden {
+            Some("cal") => flechas.extend(marcas.filter_map(Flecha::lee)),
+            Some("csl") => circulos.extend(marcas.filter_map(Circulo::lee)),
+            _ => {
+                desde = fin + 1;
+                continue;
+            },
+        }
+        textos.push(&resto[..inicio]);
+        resto = &resto[fin + 1..];
+        desde = 0;
+    }
+    textos.push(resto);
+    let texto = textos.iter().flat_map(|t| t.split_whitespace()).collect::<Vec<&str>>().join(" ");
+    (texto, flechas, circulos)
+}
+
+
+// ========= el arbol de la partida ==================
+#[derive(Clone)]
+pub struct ArbolPartida {
+    pub fen_inicial: String,        // la posicion de la raiz
+    pub nodos: Vec<Nodo>,
+    pub actual: usize,              // el nodo en el que esta el cursor
+}
+
+impl ArbolPartida {
+    // crea un arbol vacio cuya raiz es la posicion del tablero
+    pub fn nuevo(board: &mut Tablero) -> ArbolPartida {
+        let raiz = Nodo::init(Movim::init(0,0,0,0,0,[0,0,0,0]), "".to_string(), board.moves_full, 0);
+        ArbolPartida {
+            fen_inicial: get_fen(board),
+            nodos: vec![raiz],
+            actual: 0,
+        }
+    }
+
+    // un tablero en la posicion inicial del arbol
+    pub fn tablero_inicial(&self) -> Tablero {
+        let mut board = Tablero::init();
+        // la FEN de la raiz sale siempre de un tablero valido
+        set_fen(&self.fen_inicial, &mut board).unwrap();
+        board
+    }
+
+    // un tablero en la posicion del nodo, con el historico de las jugadas que llevan a el
+    pub fn tablero_en(&self, nodo: usize) -> Tablero {
+        let mut board = self.tablero_inicial();
+        for n in self.camino(nodo) {
+            if mueve_adelante(&mut board, self.nodos[n].movim).is_err() {
+                break;
+            }
+        }
+        board
+    }
+
+    // los nodos desde la raiz (excluida) hasta el nodo (incluido)
+    pub fn camino(&self, nodo: usize) -> Vec<usize> {
+        let mut camino: Vec<usize> = Vec::new();
+        let mut n = nodo;
+        while n != 0 {
+            camino.push(n);
+            n = self.nodos[n].padre;
+        }
+        camino.reverse();
+        camino
+    }
+
+    // los nodos de la linea principal a partir de la raiz
+    pub fn linea_principal(&self) -> Vec<usize> {
+        self.continuacion(0)
+    }
+
+    // los nodos que siguen al nodo por la linea principal
+    pub fn continuacion(&self, nodo: usize) -> Vec<usize> {
+        let mut linea: Vec<usize> = Vec::new();
+        let mut n = nodo;
+        while let Some(&hijo) = self.nodos[n].hijos.first() {
+            linea.push(hijo);
+            n = hijo;
+        }
+        linea
+    }
+
+    // el ultimo nodo de la linea principal que pasa por el nodo
+    pub fn final_linea(&self, nodo: usize) -> usize {
+        match self.continuacion(nodo).last() {
+            Some(&ultimo) => ultimo,
+            None => nodo,
+        }
+    }
+
+    // true si el nodo a esta en el camino de la raiz al nodo b (o es el mismo)
+    pub fn es_ancestro(&self, a: usize, b: usize) -> bool {
+        let mut n = b;
+        loop {
+            if n == a {
+                return true;
+            }
+            if n == 0 {
+                return false;
+            }
+            n = self.nodos[n].padre;
+        }
+    }
+
+    /*
+     *  Registra en el arbol la ultima jugada del historico del tablero,
+     *  que tiene que haberse hecho desde la posicion del nodo actual.
+     *  Si ya existe esa continuacion solo avanzamos; si no, se crea una
+     *  nueva linea (una variante si el nodo ya tenia continuacion)
+     *
+     *  @param board        el tablero despues de la jugada
+     *  @return usize       el nodo de la jugada, que pasa a ser el actual
+    */
+    pub fn anade_hecha(&mut self, board: &Tablero) -> usize {
+        let movim = match board.history.last() {
+            Some(hist) => hist.movim(),
+            None => return self.actual,
+        };
+
+        for &hijo in self.nodos[self.actual].hijos.iter() {
+            let m = self.nodos[hijo].movim;
+            if m.from_index == movim.from_index && m.to_index == movim.to_index &&
+                        m.move_type == movim.move_type {
+                self.actual = hijo;
+                return hijo;
+            }
+        }
+
+        let san = crea_san(&mut board.clone(), movim);
+        let mut num_jugada = board.moves_full;
+        if movim.piece_moving < 0 {
+            // make_movim ya ha incrementado el numero de jugada
+            num_jugada -= 1;
+        }
+        let nuevo = self.nodos.len();
+        self.nodos.push(Nodo::init(movim, san, num_jugada, self.actual));
+        let actual = self.actual;
+        self.nodos[actual].hijos.push(nuevo);
+        self.actual = nuevo;
+        nuevo
+    }
+
+    // hace la jugada en el tablero y la registra en el arbol
+    pub fn anade_jugada(&mut self, board: &mut Tablero, movim: Movim) -> usize {
+        if mueve_adelante(board, movim).is_err() {
+            return self.actual;
+        }
+        self.anade_hecha(board)
+    }
+
+    // retrocede una jugada. Retorna false si estamos en la raiz
+    pub fn retrocede(&mut self, board: &mut Tablero) -> bool {
+        if self.actual == 0 || mueve_atras(board).is_err() {
+            return false;
+        }
+        self.actual = self.nodos[self.actual].padre;
+        true
+    }
+
+    // avanza por el hijo indicado (0 = linea principal). Retorna false si no existe
+    pub fn avanza(&mut self, board: &mut Tablero, hijo: usize) -> bool {
+        let siguiente = match self.nodos[self.actual].hijos.get(hijo) {
+            Some(&siguiente) => siguiente,
+            None => return false,
+        };
+        if mueve_adelante(board, self.nodos[siguiente].movim).is_err() {
+            return false;
+        }
+        self.actual = siguiente;
+        true
+    }
+
+    // lleva el cursor y el tablero a cualquier nodo del arbol
+    pub fn ve_a(&mut self, board: &mut Tablero, nodo: usize) {
+        if nodo >= self.nodos.len() {
+            return;
+        }
+        // retrocedemos hasta un antecesor comun
+        while !self.es_ancestro(self.actual, nodo) {
+            if !self.retrocede(board) {
+                break;
+            }
+        }
+        // y avanzamos por el camino hasta el nodo
+        let camino = self.camino(nodo);
+        let hechos = self.camino(self.actual).len();
+        for &n in camino.iter().skip(hechos) {
+            if mueve_adelante(board, self.nodos[n].movim).is_err() {
+                return;
+            }
+            self.actual = n;
+        }
+    }
+
+    // el primer nodo de la variante a la que pertenece el nodo,
+    // o None si el nodo esta en la linea principal de la partida
+    pub fn inicio_variante(&self, nodo: usize) -> Option<usize> {
+        let mut n = nodo;
+        while n != 0 {
+            let padre = self.nodos[n].padre;
+            if self.nodos[padre].hijos[0] != n {
+                return Some(n);
+            }
+            n = padre;
+        }
+        None
+    }
+
+    // sube un nivel la variante que contiene al nodo: pasa a ser
+    // la continuacion principal en su punto de ramificacion
+    pub fn promueve_variante(&mut self, nodo: usize) -> bool {
+        let inicio = match self.inicio_variante(nodo) {
+            Some(inicio) => inicio,
+            None => return false,
+        };
+        let padre = self.nodos[inicio].padre;
+        self.nodos[padre].hijos.retain(|&h| h != inicio);
+        self.nodos[padre].hijos.insert(0, inicio);
+        true
+    }
+
+    // borra la variante que contiene al nodo. Si el cursor estaba en ella
+    // se lleva al punto de ramificacion
+    pub fn borra_variante(&mut self, board: &mut Tablero, nodo: usize) -> bool {
+        match self.inicio_variante(nodo) {
+            Some(inicio) => self.borra_desde(board, inicio),
+            None => false,
+        }
+    }
+
+    // borra el nodo y todo lo que le sigue
+    pub fn borra_desde(&mut self, board: &mut Tablero, nodo: usize) -> bool {
+        if nodo == 0 || nodo >= self.nodos.len() {
+            return false;
+        }
+        let padre = self.nodos[nodo].padre;
+        if self.es_ancestro(nodo, self.actual) {
+            self.ve_a(board, padre);
+        }
+        // el nodo queda en el Vec, pero ya no es alcanzable desde la raiz
+        self.nodos[padre].hijos.retain(|&h| h != nodo);
+        true
+    }
+
+    /*
+     *  El texto de las jugadas en formato PGN, con las variantes entre parentesis,
+     *  los NAGs y los comentarios. Se retorna como una lista de simbolos
+     *  para que quien la use pueda partir las lineas donde quiera
+    */
+    pub fn simbolos_pgn(&self) -> Vec<String> {
+        let mut simbolos: Vec<String> = Vec::new();
+        let comentario = self.nodos[0].comentario_pgn();
+        if !comentario.is_empty() {
+            simbolos.push(format!("{{{}}}", comentario));
+        }
+        self.escribe_linea(0, true, &mut simbolos);
+        simbolos
+    }
+
+    pub fn texto_pgn(&self) -> String {
+        self.simbolos_pgn().join(" ")
+    }
+
+    fn escribe_jugada(&self, nodo: usize, con_numero: bool, simbolos: &mut Vec<String>) {
+        let n = &self.nodos[nodo];
+        if !n.comentario_previo.is_empty() {
+            simbolos.push(format!("{{{}}}", n.comentario_previo));
+        }
+        if n.blancas() {
+            simbolos.push(format!("{}.", n.num_jugada));
+        }
+        else if con_numero {
+            simbolos.push(format!("{}...", n.num_jugada));
+        }
+        simbolos.push(n.san.clone());
+        for nag in n.nags.iter() {
+            simbolos.push(format!("${}", nag));
+        }
+        let comentario = n.comentario_pgn();
+        if !comentario.is_empty() {
+            simbolos.push(format!("{{{}}}", comentario));
+        }
+    }
+
+    // escribe las continuaciones del nodo: la principal, sus alternativas
+    // entre parentesis y despues el resto de la linea principal
+    fn escribe_linea(&self, nodo: usize, con_numero: bool, simbolos: &mut Vec<String>) {
+        let mut n = nodo;
+        let mut con_numero = con_numero;
+        while let Some(&principal) = self.nodos[n].hijos.first() {
+            self.escribe_jugada(principal, con_numero, simbolos);
+            con_numero = !self.nodos[principal].comentario_pgn().is_empty();
+
+            for &variante in self.nodos[n].hijos.iter().skip(1) {
+                simbolos.push("(".to_string());
+                self.escribe_jugada(variante, true, simbolos);
+                let despues = !self.nodos[variante].comentario_pgn().is_empty();
+                self.escribe_linea(variante, despues, simbolos);
+                simbolos.push(")".to_string());
+                con_numero = true;
+            }
+            n = principal;
+        }
+    }
+}
+
+
+// el simbolo de los NAG mas habituales
+pub fn nag_texto(nag: u8) -> String {
+    match nag {
+        1 => "!".to_string(),
+        2 => "?".to_string(),
+        3 => "!!".to_string(),
+        4 => "??".to_string(),
+        5 => "!?".to_string(),
+        6 => "?!".to_string(),
+        _ => format!("${}", nag),
+    }
+}
+
+
+// el NAG que corresponde a un sufijo de anotacion (!, ?, !!, ...)
+pub fn nag_de_sufijo(sufijo: &str) -> Option<u8> {
+    match sufijo {
+        "!"  => Some(1),
+        "?"  => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
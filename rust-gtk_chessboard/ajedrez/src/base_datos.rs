@@ -0,0 +1,197 @@
+/***************************************************************
+*
+* Bases de datos de partidas PGN
+*
+* Un fichero PGN puede contener miles de partidas. Al abrirlo solo
+* se indexa: para cada partida se guarda su posicion en el fichero
+* (en bytes) y sus cabeceras. Las jugadas se leen cuando se pide
+* una partida concreta.
+*
+***************************************************************/
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::partida::{Partida, RESULTADOS, lee_pgn, lee_cabecera};
+
+
+// ========= una partida dentro del indice ==================
+#[derive(Clone)]
+pub struct EntradaIndice {
+    pub inicio: u64,                        // primer byte de la partida en el fichero
+    pub fin: u64,                           // primer byte despues de la partida
+    pub cabeceras: Vec<(String, String)>,   // las cabeceras en el orden del fichero
+}
+
+impl EntradaIndice {
+    // retorna el valor de la cabecera o "" si la partida no la tiene
+    pub fn cabecera(&self, nombre: &str) -> &str {
+        for (clave, valor) in self.cabeceras.iter() {
+            if clave == nombre {
+                return valor.as_str();
+            }
+        }
+        ""
+    }
+}
+
+
+// ========= el fichero PGN indexado ==================
+#[derive(Clone)]
+pub struct BaseDatos {
+    pub ruta: PathBuf,
+    pub partidas: Vec<EntradaIndice>,
+}
+
+impl BaseDatos {
+    /*
+     *  Abre un fichero PGN e indexa todas sus partidas
+     *
+     *  @param ruta             el fichero PGN
+     *  @return Result          la base de datos o el motivo del error
+    */
+    pub fn abre(ruta: &Path) -> Result<BaseDatos, String> {
+        let fichero = match File::open(ruta) {
+            Ok(fichero) => fichero,
+            Err(err) => return Err(format!("No se puede abrir {}: {}", ruta.display(), err)),
+        };
+        let partidas = match indexa(BufReader::new(fichero)) {
+            Ok(partidas) => partidas,
+            Err(err) => return Err(format!("Error leyendo {}: {}", ruta.display(), err)),
+        };
+
+        Ok(BaseDatos {
+            ruta: ruta.to_path_buf(),
+            partidas,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.partidas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partidas.is_empty()
+    }
+
+    // retorna el texto PGN de la partida numero n (empezando en 0)
+    pub fn texto_partida(&self, n: usize) -> Result<String, String> {
+        if n >= self.partidas.len() {
+            return Err(format!("La base de datos no tiene la partida {}", n + 1));
+        }
+        let entrada = &self.partidas[n];
+
+        let mut bytes = vec![0u8; (entrada.fin - entrada.inicio) as usize];
+        let leido = File::open(&self.ruta).and_then(|mut fichero| {
+            fichero.seek(SeekFrom::Start(entrada.inicio))?;
+            fichero.read_exact(&mut bytes)
+        });
+        if let Err(err) = leido {
+            return Err(format!("Error leyendo {}: {}", self.ruta.display(), err));
+        }
+        // muchos ficheros PGN estan en latin-1, por lo que no exigimos utf-8
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    // lee y procesa la partida numero n (empezando en 0)
+    pub fn carga_partida(&self, n: usize) -> Result<Partida, String> {
+        let texto = self.texto_partida(n)?;
+        lee_pgn(&texto)
+    }
+}
+
+
+// recorre el fichero linea a linea anotando donde empieza y termina cada partida
+fn indexa<R: BufRead>(mut lector: R) -> std::io::Result<Vec<EntradaIndice>> {
+    let mut partidas: Vec<EntradaIndice> = Vec::new();
+    let mut actual: Option<EntradaIndice> = None;
+    let mut en_jugadas = false;         // estamos en el texto de las jugadas
+    let mut en_comentario = false;      // dentro de un comentario { ... } de varias lineas
+    let mut terminada = false;          // se ha leido la marca de resultado de la partida
+    let mut posicion: u64 = 0;
+    let mut linea: Vec<u8> = Vec::new();
+
+    loop {
+        linea.clear();
+        let leidos = lector.read_until(b'\n', &mut linea)?;
+        if leidos == 0 {
+            break;
+        }
+        let inicio_linea = posicion;
+        posicion += leidos as u64;
+
+        let texto = String::from_utf8_lossy(&linea);
+        let texto = texto.trim();
+        if texto.is_empty() || texto.starts_with('%') {
+            continue;
+        }
+
+        if !en_comentario && texto.starts_with('[') {
+            // una cabecera despues de las jugadas es el comienzo de la partida siguiente
+            if en_jugadas || actual.is_none() {
+                if let Some(mut partida) = actual.take() {
+                    partida.fin = inicio_linea;
+                    partidas.push(partida);
+                }
+                actual = Some(EntradaIndice {
+                    inicio: inicio_linea,
+                    fin: inicio_linea,
+                    cabeceras: Vec::new(),
+                });
+                en_jugadas = false;
+                terminada = false;
+            }
+            if let Some(cabecera) = lee_cabecera(texto) {
+                if let Some(ref mut partida) = actual {
+                    partida.cabeceras.push(cabecera);
+                }
+            }
+            continue;
+        }
+
+        // texto de jugadas; puede haber partidas sin cabeceras
+        if terminada || actual.is_none() {
+            if let Some(mut partida) = actual.take() {
+                partida.fin = inicio_linea;
+                partidas.push(partida);
+            }
+            actual = Some(EntradaIndice {
+                inicio: inicio_linea,
+                fin: inicio_linea,
+                cabeceras: Vec::new(),
+            });
+            terminada = false;
+        }
+        en_jugadas = true;
+
+        // seguimos la pista de los comentarios que ocupan varias lineas,
+        // ya que pueden contener lineas que empiezan por '['
+        for c in texto.chars() {
+            if en_comentario {
+                if c == '}' {
+                    en_comentario = false;
+                }
+            }
+            else if c == '{' {
+                en_comentario = true;
+            }
+            else if c == ';' {
+                break;
+            }
+        }
+        // la marca de resultado cierra la partida aunque no vengan cabeceras detras
+        if !en_comentario {
+            if let Some(ultimo) = texto.split_whitespace().last() {
+                terminada = RESULTADOS.contains(&ultimo);
+            }
+        }
+    }
+
+    if let Some(mut partida) = actual.take() {
+        partida.fin = posicion;
+        partidas.push(partida);
+    }
+
+    Ok(partidas)
+}
@@ -18,6 +18,10 @@ pub const WHITE_TO_MOVE: i16 = 1;
 pub const BLACK_TO_MOVE: i16 = -1;
 
 
+// La posicion inicial de una partida normal
+pub const FEN_INICIO: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+
 //Constantes para las piezas
 pub const W_KING: i16 = 1;
 pub const W_QUEEN: i16 = 2;
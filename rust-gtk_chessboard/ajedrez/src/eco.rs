@@ -0,0 +1,129 @@
+/***************************************************************
+*
+* Clasificacion ECO de las aperturas
+*
+* La tabla (eco.txt) va dentro del programa. Cada apertura se
+* reproduce desde la posicion inicial y se guarda por la clave
+* Polyglot de la posicion a la que llega, de modo que una partida
+* que llega a ella con otro orden de jugadas tambien se reconoce.
+* La clasificacion de una linea es la de la ultima de sus
+* posiciones que esta en la tabla.
+*
+***************************************************************/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use super::{Tablero, reset, mueve_san, mueve_adelante};
+use super::arbol::ArbolPartida;
+use super::polyglot;
+
+
+const TABLA: &str = include_str!("eco.txt");
+
+
+// ========= una apertura de la tabla ==================
+#[derive(Clone, Debug, PartialEq)]
+pub struct Apertura {
+    pub eco: String,                // "D35"
+    pub nombre: String,             // "Queen's Gambit Declined: Exchange Variation"
+    pub jugadas: String,            // las jugadas SAN desde la posicion inicial
+}
+
+impl fmt::Display for Apertura {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.eco, self.nombre)
+    }
+}
+
+
+// la tabla leida y el indice por clave de posicion
+struct Clasificacion {
+    aperturas: Vec<Apertura>,
+    posiciones: HashMap<u64, usize>,
+    max_medias: usize,              // la linea mas larga de la tabla, en medias jugadas
+}
+
+static CLASIFICACION: OnceLock<Clasificacion> = OnceLock::new();
+
+
+// la tabla se lee la primera vez que se usa
+fn clasificacion() -> &'static Clasificacion {
+    CLASIFICACION.get_or_init(|| {
+        let mut aperturas = Vec::new();
+        let mut posiciones = HashMap::new();
+        let mut max_medias = 0;
+        for linea in TABLA.lines().map(str::trim) {
+            if linea.is_empty() || linea.starts_with('#') {
+                continue;
+            }
+            let apertura = match lee_linea(linea) {
+                Some(apertura) => apertura,
+                None => continue,
+            };
+            let mut board = reset();
+            let mut medias = 0;
+            let mut legal = true;
+            for san in apertura.jugadas.split_whitespace().filter(|s| !s.ends_with('.')) {
+                if mueve_san(&mut board, san).is_err() {
+                    legal = false;
+                    break;
+                }
+                medias += 1;
+            }
+            if !legal {
+                continue;
+            }
+            // si dos lineas llegan a la misma posicion vale la primera
+            posiciones.entry(polyglot::clave(&board)).or_insert(aperturas.len());
+            max_medias = max_medias.max(medias);
+            aperturas.push(apertura);
+        }
+        Clasificacion { aperturas, posiciones, max_medias }
+    })
+}
+
+
+// una linea de la tabla: D35 "Queen's Gambit Declined: Exchange Variation" 1. d4 d5 ...
+fn lee_linea(linea: &str) -> Option<Apertura> {
+    let (eco, resto) = linea.split_at(linea.find(' ')?);
+    let resto = resto.trim_start().strip_prefix('"')?;
+    let fin = resto.find('"')?;
+    Some(Apertura {
+        eco: eco.to_string(),
+        nombre: resto[..fin].to_string(),
+        jugadas: resto[fin + 1..].trim().to_string(),
+    })
+}
+
+
+// todas las aperturas de la tabla, en su orden
+pub fn aperturas() -> &'static [Apertura] {
+    &clasificacion().aperturas
+}
+
+
+// la apertura de la posicion del tablero, solo si esta en la tabla
+pub fn clasifica(board: &Tablero) -> Option<&'static Apertura> {
+    let clasificacion = clasificacion();
+    clasificacion.posiciones.get(&polyglot::clave(board))
+                .map(|&i| &clasificacion.aperturas[i])
+}
+
+
+// La apertura de la linea que lleva al nodo del arbol: la de la ultima
+// posicion de la linea que esta en la tabla
+pub fn clasifica_linea(arbol: &ArbolPartida, nodo: usize) -> Option<&'static Apertura> {
+    let mut board = arbol.tablero_inicial();
+    let mut apertura = clasifica(&board);
+    for n in arbol.camino(nodo).into_iter().take(clasificacion().max_medias) {
+        if mueve_adelante(&mut board, arbol.nodos[n].movim).is_err() {
+            break;
+        }
+        if let Some(encontrada) = clasifica(&board) {
+            apertura = Some(encontrada);
+        }
+    }
+    apertura
+}
@@ -0,0 +1,259 @@
+/***************************************************************
+*
+* Posiciones en formato EPD
+*
+* Una linea EPD son los cuatro primeros campos de la FEN (piezas,
+* turno, enroques y casilla al paso) seguidos de operaciones. Cada
+* operacion es un codigo y sus operandos y termina en ';'. Los
+* operandos con espacios van entre comillas:
+*
+*   2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+*
+* Las baterias de pruebas (WAC, STS...) son ficheros con una
+* posicion por linea. Los codigos mas usados son bm (mejor jugada),
+* am (jugada a evitar), id (nombre de la posicion), c0..c9
+* (comentarios) y hmvc/fmvn (los dos ultimos campos de la FEN).
+*
+***************************************************************/
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::{Tablero, get_fen, set_fen, valida_fen};
+use super::errores::{ErrorAjedrez, CampoFen, fen_invalida};
+use super::tipos::Move;
+
+
+// ========= una operacion ==================
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operacion {
+    pub codigo: String,             // "bm", "id", "c0"...
+    pub operandos: Vec<String>,     // sin las comillas
+}
+
+// los codigos cuyos operandos son textos y se escriben siempre entre comillas
+fn es_texto(codigo: &str) -> bool {
+    let mut letras = codigo.chars();
+    match (letras.next(), letras.next(), letras.next()) {
+        (Some('i'), Some('d'), None) => true,
+        (Some('c'), Some(n), None) | (Some('v'), Some(n), None) => n.is_ascii_digit(),
+        _ => false,
+    }
+}
+
+impl fmt::Display for Operacion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.codigo)?;
+        for operando in &self.operandos {
+            let comillas = es_texto(&self.codigo) || operando.is_empty()
+                        || operando.contains(|c: char| c.is_whitespace() || c == ';');
+            if comillas {
+                write!(f, " \"{}\"", operando)?;
+            }
+            else {
+                write!(f, " {}", operando)?;
+            }
+        }
+        write!(f, ";")
+    }
+}
+
+
+// ========= una linea EPD ==================
+#[derive(Clone, Debug, PartialEq)]
+pub struct Epd {
+    pub posicion: String,           // los cuatro primeros campos de la FEN
+    pub operaciones: Vec<Operacion>,
+}
+
+impl Epd {
+    pub fn lee(linea: &str) -> Result<Epd, ErrorAjedrez> {
+        let mut resto = linea.trim();
+        let mut campos = Vec::with_capacity(4);
+        while campos.len() < 4 {
+            let fin = resto.find(char::is_whitespace).unwrap_or(resto.len());
+            if fin == 0 {
+                break;
+            }
+            campos.push(&resto[..fin]);
+            resto = resto[fin..].trim_start();
+        }
+        if campos.len() < 4 {
+            return Err(fen_invalida(CampoFen::Completa,
+                        format!("tiene {} campos y una EPD empieza por 4", campos.len())));
+        }
+        let epd = Epd {
+            posicion: campos.join(" "),
+            operaciones: lee_operaciones(resto)?,
+        };
+        // la posicion se valida con los contadores de la propia EPD
+        valida_fen(&epd.fen())?;
+        Ok(epd)
+    }
+
+    // la EPD de la posicion del tablero, sin operaciones
+    pub fn desde_tablero(board: &mut Tablero) -> Epd {
+        let fen = get_fen(board);
+        Epd {
+            posicion: fen.split_whitespace().take(4).collect::<Vec<&str>>().join(" "),
+            operaciones: Vec::new(),
+        }
+    }
+
+    // la FEN completa. Las medias jugadas y el numero de jugada salen de
+    // hmvc y fmvn; si no estan son 0 y 1
+    pub fn fen(&self) -> String {
+        format!("{} {} {}", self.posicion,
+                self.operando("hmvc").unwrap_or("0"),
+                self.operando("fmvn").unwrap_or("1"))
+    }
+
+    pub fn tablero(&self) -> Result<Tablero, ErrorAjedrez> {
+        let mut board = Tablero::init();
+        set_fen(&self.fen(), &mut board)?;
+        Ok(board)
+    }
+
+    pub fn operacion(&self, codigo: &str) -> Option<&Operacion> {
+        self.operaciones.iter().find(|op| op.codigo == codigo)
+    }
+
+    // el primer operando de una operacion
+    pub fn operando(&self, codigo: &str) -> Option<&str> {
+        self.operacion(codigo)
+            .and_then(|op| op.operandos.first())
+            .map(|operando| operando.as_str())
+    }
+
+    // pone una operacion, sustituyendo la que ya tenga el mismo codigo
+    pub fn pon(&mut self, codigo: &str, operandos: Vec<String>) {
+        let operacion = Operacion { codigo: codigo.to_string(), operandos };
+        match self.operaciones.iter_mut().find(|op| op.codigo == codigo) {
+            Some(op) => *op = operacion,
+            None => self.operaciones.push(operacion),
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operando("id")
+    }
+
+    // las mejores jugadas (bm)
+    pub fn mejores(&self) -> Result<Vec<Move>, ErrorAjedrez> {
+        self.jugadas("bm")
+    }
+
+    // las jugadas a evitar (am)
+    pub fn evitar(&self) -> Result<Vec<Move>, ErrorAjedrez> {
+        self.jugadas("am")
+    }
+
+    // los operandos de la operacion leidos como jugadas SAN de la posicion
+    fn jugadas(&self, codigo: &str) -> Result<Vec<Move>, ErrorAjedrez> {
+        let operacion = match self.operacion(codigo) {
+            Some(operacion) => operacion,
+            None => return Ok(Vec::new()),
+        };
+        let board = self.tablero()?;
+        operacion.operandos.iter()
+                .map(|san| Move::from_san(&board, san))
+                .collect()
+    }
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.posicion)?;
+        for operacion in &self.operaciones {
+            write!(f, " {}", operacion)?;
+        }
+        Ok(())
+    }
+}
+
+
+// las operaciones que siguen a la posicion. A la ultima se le perdona
+// que le falte el ';', que se suele perder al copiar y pegar
+fn lee_operaciones(texto: &str) -> Result<Vec<Operacion>, ErrorAjedrez> {
+    let mut operaciones = Vec::new();
+    let mut letras = texto.chars().peekable();
+    loop {
+        while let Some(&c) = letras.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            letras.next();
+        }
+        if letras.peek().is_none() {
+            return Ok(operaciones);
+        }
+
+        let mut codigo = String::new();
+        while let Some(&c) = letras.peek() {
+            if c.is_whitespace() || c == ';' {
+                break;
+            }
+            codigo.push(c);
+            letras.next();
+        }
+        let valido = codigo.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && codigo.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valido {
+            return Err(ErrorAjedrez::EpdMalFormada(format!("'{}' no es un codigo de operacion", codigo)));
+        }
+
+        let mut operandos = Vec::new();
+        loop {
+            match letras.next() {
+                None | Some(';') => break,
+                Some(c) if c.is_whitespace() => (),
+                Some('"') => {
+                    let mut operando = String::new();
+                    loop {
+                        match letras.next() {
+                            Some('"') => break,
+                            Some(c) => operando.push(c),
+                            None => return Err(ErrorAjedrez::EpdMalFormada(
+                                        format!("falta cerrar las comillas de la operacion {}", codigo))),
+                        }
+                    }
+                    operandos.push(operando);
+                },
+                Some(c) => {
+                    let mut operando = c.to_string();
+                    while let Some(&c) = letras.peek() {
+                        if c.is_whitespace() || c == ';' {
+                            break;
+                        }
+                        operando.push(c);
+                        letras.next();
+                    }
+                    operandos.push(operando);
+                },
+            }
+        }
+        operaciones.push(Operacion { codigo, operandos });
+    }
+}
+
+
+// Lee un fichero EPD entero, una posicion por linea. Las lineas vacias
+// se saltan y el error dice en que linea esta la que no se puede leer
+pub fn abre(ruta: &Path) -> Result<Vec<Epd>, String> {
+    let texto = match fs::read_to_string(ruta) {
+        Ok(texto) => texto,
+        Err(err) => return Err(format!("No se puede abrir {}: {}", ruta.display(), err)),
+    };
+    let mut posiciones = Vec::new();
+    for (n, linea) in texto.lines().enumerate() {
+        if linea.trim().is_empty() {
+            continue;
+        }
+        match Epd::lee(linea) {
+            Ok(epd) => posiciones.push(epd),
+            Err(err) => return Err(format!("Error leyendo {}, linea {}: {}", ruta.display(), n + 1, err)),
+        }
+    }
+    Ok(posiciones)
+}
@@ -0,0 +1,77 @@
+/***************************************************************
+*
+* Los errores de la libreria de ajedrez
+*
+* Las funciones que pueden fallar (hacer una jugada, leer una FEN...)
+* retornan un Result con un ErrorAjedrez que dice que ha ido mal
+*
+***************************************************************/
+
+use std::error::Error;
+use std::fmt;
+
+
+// ========= los campos de una FEN ==================
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CampoFen {
+    Completa,           // la FEN entera (p.ej. le faltan campos)
+    Piezas,
+    Turno,
+    Enroques,
+    AlPaso,
+    MediasJugadas,
+    Jugadas,
+}
+
+impl fmt::Display for CampoFen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nombre = match *self {
+            CampoFen::Completa => "la FEN",
+            CampoFen::Piezas => "la colocacion de las piezas",
+            CampoFen::Turno => "el turno",
+            CampoFen::Enroques => "los enroques",
+            CampoFen::AlPaso => "la casilla al paso",
+            CampoFen::MediasJugadas => "las medias jugadas",
+            CampoFen::Jugadas => "el numero de jugada",
+        };
+        write!(f, "{}", nombre)
+    }
+}
+
+
+// ========= el error ==================
+#[derive(Clone, PartialEq, Debug)]
+pub enum ErrorAjedrez {
+    JugadaIlegal(String),                               // la jugada no es legal en la posicion
+    SanAmbigua(String),                                 // la SAN vale para mas de una jugada
+    SanMalFormada(String),                              // la SAN no se puede leer
+    CasillaDesconocida(String),                         // no es el nombre de una casilla (a1..h8)
+    UciMalFormada(String),                              // la jugada UCI no se puede leer
+    FenInvalida { campo: CampoFen, motivo: String },
+    EpdMalFormada(String),                              // las operaciones de una EPD no se pueden leer
+    HistoricoVacio,                                     // no hay jugadas que deshacer
+}
+
+impl fmt::Display for ErrorAjedrez {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorAjedrez::JugadaIlegal(jugada) => write!(f, "La jugada {} no es legal", jugada),
+            ErrorAjedrez::SanAmbigua(san) => write!(f, "La jugada {} es ambigua", san),
+            ErrorAjedrez::SanMalFormada(san) => write!(f, "La jugada {} no esta bien escrita", san),
+            ErrorAjedrez::CasillaDesconocida(casilla) => write!(f, "La casilla {} no existe", casilla),
+            ErrorAjedrez::UciMalFormada(uci) => write!(f, "La jugada UCI {} no esta bien escrita", uci),
+            ErrorAjedrez::FenInvalida { campo, motivo } =>
+                write!(f, "FEN no valida, {}: {}", campo, motivo),
+            ErrorAjedrez::EpdMalFormada(motivo) => write!(f, "EPD no valida: {}", motivo),
+            ErrorAjedrez::HistoricoVacio => write!(f, "No hay jugadas que deshacer"),
+        }
+    }
+}
+
+impl Error for ErrorAjedrez {}
+
+
+// atajo para los errores de FEN
+pub fn fen_invalida(campo: CampoFen, motivo: String) -> ErrorAjedrez {
+    ErrorAjedrez::FenInvalida { campo, motivo }
+}
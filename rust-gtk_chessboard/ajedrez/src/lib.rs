@@ -5,6 +5,46 @@
 *
 ***************************************************************/
 
+//! La logica del ajedrez del tablero, sin ninguna dependencia de GTK,
+//! para poder usarla desde otros programas (herramientas de linea de
+//! comandos, servidores...).
+//!
+//! - [`Tablero`] es la posicion (tablero 0x88) con el historico de jugadas.
+//!   Se crea con [`reset`] o con [`Tablero::init`] + [`set_fen`].
+//! - Las jugadas se hacen con [`mueve_san`], [`mueve_algebra`] o [`mueve`]
+//!   (con un [`Move`] tipado) y se deshacen con [`mueve_atras`]. Todas
+//!   retornan un [`MoveResult`] o un [`ErrorAjedrez`] que dice por que
+//!   no se ha podido hacer.
+//! - [`jugadas_legales`], [`rey_en_jaque`], [`dan_jaque`] y
+//!   [`resultado_partida`] dan el estado de la posicion y [`clave_zobrist`]
+//!   la identifica con un `u64`.
+//! - [`genera_jugadas`] rellena una [`ListaJugadas`] de tamaño fijo sin pedir
+//!   memoria; es lo que usa [`perft`].
+//! - [`Square`], [`Piece`], [`Color`], [`MoveKind`] y [`Move`] son los tipos
+//!   publicos; [`defs`] tiene los codigos internos de piezas y jugadas.
+//! - [`partida`] lee y escribe PGN, [`arbol`] guarda una partida con sus
+//!   variantes, comentarios y flechas, [`base_datos`] indexa ficheros PGN
+//!   con muchas partidas y [`motor_uci`] habla con motores UCI.
+//! - [`polyglot`] lee libros de aperturas `.bin` y da las jugadas de
+//!   libro de una posicion con su peso.
+//! - [`eco`] reconoce la apertura (codigo ECO y nombre) de una posicion
+//!   o de una linea de la partida.
+//! - [`epd`] lee y escribe posiciones EPD con sus operaciones (`bm`, `am`,
+//!   `id`...), como las de las baterias de pruebas WAC o STS.
+//!
+//! ```
+//! let mut tablero = ajedrez::reset();
+//! let jugada = ajedrez::mueve_san(&mut tablero, "e4").unwrap();
+//! assert_eq!(jugada.uci(), "e2e4");
+//! assert_eq!(ajedrez::get_fen(&mut tablero),
+//!            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+//!
+//! match ajedrez::mueve_san(&mut tablero, "Nf4") {
+//!     Err(ajedrez::ErrorAjedrez::JugadaIlegal(san)) => assert_eq!(san, "Nf4"),
+//!     _ => unreachable!(),
+//! }
+//! ```
+
 
 extern crate regex;
 
@@ -14,6 +54,20 @@ use std::collections::HashMap;
 
 
 pub mod defs;
+pub mod arbol;
+pub mod partida;
+pub mod base_datos;
+pub mod motor_uci;
+pub mod errores;
+pub mod tipos;
+pub mod polyglot;
+pub mod eco;
+pub mod epd;
+mod zobrist;
+
+pub use self::errores::{ErrorAjedrez, CampoFen};
+pub use self::tipos::{Color, PieceKind, Piece, Square, MoveKind, Move, MoveResult};
+use self::errores::fen_invalida;
 
 
 
@@ -53,13 +107,66 @@ impl Movim {
 }
 
 
+// ========= la lista de jugadas de una posicion ==================
+// Un array de tamaño fijo en lugar de un Vec, para que generar las jugadas
+// no pida memoria. Ninguna posicion legal tiene mas de 218 jugadas
+pub const MAX_JUGADAS: usize = 256;
+
+#[derive(Copy, Clone)]
+pub struct ListaJugadas {
+    jugadas: [Movim; MAX_JUGADAS],
+    num: usize
+}
+
+impl ListaJugadas {
+    pub fn init() -> ListaJugadas {
+        ListaJugadas {
+            jugadas: [Movim::init(0, 0, 0, 0, 0, [0, 0, 0, 0]); MAX_JUGADAS],
+            num: 0
+        }
+    }
+    
+    pub fn push(&mut self, movim: Movim) {
+        self.jugadas[self.num] = movim;
+        self.num += 1;
+    }
+    
+    pub fn clear(&mut self) {
+        self.num = 0;
+    }
+    
+    pub fn len(&self) -> usize {
+        self.num
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.num == 0
+    }
+    
+    pub fn as_slice(&self) -> &[Movim] {
+        &self.jugadas[..self.num]
+    }
+    
+    pub fn iter(&self) -> std::slice::Iter<'_, Movim> {
+        self.as_slice().iter()
+    }
+}
+
+
 // ==========}===== la estructura del historico ==================
 #[derive(Clone)]
 pub struct Historia{
-    posicion: String,
+    clave: u64,         // la clave zobrist de la posicion de antes de la jugada
     mov : Movim
 }
 
+impl Historia {
+    // la jugada que se hizo desde esta posicion
+    pub fn movim(&self) -> Movim {
+        self.mov
+    }
+}
+
 
 // ======================= la estructura del tablero =============
 #[derive(Clone)]
@@ -71,6 +178,7 @@ pub struct Tablero {
     black_castle: i16,                  // enroque negro - idem
     moves_fifty: i16,                    // regla 50 jugadas (penultimo campo FEN)
     moves_full: i16,                      // num. jugadas de la partida (ultimo campo FEN)
+    zobrist: u64,                         // la clave zobrist de la posicion
     pub history: Vec<Historia>                 // histórico de movimientos
 }
 
@@ -84,11 +192,13 @@ impl Tablero {
             black_castle: defs::CASTLE_NONE,
             moves_fifty: 0,
             moves_full: 0,
+            zobrist: 0,
             history: Vec::new()
         }
     }
     
     fn modif_board(&mut self, indice: usize, pieza: i16){
+        self.zobrist ^= zobrist::pieza(self.board_array[indice], indice) ^ zobrist::pieza(pieza, indice);
         self.board_array[indice] = pieza;
     }
     
@@ -123,8 +233,8 @@ impl Tablero {
     fn quita_historico(&mut self) -> Historia{
         let quitado = self.history.pop();
         match quitado {
-            Some(quitado) => return quitado,
-            None => return Historia {posicion: "".to_string(),
+            Some(quitado) => quitado,
+            None => Historia {clave: 0,
                             mov: Movim::init(0,0,0,0,0,[0,0,0,0]),
                             }
         }
@@ -141,92 +251,23 @@ impl Tablero {
 /** Metodos de manipulación de la FEN    **/
 /******************************************/
 
-pub fn setup_inicio(mut board: &mut Tablero) -> bool {
-    let _resultado = set_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &mut board);
-    _resultado
+pub fn setup_inicio(board: &mut Tablero) -> Result<(), ErrorAjedrez> {
+    set_fen(defs::FEN_INICIO, board)
 }
 
 
-pub fn set_fen(fen: &str, board: &mut Tablero) -> bool{
-    /*
-    let fen_valida: bool;   // = true;
-    fen_valida = valida_fen(fen);
-    if !fen_valida{
-        panic!("La cadena FEN no es valida {}", fen_valida);
-    }
-    */
-    // dividimos la fen en seis trozos
-    let mut var_fen = String::from(fen);
-    
-    var_fen = var_fen.trim().to_string();
-    let mut iter = var_fen.split_whitespace();
-    /*
-    iter.next(), iter.next(), iter.next(), iter.next());
-    let fen_dividida = iter.collect::<Vec<&str>>();
-    */
-    let mut partes = iter.next();
-    let parte1: &str;   // = "";
-    
-    // TODO quitar estas comprobaciones ya que se valida la FEN con una funcion nueva
-    // estas comprobaciones parece que son redundantes fn valida_fen
-    match partes {
-        // si lo ha encontrado
-        Some(x) => parte1 = x,
-        // no lo ha encontrado
-        None    => parte1 = "None",
-    }
-    
-    partes = iter.next();
-    let parte2: &str;   // = "";
-    match partes {
-        // si lo ha encontrado
-        Some(x) => parte2 = x,
-        // no lo ha encontrado
-        None    => parte2 = "None",
-    }
-    
-    partes = iter.next();
-    let parte3: &str;   // = "";
-    match partes {
-        // si lo ha encontrado
-        Some(x) => parte3 = x,
-        // no lo ha encontrado
-        None    => parte3 = "None",
-    }
+pub fn set_fen(fen: &str, board: &mut Tablero) -> Result<(), ErrorAjedrez> {
+    // primero se valida la FEN, para no dejar el tablero a medias
+    valida_fen(fen)?;
     
-    partes = iter.next();
-    let parte4: &str;   // = "";
-    match partes {
-        // si lo ha encontrado
-        Some(x) => parte4 = x,
-        // no lo ha encontrado
-        None    => parte4 = "None",
-    }
-    
-    partes = iter.next();
-    let parte5: &str;    // = "";
-    match partes {
-        // si lo ha encontrado
-        Some(x) => parte5 = x,
-        // no lo ha encontrado
-        None    => parte5 = "None",
-    }
-    
-    partes = iter.next();
-    let parte6: &str;   // = "";
-    match partes {
-        // si lo ha encontrado
-        Some(x) => parte6 = x,
-        // no lo ha encontrado
-        None    => parte6 = "None",
-    }
-    
-    
-    if parte1 == "None" || parte2 == "None" ||
-            parte3 == "None" || parte4 == "None" ||
-            parte5 == "None" || parte6 == "None" {
-        return false;
-    }
+    // dividimos la fen en seis trozos
+    let fen_dividida = fen.split_whitespace().collect::<Vec<&str>>();
+    let parte1 = fen_dividida[0];
+    let parte2 = fen_dividida[1];
+    let parte3 = fen_dividida[2];
+    let parte4 = fen_dividida[3];
+    let parte5 = fen_dividida[4];
+    let parte6 = fen_dividida[5];
     
     let mut i: usize = 0;
     
@@ -430,7 +471,9 @@ pub fn set_fen(fen: &str, board: &mut Tablero) -> bool{
     jug = parte6.parse().unwrap();
     board.modif_todas(jug);
     
-    true                                   
+    board.zobrist = zobrist::calcula(board);
+    
+    Ok(())
 }
 
 
@@ -616,117 +659,197 @@ pub fn get_fen(board: &mut Tablero) -> String{
     fen_string
 }
 
-fn valida_fen(fen: &str) -> bool{
+// Comprueba que la FEN es correcta y que la posicion es posible. Si no
+// lo es, el error dice que campo esta mal y por que
+pub fn valida_fen(fen: &str) -> Result<(), ErrorAjedrez> {
     // dividimos la fen en seis trozos
-    let var_fen = String::from(fen);
-    
-    let v_fen = var_fen.trim().to_string();
-    let iter = v_fen.split_whitespace();
-    let fen_dividida = iter.collect::<Vec<&str>>();
-    
-    //let mut numero: i16;
-    if fen_dividida.len() != 6{
-        return false;
+    let fen_dividida = fen.split_whitespace().collect::<Vec<&str>>();
+    if fen_dividida.len() != 6 {
+        return Err(fen_invalida(CampoFen::Completa,
+                    format!("tiene {} campos y deben ser 6", fen_dividida.len())));
     }
     
-    // ¿ campo numero de jugadas completas es un valor entero > 0? 
-    match texto_a_int(fen_dividida[5]){
-        Ok(n) => if n < 0{ 
-                    return false;
-                 },
-        Err(_err) => return false,
+    // ¿ campo numero de jugadas completas es un valor entero >= 0? 
+    match texto_a_int(fen_dividida[5]) {
+        Ok(n) if n >= 0 => (),
+        _ => return Err(fen_invalida(CampoFen::Jugadas,
+                    format!("'{}' no es un numero valido", fen_dividida[5]))),
     }
     
     // las medias jugadas
-    match texto_a_int(fen_dividida[4]){
-        Ok(n) => if n < 0{ 
-                    return false;
-                 },
-        Err(_err) => return false,
+    match texto_a_int(fen_dividida[4]) {
+        Ok(n) if n >= 0 => (),
+        _ => return Err(fen_invalida(CampoFen::MediasJugadas,
+                    format!("'{}' no es un numero valido", fen_dividida[4]))),
     }
     
     // 4to es una string válida? posibles son '-' o e3, e6, etc es la casilla al paso
-    let mut patron = Regex::new(r"^(-|[a-h]{1}[3|6]{1})$").unwrap();
-    if !patron.is_match(fen_dividida[3]){
-        return false;
+    let mut patron = Regex::new(r"^(-|[a-h][36])$").unwrap();
+    if !patron.is_match(fen_dividida[3]) {
+        return Err(fen_invalida(CampoFen::AlPaso,
+                    format!("'{}' no es '-' ni una casilla de la fila 3 o 6", fen_dividida[3])));
     }
     
     // los enroques
-    patron = Regex::new(r"(^-$)|(^[K|Q|k|q]{1,}$)").unwrap();
-    if !patron.is_match(fen_dividida[2]){
-        return false;
+    patron = Regex::new(r"^(-|[KQkq]{1,4})$").unwrap();
+    if !patron.is_match(fen_dividida[2]) {
+        return Err(fen_invalida(CampoFen::Enroques,
+                    format!("'{}' no es '-' ni una combinacion de K, Q, k y q", fen_dividida[2])));
     }
     
     // el turno
     patron = Regex::new(r"^(w|b)$").unwrap();
-    if !patron.is_match(fen_dividida[1]){
-        return false;
-    }
-    
-    // la ultima parte de las pueas
-    // primero el iterador
-    let mut split = fen_dividida[0].split("/");
-    // ahora construyo el array
-    let filas = split.collect::<Vec<&str>>();
-    if filas.len() < 8 {
-        return false;
-    }
-    let mut sum_fields:i16;             // = 0;
-    let mut previous_was_number: bool;  // = false;
-    
-    for i in 0..filas.len(){
-        sum_fields = 0;
-        previous_was_number = false;
+    if !patron.is_match(fen_dividida[1]) {
+        return Err(fen_invalida(CampoFen::Turno,
+                    format!("'{}' tiene que ser w o b", fen_dividida[1])));
+    }
+    
+    // la ultima parte de las piezas
+    let filas = fen_dividida[0].split('/').collect::<Vec<&str>>();
+    if filas.len() != 8 {
+        return Err(fen_invalida(CampoFen::Piezas,
+                    format!("tiene {} filas y deben ser 8", filas.len())));
+    }
+    patron = Regex::new(r"^[prnbqkPRNBQK]$").unwrap();
+    let mut reyes_blancos = 0;
+    let mut reyes_negros = 0;
+    
+    for (i, fila) in filas.iter().enumerate() {
+        // las filas de la FEN van de la 8 a la 1
+        let num_fila = 8 - i;
+        let mut sum_fields: i16 = 0;
+        let mut previous_was_number = false;
         
-        let s = filas[i];
-        let char_vec: Vec<char> = s.chars().collect();
-        for k in 0..char_vec.len(){
-            if char_vec[k].is_digit(10){        // en base 10
-                // cada fila es valida
-                if previous_was_number{
-                    return false
-                }
-                //sum_fields += int(char_vec[k], 10);
-                let cad = char_vec[k].to_string();
-                match texto_a_int(&cad){
-                    Ok(n) => sum_fields += n,
-                    Err(_err) => return false,
+        for caracter in fila.chars() {
+            if caracter.is_ascii_digit() {
+                if previous_was_number {
+                    return Err(fen_invalida(CampoFen::Piezas,
+                                format!("la fila {} tiene dos numeros seguidos", num_fila)));
                 }
+                sum_fields += caracter.to_digit(10).unwrap() as i16;
                 previous_was_number = true;
             }
-            else{
+            else {
                 // comprobar los simbolos de las piezas
-                patron = Regex::new(r"^[prnbqkPRNBQK]$").unwrap();
-                if !patron.is_match(&char_vec[k].to_string()){
-                    return false;
+                if !patron.is_match(&caracter.to_string()) {
+                    return Err(fen_invalida(CampoFen::Piezas,
+                                format!("'{}' no es una pieza", caracter)));
                 }
+                if caracter == 'K' { reyes_blancos += 1; }
+                if caracter == 'k' { reyes_negros += 1; }
                 sum_fields += 1;
                 previous_was_number = false;
             }
         }
         // compueba la suma de piezas + casillas en blanco debe ser 8    
-        if sum_fields != 8{
-            return false;
+        if sum_fields != 8 {
+            return Err(fen_invalida(CampoFen::Piezas,
+                        format!("la fila {} tiene {} casillas y deben ser 8", num_fila, sum_fields)));
         }
     }
     
-    // por ultomo la casilla al paso:
+    if reyes_blancos != 1 || reyes_negros != 1 {
+        return Err(fen_invalida(CampoFen::Piezas,
+                    "tiene que haber un rey de cada color".to_string()));
+    }
+    
+    // por ultimo la casilla al paso:
     // si es el turno blanco la de casilla al paso ser 6 y 3 en caso de que sea el negro
+    if (fen_dividida[3].ends_with('3') && fen_dividida[1] == "w") ||
+            (fen_dividida[3].ends_with('6') && fen_dividida[1] == "b") {
+        return Err(fen_invalida(CampoFen::AlPaso,
+                    format!("{} no puede ser la casilla al paso si juegan las {}", fen_dividida[3],
+                            if fen_dividida[1] == "w" { "blancas" } else { "negras" })));
+    }
     
-    if fen_dividida[3].len() > 1{
-        split = fen_dividida[3].trim().split("");   
-        //añade dos elem.vacios al principio y final. por tanto...
-        let paso = split.collect::<Vec<&str>>();
-        if (paso[2] == "3" && fen_dividida[1] == "w") ||
-                (paso[2] == "6" && fen_dividida[1] == "b"){
-            return false;
+    // la FEN se puede leer; falta ver que la posicion sea posible
+    valida_posicion(&casillas_fen(fen_dividida[0]), fen_dividida[1], fen_dividida[2], fen_dividida[3])
+}
+
+
+// las piezas de la primera parte de una FEN ya validada, en un tablero 0x88
+fn casillas_fen(piezas: &str) -> [i16; 128] {
+    let mut casillas = [defs::EMPTY_SQUARE; 128];
+    let mut casilla: usize = 112;
+    for caracter in piezas.chars() {
+        if caracter == '/' {
+            casilla -= 24;
+        }
+        else if let Some(n) = caracter.to_digit(10) {
+            casilla += n as usize;
+        }
+        else {
+            casillas[casilla] = Piece::from_fen_char(caracter).map_or(defs::EMPTY_SQUARE, Piece::code);
+            casilla += 1;
+        }
+    }
+    casillas
+}
+
+
+fn nombre_casilla(casilla: i16) -> String {
+    Square::from_088(casilla as usize).map_or("?".to_string(), Square::algebraic)
+}
+
+
+// Lo que no se ve en la sintaxis de la FEN: peones en la primera o la ultima fila,
+// demasiadas piezas, el rey del bando que no juega en jaque, enroques sin el rey
+// o la torre en su casilla y casillas al paso sin el peon que acaba de avanzar
+fn valida_posicion(casillas: &[i16; 128], turno: &str, enroques: &str, al_paso: &str) -> Result<(), ErrorAjedrez> {
+    for (bando, nombre) in [(defs::WHITE_TO_MOVE, "blancas"), (defs::BLACK_TO_MOVE, "negras")].iter() {
+        let propias = (0..128).filter(|&c| c & 0x88 == 0 && casillas[c] * bando > 0).count();
+        let peones = (0..128).filter(|&c| c & 0x88 == 0 && casillas[c] == defs::W_PAWN * bando).count();
+        if peones > 8 {
+            return Err(fen_invalida(CampoFen::Piezas,
+                        format!("las {} tienen {} peones y como mucho pueden ser 8", nombre, peones)));
+        }
+        if propias > 16 {
+            return Err(fen_invalida(CampoFen::Piezas,
+                        format!("las {} tienen {} piezas y como mucho pueden ser 16", nombre, propias)));
         }
     }
     
+    for casilla in (0..8).chain(112..120) {
+        if casillas[casilla].abs() == defs::W_PAWN {
+            return Err(fen_invalida(CampoFen::Piezas,
+                        format!("hay un peon en {}, en la primera o la ultima fila", nombre_casilla(casilla as i16))));
+        }
+    }
+    
+    let bando = if turno == "w" { defs::WHITE_TO_MOVE } else { defs::BLACK_TO_MOVE };
+    let rey_contrario = (0..128).find(|&c| c & 0x88 == 0 && casillas[c as usize] == defs::B_KING * bando);
+    if let Some(rey) = rey_contrario {
+        if es_atacada(casillas, rey, bando) {
+            return Err(fen_invalida(CampoFen::Turno,
+                        format!("el rey {} esta en jaque y no le toca mover",
+                                if bando == defs::WHITE_TO_MOVE { "negro" } else { "blanco" })));
+        }
+    }
     
-    true
+    // cada derecho de enroque necesita el rey y la torre en su casilla inicial
+    for (letra, rey, torre, pieza) in [('K', 0x04, 0x07, defs::W_KING), ('Q', 0x04, 0x00, defs::W_KING),
+                                       ('k', 0x74, 0x77, defs::B_KING), ('q', 0x74, 0x70, defs::B_KING)].iter() {
+        if enroques.contains(*letra) && (casillas[*rey] != *pieza || casillas[*torre] != defs::W_ROOK * pieza) {
+            return Err(fen_invalida(CampoFen::Enroques,
+                        format!("para {} el rey tiene que estar en {} y la torre en {}", letra,
+                                nombre_casilla(*rey as i16), nombre_casilla(*torre as i16))));
+        }
+    }
     
+    // al paso: el peon contrario delante de la casilla, y ella y la de detras vacias
+    if al_paso != "-" {
+        let casilla = Square::from_algebraic(al_paso)?.to_088() as i16;
+        let peon = casilla - 16 * bando;
+        let origen = casilla + 16 * bando;
+        if casillas[peon as usize] != defs::B_PAWN * bando ||
+                    casillas[casilla as usize] != defs::EMPTY_SQUARE ||
+                    casillas[origen as usize] != defs::EMPTY_SQUARE {
+            return Err(fen_invalida(CampoFen::AlPaso,
+                        format!("no hay un peon en {} que acabe de avanzar dos casillas", nombre_casilla(peon))));
+        }
+    }
     
+    Ok(())
 }
 
 fn texto_a_int(number_str: &str) -> Result<i16, ParseIntError> {
@@ -743,6 +866,11 @@ fn texto_a_int(number_str: &str) -> Result<i16, ParseIntError> {
 // las funcs. make_movim y unmake_movim
 
 fn make_movim(board: &mut Tablero, movim: Movim) -> Movim {
+    // quitamos de la clave zobrist el turno, los enroques y el al paso;
+    // al final, con las piezas ya movidas, se ponen los nuevos (el al
+    // paso depende de los peones). Las piezas las cambia modif_board
+    board.zobrist ^= zobrist::estado(board);
+    
     // anulamos cualquier al paso, luego se positivará si procede
     board.modif_paso(-1); 
     // cambiamos el lado a mover (positivo a negativo y viceversa)
@@ -772,8 +900,8 @@ fn make_movim(board: &mut Tablero, movim: Movim) -> Movim {
     let indice: i16;          // para trabajar con los indices. La resta de indices puede ser negativa
     
     if movim.move_type == defs::ORDINARY_MOVE{
-        board.board_array[movim.to_index] = movim.piece_moving;         // activa la casilla objetivo
-        board.board_array[movim.from_index] = defs::EMPTY_SQUARE;      // limpia la casilla de origen
+        board.modif_board(movim.to_index, movim.piece_moving);         // activa la casilla objetivo
+        board.modif_board(movim.from_index, defs::EMPTY_SQUARE);      // limpia la casilla de origen
         
         // Verifica si hay captura al paso 
         // Si la pieza que se mueve es un peón blanco o negro, 
@@ -788,34 +916,34 @@ fn make_movim(board: &mut Tablero, movim: Movim) -> Movim {
     
     else if movim.move_type == defs::SHORT_CASTLE{
         if movim.piece_moving == defs::W_KING{               //# el rey blanco se enroca corto
-            board.board_array[7] = defs::EMPTY_SQUARE;       //# se vacia la casilla de la torre
-            board.board_array[4] = defs::EMPTY_SQUARE;       //# se vacia la casilla del rey
-            board.board_array[6] = defs::W_KING;             //# ponemos el rey blanco
-            board.board_array[5] = defs::W_ROOK;             //# ponemos la torre blanca
+            board.modif_board(7, defs::EMPTY_SQUARE);       //# se vacia la casilla de la torre
+            board.modif_board(4, defs::EMPTY_SQUARE);       //# se vacia la casilla del rey
+            board.modif_board(6, defs::W_KING);             //# ponemos el rey blanco
+            board.modif_board(5, defs::W_ROOK);             //# ponemos la torre blanca
             board.white_castle = defs::CASTLE_NONE;         //# cambiamos el flag para hacer imposible otro enroque
         }
         else{
-            board.board_array[119] = defs::EMPTY_SQUARE;    //# vaciamos la casilla de la torre
-            board.board_array[116] = defs::EMPTY_SQUARE;    //# vaciamos la casilla del rey
-            board.board_array[118] = defs::B_KING;          //# ponemos el rey
-            board.board_array[117] = defs::B_ROOK;          //# ponemos la torre negra
+            board.modif_board(119, defs::EMPTY_SQUARE);    //# vaciamos la casilla de la torre
+            board.modif_board(116, defs::EMPTY_SQUARE);    //# vaciamos la casilla del rey
+            board.modif_board(118, defs::B_KING);          //# ponemos el rey
+            board.modif_board(117, defs::B_ROOK);          //# ponemos la torre negra
             board.black_castle = defs::CASTLE_NONE;        //# imposibilitamos ulteriores enroques del negro
         }
     }
     
     else if movim.move_type == defs::LONG_CASTLE{
         if movim.piece_moving == defs::W_KING{                   //# el rey blanco se enroca largo
-            board.board_array[0] = defs::EMPTY_SQUARE;           //# se vacia la casilla de la torre
-            board.board_array[4] = defs::EMPTY_SQUARE;           //# se vacia la casilla del rey
-            board.board_array[2] = defs::W_KING;                 //# ponemos el rey blanco
-            board.board_array[3] = defs::W_ROOK;                 //# ponemos la torre blanca
+            board.modif_board(0, defs::EMPTY_SQUARE);           //# se vacia la casilla de la torre
+            board.modif_board(4, defs::EMPTY_SQUARE);           //# se vacia la casilla del rey
+            board.modif_board(2, defs::W_KING);                 //# ponemos el rey blanco
+            board.modif_board(3, defs::W_ROOK);                 //# ponemos la torre blanca
             board.white_castle = defs::CASTLE_NONE;             //# imposibilitamos mas enroques
         }
         else{
-            board.board_array[112] = defs::EMPTY_SQUARE;         //# vaciamos la casilla de la torre
-            board.board_array[116] = defs::EMPTY_SQUARE;         //# vaciamos la casilla del rey
-            board.board_array[114] = defs::B_KING;               //# ponemos el rey negro
-            board.board_array[115] = defs::B_ROOK;               //# ponemos la torre negra
+            board.modif_board(112, defs::EMPTY_SQUARE);         //# vaciamos la casilla de la torre
+            board.modif_board(116, defs::EMPTY_SQUARE);         //# vaciamos la casilla del rey
+            board.modif_board(114, defs::B_KING);               //# ponemos el rey negro
+            board.modif_board(115, defs::B_ROOK);               //# ponemos la torre negra
             board.black_castle = defs::CASTLE_NONE;              //# imposibilitamos mas enroques
         }
     }
@@ -830,14 +958,14 @@ fn make_movim(board: &mut Tablero, movim: Movim) -> Movim {
             # Si se trata de una captura de peón blanco, borramos la casilla que se encuentra debajo.
             */
             idx = (movim.to_index - 16) as usize;
-            board.board_array[idx] = defs::EMPTY_SQUARE;
+            board.modif_board(idx, defs::EMPTY_SQUARE);
         }
         else{
             idx = (movim.to_index + 16) as usize;
-            board.board_array[idx] = defs::EMPTY_SQUARE
+            board.modif_board(idx, defs::EMPTY_SQUARE);
         }
-        board.board_array[movim.to_index] = movim.piece_moving;        //# pone la casilla destino
-        board.board_array[movim.from_index] = defs::EMPTY_SQUARE;     //# limpia la casilla origen
+        board.modif_board(movim.to_index, movim.piece_moving);        //# pone la casilla destino
+        board.modif_board(movim.from_index, defs::EMPTY_SQUARE);     //# limpia la casilla origen
     }
     
     // capturamos las promociones que es lo unico que nos queda
@@ -845,22 +973,22 @@ fn make_movim(board: &mut Tablero, movim: Movim) -> Movim {
         let mut turno: i16;
         if movim.move_type == defs::PROMOTION_QUEEN{
             turno = board.to_move * -1;
-            board.board_array[movim.to_index] = defs::W_QUEEN * turno;
+            board.modif_board(movim.to_index, defs::W_QUEEN * turno);
         }
         if movim.move_type == defs::PROMOTION_ROOK{
             turno = board.to_move * -1;
-            board.board_array[movim.to_index] = defs::W_ROOK * turno;
+            board.modif_board(movim.to_index, defs::W_ROOK * turno);
         }
         if movim.move_type == defs::PROMOTION_BISHOP{
             turno = board.to_move * -1;
-            board.board_array[movim.to_index] = defs::W_BISHOP * turno;
+            board.modif_board(movim.to_index, defs::W_BISHOP * turno);
         }
         if movim.move_type == defs::PROMOTION_KNIGHT{
             turno = board.to_move * -1;
-            board.board_array[movim.to_index] = defs::W_KNIGHT * turno;
+            board.modif_board(movim.to_index, defs::W_KNIGHT * turno);
         }
             
-        board.board_array[movim.from_index] = defs::EMPTY_SQUARE;     //# vaciamos la casilla origen
+        board.modif_board(movim.from_index, defs::EMPTY_SQUARE);     //# vaciamos la casilla origen
     }
     
     /*
@@ -929,6 +1057,8 @@ fn make_movim(board: &mut Tablero, movim: Movim) -> Movim {
         }
     }
     
+    board.zobrist ^= zobrist::estado(board);
+    
     // ahora vamos a comprobar si se ha hecho el movimiento
     //println!("925 -- {}", get_fen(board));
     // retornamos el movim para reutilizar el valor
@@ -944,6 +1074,10 @@ fn make_movim(board: &mut Tablero, movim: Movim) -> Movim {
 	 *  @param movim de tipo Movim
 */
 fn unmake_movim(board: &mut Tablero, movim: Movim){
+    // igual que en make_movim, el estado sale de la clave y al final, con
+    // las piezas ya en su sitio, se pone el previo
+    board.zobrist ^= zobrist::estado(board);
+    
     //reseteamos las variables conocidas
     board.en_passant = movim.prev_pos[0];
     board.white_castle = movim.prev_pos[1];
@@ -967,56 +1101,57 @@ fn unmake_movim(board: &mut Tablero, movim: Movim){
     
     if movim.move_type == defs::SHORT_CASTLE{
         if movim.piece_moving == defs::W_KING{               //# El rey blanco enroca corto
-            board.board_array[5] = defs::EMPTY_SQUARE;       //# Vaciamos las casillas del enroque
-            board.board_array[6] = defs::EMPTY_SQUARE;       //# .
-            board.board_array[4] = defs::W_KING;             //# ponemos de vuelta el rey
-            board.board_array[7] = defs::W_ROOK;             //# ponemos de vuelta la torre blanca
+            board.modif_board(5, defs::EMPTY_SQUARE);       //# Vaciamos las casillas del enroque
+            board.modif_board(6, defs::EMPTY_SQUARE);       //# .
+            board.modif_board(4, defs::W_KING);             //# ponemos de vuelta el rey
+            board.modif_board(7, defs::W_ROOK);             //# ponemos de vuelta la torre blanca
         }
         else if movim.piece_moving == defs::B_KING{          //# el rey negro enroca corto
-            board.board_array[117] = defs::EMPTY_SQUARE;     //# Vaciamos las casillas del enroque
-            board.board_array[118] = defs::EMPTY_SQUARE;     //# .
-            board.board_array[116] = defs::B_KING;           //# ponemos de vuelta el rey negro
-            board.board_array[119] = defs::B_ROOK;           //# ponemos de vuelta la torre negra
+            board.modif_board(117, defs::EMPTY_SQUARE);     //# Vaciamos las casillas del enroque
+            board.modif_board(118, defs::EMPTY_SQUARE);     //# .
+            board.modif_board(116, defs::B_KING);           //# ponemos de vuelta el rey negro
+            board.modif_board(119, defs::B_ROOK);           //# ponemos de vuelta la torre negra
         }
     }
     
     else if movim.move_type == defs::LONG_CASTLE{
         if movim.piece_moving == defs::W_KING{                //# El rey blanco se enroca largo
-            board.board_array[2] = defs::EMPTY_SQUARE;       //# Vaciamos las casillas de enroque
-            board.board_array[3] = defs::EMPTY_SQUARE;       //# .
-            board.board_array[4] = defs::W_KING;             //# Ponemos el rey de vuelta
-            board.board_array[0] = defs::W_ROOK;             //# Ponemos la torre de vuelta
+            board.modif_board(2, defs::EMPTY_SQUARE);       //# Vaciamos las casillas de enroque
+            board.modif_board(3, defs::EMPTY_SQUARE);       //# .
+            board.modif_board(4, defs::W_KING);             //# Ponemos el rey de vuelta
+            board.modif_board(0, defs::W_ROOK);             //# Ponemos la torre de vuelta
         }
         else if movim.piece_moving == defs::B_KING{           //# El negro se enroca largo
-            board.board_array[114] = defs::EMPTY_SQUARE;     //# vaciamos la casilla del enroque
-            board.board_array[115] = defs::EMPTY_SQUARE;     //# .
-            board.board_array[116] = defs::B_KING;           //# Ponemos el rey negro de vuelta
-            board.board_array[112] = defs::B_ROOK;           //# Ponemos de vuelta la torre negra
+            board.modif_board(114, defs::EMPTY_SQUARE);     //# vaciamos la casilla del enroque
+            board.modif_board(115, defs::EMPTY_SQUARE);     //# .
+            board.modif_board(116, defs::B_KING);           //# Ponemos el rey negro de vuelta
+            board.modif_board(112, defs::B_ROOK);           //# Ponemos de vuelta la torre negra
         }
     }
     
     else if movim.move_type == defs::EN_PASSANT {
         
-        board.board_array[movim.from_index] = movim.piece_moving;      // Ponemos el peon de vuelta
-        board.board_array[movim.to_index] = defs::EMPTY_SQUARE;       // Limpiamos la casilla original
+        board.modif_board(movim.from_index, movim.piece_moving);      // Ponemos el peon de vuelta
+        board.modif_board(movim.to_index, defs::EMPTY_SQUARE);       // Limpiamos la casilla original
         
         // Ponemos de vuelta un peon negro en la casilla correcta
         // si es el blanco el que está moviendo
         if movim.piece_moving == defs::W_PAWN{
-            board.board_array[movim.to_index - 16] = defs::B_PAWN;
+            board.modif_board(movim.to_index - 16, defs::B_PAWN);
         }
         // si no es el blanco, entonces solo queda el negro
         else{
-            board.board_array[movim.to_index + 16] = defs::W_PAWN;
+            board.modif_board(movim.to_index + 16, defs::W_PAWN);
         }
     }
             
     else{
-        board.board_array[movim.from_index] = movim.piece_moving;      //# Ponemos la pieza de vuelta
-        board.board_array[movim.to_index] = movim.capture;            //# La captura mantiene el valor para
+        board.modif_board(movim.from_index, movim.piece_moving);      //# Ponemos la pieza de vuelta
+        board.modif_board(movim.to_index, movim.capture);            //# La captura mantiene el valor para
         //# numero de la pieza capturada o 0
         //# (EMPTY_SQUARE) si no es una captura
     }
+    board.zobrist ^= zobrist::estado(board);
     // ahora vamos a comprobar si se ha hecho el movimiento
     //println!("1014 -- {}", get_fen(board));
 }
@@ -1034,260 +1169,280 @@ fn unmake_movim(board: &mut Tablero, movim: Movim){
  *  Retorna un vector con todas las jugadas legales posibles
  *
  */
-fn generate_moves(board: &mut Tablero) -> Vec<Movim>{
-    let mut pseudo_moves: Vec<Movim> = Vec::new();
-    generate_pseudo_moves(board, &mut pseudo_moves);
-    let legal_moves = filter_moves(board, pseudo_moves);
-    // retornamos solo las jugadas legales
-    legal_moves
+fn generate_moves(board: &Tablero) -> Vec<Movim>{
+    let mut legales = ListaJugadas::init();
+    genera_jugadas(board, &mut legales);
+    legales.iter().cloned().collect()
 }
 // FIN getLegalMoves()
 
 
-
-
 /*
- *  Toma una lista con las jugadas seudo-legales y quita las ilegales
- *  retornando una lista con las jugadas legales
+ *  Rellena la lista con las jugadas legales de la posicion, sin pedir memoria.
+ *  Es lo que usan perft y el resto de funciones que generan muchas veces
  *
- *  @param board y vector   con la direccion en memoria de las jugadas seudo-legales
- *  @return Vector          retorna las jugadas legales
+ *  @param board    la posicion
+ *  @param lista    se vacia y se rellena con las jugadas legales
 */
+pub fn genera_jugadas(board: &Tablero, lista: &mut ListaJugadas) {
+    lista.clear();
+    generate_pseudo_moves(board, lista);
+    filter_moves(board, lista);
+}
 
-fn filter_moves(board: &mut Tablero, pseudo_moves: Vec<Movim>) -> Vec<Movim>{
-    let mut filtered_moves: Vec<Movim> = Vec::new();
-    
-    // Comenzamos encontrando el rey en el tablero
-    let mut king_square: i16 = -1;
-    // el indice para el bucle
-    let mut index: i16 = 0;
+
+// Los jaques y las clavadas sobre el rey del bando que mueve. Se calculan
+// una sola vez por posicion y con ellos se sabe si una jugada es legal
+// sin tener que hacerla y deshacerla
+struct Amenazas {
+    rey: i16,                   // la casilla del rey
+    jaques: u8,                 // cuantas piezas le dan jaque
+    bloqueo: [bool; 128],       // con un solo jaque, casillas en las que capturar o interponerse
+    clavadas: [i16; 128],       // direccion desde el rey de la clavada; 0 si no esta clavada
+    sin_rey: [i16; 128]         // el tablero sin el rey, para ver a donde puede ir
+}
+
+
+fn calcula_amenazas(board: &Tablero, rey: i16) -> Amenazas {
+    let bando = board.to_move;
+    let mut amenazas = Amenazas {
+        rey,
+        jaques: 0,
+        bloqueo: [false; 128],
+        clavadas: [0; 128],
+        sin_rey: board.board_array
+    };
+    amenazas.sin_rey[rey as usize] = defs::EMPTY_SQUARE;
     
-    while index < 120 {
-        if (index & 0x88) == 0{
-            /*
-            # Queremos encontrar el rey del bando que mueve,
-            # asi que se multiplica por to_move y encuentra el rey 'blanco'
-            */
-            if board.board_array[index as usize] * board.to_move == defs::W_KING{
-                king_square = index;     //# Recuerda el index del rey
-                index = 120;             //# Fin del bucle
-            }
-        }
-        else{
-            index += 7;
+    // los caballos y los peones solo dan jaque desde su casilla
+    for &delta in defs::KNIGHT_DELTA.iter() {
+        let casilla = rey + delta;
+        if (casilla & 0x88) == 0 && board.board_array[casilla as usize] == defs::W_KNIGHT * -bando {
+            amenazas.jaques += 1;
+            amenazas.bloqueo[casilla as usize] = true;
         }
-        index += 1;
     }
-    
-    if king_square == -1{
-        // No se ha encontrado el rey en las seudomoves, 
-        // por lo que no podemos filtrar las ilegales
-        filtered_moves = pseudo_moves;
-        return filtered_moves ;
+    // los peones negros atacan hacia abajo, asi que estan por encima del rey blanco
+    for &delta in [15, 17].iter() {
+        let casilla = rey + delta * bando;
+        if (casilla & 0x88) == 0 && board.board_array[casilla as usize] == defs::W_PAWN * -bando {
+            amenazas.jaques += 1;
+            amenazas.bloqueo[casilla as usize] = true;
+        }
     }
     
-    //# Ahora recorremos cada seudo-jugada
-    for i in 0..pseudo_moves.len(){
-        let mut current_move = pseudo_moves[i as usize];     // Obtiene un movimiento de la lista
-        /*
-        # Si el movim. es un enroque tambien necesitamos comprobar el enroque en jaque
-        # y las casillas de transito, por tanto comprobamos el tipo de movimiento
-        */
-        if current_move.move_type == defs::SHORT_CASTLE{
-            make_movim(board, current_move);     // Hace la jugada en el tablero
-            /*
-            # Ahora verificamos si la casilla original del rey o las dos a 
-            # la derecha son atacadas.
-            # Esto atrapa las tres posibilidades; Enroque bajo jaque, 
-            # enroque sobre casilla atacada, y enroque bajo jaque
-            #
-            # Si ninguno de ellos es atacado podemos agregarlo a movimientos legales.
-            */
-            if !es_atacada(board, current_move.from_index as i16) &&
-                        !es_atacada(board, (current_move.from_index + 1) as i16) &&
-                        !es_atacada(board, (current_move.from_index + 2) as i16){
-                filtered_moves.push(current_move);
-            }
-            unmake_movim(board, current_move);     //# deshacemos la jugada para restaurar el tablero
-        }
-        else if current_move.move_type == defs::LONG_CASTLE{
-            make_movim(board, current_move);    //# Hacemos la jugada en el tablero
-
-            // aqui hacemos lo mismo que arriba para SHORT_CASTLE
-            if !es_atacada(board, current_move.from_index as i16) &&
-                        !es_atacada(board, (current_move.from_index - 1) as i16) &&
-                        !es_atacada(board, (current_move.from_index - 2) as i16){
-                filtered_moves.push(current_move);
-            }
-            unmake_movim(board, current_move);      //deshacemos la jugada para restaurar el tablero
-        }
-        // # capturamos todos los demas tipos de jugadas
-        else{   
-            make_movim(board, current_move);    //# realizamos el movim. en el tablero
-            /*
-            # Ya hemos determinado la casilla del rey, pero si el rey se está moviendo, 
-            # debemos llamar a es_atacada con su nuevo casilla y no donde estaba antes.
-            */
-            if current_move.piece_moving == defs::W_KING ||
-                            current_move.piece_moving == defs::B_KING{
-                if !es_atacada(board, current_move.to_index as i16){
-                    filtered_moves.push(current_move);
+    // las piezas de largo alcance: recorremos las 8 direcciones desde el rey.
+    // Si la primera pieza es contraria y se mueve en esa direccion da jaque;
+    // si es propia y detras hay una contraria que se mueve asi, esta clavada
+    for &delta in defs::QUEEN_DELTA.iter() {
+        let diagonal = delta.abs() == 15 || delta.abs() == 17;
+        let mut propia: i16 = -1;
+        let mut casilla = rey + delta;
+        
+        while (casilla & 0x88) == 0 {
+            // como en el resto del generador, las piezas del bando que mueve quedan positivas
+            let pieza = board.board_array[casilla as usize] * bando;
+            if pieza > 0 {
+                if propia != -1 {
+                    break;      // dos piezas propias, no hay clavada
                 }
-            }
-            //# Si no es el rey el que está moviendo, usamos dondes estaba determinado antes
-            else{
-                //# Si el no puede ser atacado añadimos la jugada
-                if !es_atacada(board, king_square){
-                    filtered_moves.push(current_move);
+                propia = casilla;
+            }
+            else if pieza < 0 {
+                let alcanza = pieza == -defs::W_QUEEN ||
+                              (diagonal && pieza == -defs::W_BISHOP) ||
+                              (!diagonal && pieza == -defs::W_ROOK);
+                if alcanza {
+                    if propia == -1 {
+                        amenazas.jaques += 1;
+                        let mut tapa = rey + delta;
+                        loop {
+                            amenazas.bloqueo[tapa as usize] = true;
+                            if tapa == casilla {
+                                break;
+                            }
+                            tapa += delta;
+                        }
+                    }
+                    else {
+                        amenazas.clavadas[propia as usize] = delta;
+                    }
                 }
+                break;
             }
-            unmake_movim(board, current_move);    //# Deshacemos la jugada y restauramos el tablero
+            casilla += delta;
         }
     }
     
-    // retornamos solo las jugadas validas
-    filtered_moves
+    amenazas
 }
 
 
 
 
-fn es_atacada(board: &mut Tablero, attacked: i16) -> bool{
-    let mut attacker: i16 = 0;
+/*
+ *  Quita de la lista las jugadas seudo-legales que dejan el rey en jaque
+ *
+ *  @param board    la posicion
+ *  @param lista    las jugadas seudo-legales; se quedan solo las legales
+*/
+
+fn filter_moves(board: &Tablero, lista: &mut ListaJugadas){
+    let rey = busca_rey(board, board.to_move);
+    if rey == -1 {
+        // No hay rey en el tablero, asi que no podemos filtrar las ilegales
+        return;
+    }
     
-    while attacker < 120{
-        if (attacker & 0x88) == 0{      // Si estamos en las casillas del tablero real
-            /*
-            # Cambia el color de la pieza en la casilla y lo mantiene en la variable, 
-            # esto se hace para hacer el código más claro a continuación.
-            
-            # Entonces, si el negro se está moviendo y encontramos una reina negra (-2), 
-            # la convertimos en una reina blanca (2) multiplicando por to_move = -1 (movimiento del negro). 
-            # Ahora solo tenemos que revisar las piezas 'blancas' a continuación.
-            */
-            let piece_on_square = board.board_array[attacker as usize] * board.to_move;
-            
-            if defs::ATTACK_ARRAY[(attacked-attacker+128) as usize] == defs::ATTACK_NONE{
-                let _xxx = 1;   // no hacemos nada
-            }
-            
-            else if defs::ATTACK_ARRAY[(attacked-attacker+128) as usize] == defs::ATTACK_KQR{
-                /*
-                // Si la casilla chequeada contiene alguna de las piezas que
-                // son capaces de llegar a la casilla atacada.
-                //
-                // Ya que está a solo una casilla de distancia de la casilla 
-                // atacada, podemos concluir con seguridad que la casilla es atacada.
-                */
-                if piece_on_square == defs::W_KING ||
-                        piece_on_square == defs::W_QUEEN ||
-                        piece_on_square == defs::W_ROOK {
-                    return true;
-                }
-            }
-            
-            else if defs::ATTACK_ARRAY[(attacked-attacker+128) as usize] == defs::ATTACK_QR {
-                if piece_on_square == defs::W_QUEEN || piece_on_square == defs::W_ROOK {
-                    // Si el racorrido (traverse) golpea la casilla atacada
-                    // la pieza puede ser atacada.
-                    if traverse_delta(board, attacker, attacked){
-                        return true;
-                    }
-                }
-            }
-            
-            else if defs::ATTACK_ARRAY[(attacked-attacker+128) as usize] == defs::ATTACK_KQB_WP{
-                if piece_on_square == defs::W_KING ||
-                        piece_on_square == defs::W_QUEEN ||
-                        piece_on_square == defs::W_BISHOP {
-                    return true;
-                }
-                /*
-                # Si es el turno de las blancas para moverse y la pieza es un peón blanco, 
-                # puede atacar la casilla.
-
-                # Hacemos esta distinción para no confundir los peones negros y blancos, 
-                #ya que solo pueden moverse en una dirección dependiendo del color.
-                */
-                if board.to_move == 1 && board.board_array[attacker as usize] == defs::W_PAWN {
-                    return true;
-                }
-            }
-            
-            else if defs::ATTACK_ARRAY[(attacked-attacker+128) as usize] == defs::ATTACK_KQB_BP{
-                    if piece_on_square == defs::W_KING ||
-                            piece_on_square == defs::W_QUEEN ||
-                            piece_on_square == defs::W_BISHOP {
-                        return true;
-                    }
-                    /*
-                    #// Si es el turno del negro y la pieza es un peon blanco
-                    #// puede atacar la casilla.
-                    */
-                    if board.to_move == -1 && board.board_array[attacker as usize] == defs::B_PAWN {
-                        return true;
-                    }
-            }
-            
-            else if defs::ATTACK_ARRAY[(attacked-attacker+128) as usize] == defs::ATTACK_QB{
-                if piece_on_square == defs::W_QUEEN || piece_on_square == defs::W_BISHOP{
-                    /*
-                    # Si al hacer traverse toca la casilla atacada
-                    # entonces la pieza puede ser atacada
-                    */
-                    if traverse_delta(board, attacker, attacked) {
-                        return true;
-                    }
-                }
-            }
-            
-            else if defs::ATTACK_ARRAY[(attacked-attacker+128) as usize] == defs::ATTACK_N{
-                /*
-                # Los caballos pueden saltar, por lo que no nos preocupamos
-                # posibles piezas en el camino
-                */
-                if piece_on_square == defs::W_KNIGHT {
-                    return true;
-                }
-            }
+    let amenazas = calcula_amenazas(board, rey);
+    let mut legales = 0;
+    for i in 0..lista.num {
+        let movim = lista.jugadas[i];
+        if es_legal(board, &amenazas, movim) {
+            lista.jugadas[legales] = movim;
+            legales += 1;
         }
-        
-        else{ 
-            attacker += 7;   // No en el tablero, saltamos sig. fila
-        }                    // +7 ya que en el bucle general sumo +1 tambien
-        
-        attacker += 1;
     }
-   
-    return false             // si false, entonces ninguna casilla puede atacar la casilla
+    lista.num = legales;
+}
+
+
+fn es_legal(board: &Tablero, amenazas: &Amenazas, movim: Movim) -> bool {
+    let contrario = -board.to_move;
+    let desde = movim.from_index as i16;
+    let hasta = movim.to_index as i16;
+    
+    if movim.move_type == defs::SHORT_CASTLE {
+        // no se puede enrocar en jaque, ni pasando por una casilla atacada ni llegando a ella
+        amenazas.jaques == 0 &&
+                    !es_atacada(&board.board_array, desde + 1, contrario) &&
+                    !es_atacada(&board.board_array, desde + 2, contrario)
+    }
+    else if movim.move_type == defs::LONG_CASTLE {
+        amenazas.jaques == 0 &&
+                    !es_atacada(&board.board_array, desde - 1, contrario) &&
+                    !es_atacada(&board.board_array, desde - 2, contrario)
+    }
+    else if movim.move_type == defs::EN_PASSANT {
+        // la captura al paso quita dos peones de la misma fila, lo que puede descubrir
+        // un jaque que no se ve con las clavadas; la hacemos en una copia del tablero
+        let mut casillas = board.board_array;
+        casillas[movim.from_index] = defs::EMPTY_SQUARE;
+        casillas[movim.to_index] = movim.piece_moving;
+        casillas[(hasta - 16 * board.to_move) as usize] = defs::EMPTY_SQUARE;
+        !es_atacada(&casillas, amenazas.rey, contrario)
+    }
+    else if desde == amenazas.rey {
+        // el rey no puede ir a una casilla atacada. Se mira sin el rey en el tablero
+        // para que no tape los ataques de las piezas de largo alcance
+        !es_atacada(&amenazas.sin_rey, hasta, contrario)
+    }
+    else if amenazas.jaques > 1 || (amenazas.jaques == 1 && !amenazas.bloqueo[hasta as usize]) {
+        // con jaque doble solo puede mover el rey; con uno hay que capturar
+        // la pieza que da jaque o interponerse
+        false
+    }
+    else {
+        // una pieza clavada solo se mueve en la linea entre el rey y la que la clava
+        let clavada = amenazas.clavadas[desde as usize];
+        clavada == 0 || defs::DELTA_ARRAY[(hasta - amenazas.rey + 128) as usize] == clavada
+    }
 }
 
 
 
 /*
- *  Usado por es_atacada() para recorrer el delta de una pieza para
- *  ver si se interpone en el camino de cualquier pieza a la casilla atacada.
+ *  Comprueba si alguna pieza del bando puede capturar en la casilla,
+ *  mirando desde la casilla hacia fuera en vez de recorrer todo el tablero
  *
- *  @param int attacker     La casilla atacante
- *  @param int attacked     La casilla atacada
- *  @return booleano        True si la pieza puede alcanzar la casilla atacada, False si no puede
+ *  @param casillas     el tablero 0x88
+ *  @param attacked     la casilla atacada
+ *  @param bando        el bando atacante (WHITE_TO_MOVE o BLACK_TO_MOVE)
+ *  @return bool        true si la casilla esta atacada
 */
-fn traverse_delta(board: &mut Tablero, attacker: i16, attacked: i16) -> bool {
-    let mut delta_index: i16 = attacker;                                        //# Inicializa desde la primera casilla
-    let delta: i16 = defs::DELTA_ARRAY[(attacked - attacker + 128) as usize];   //# encuentra el delta necesitado
+fn es_atacada(casillas: &[i16; 128], attacked: i16, bando: i16) -> bool{
+    // caballos
+    for &delta in defs::KNIGHT_DELTA.iter() {
+        let casilla = attacked + delta;
+        if (casilla & 0x88) == 0 && casillas[casilla as usize] == defs::W_KNIGHT * bando {
+            return true;
+        }
+    }
     
-    loop {
-        delta_index += delta; // Añade el delta de la jug. a la casilla siguiente
-
-        // Alcanzamos la casilla atacada, retornamos por tanto true
-        if delta_index == attacked{
+    // peones: los blancos atacan hacia arriba, asi que estan por debajo de la casilla
+    for &delta in [15, 17].iter() {
+        let casilla = attacked - delta * bando;
+        if (casilla & 0x88) == 0 && casillas[casilla as usize] == defs::W_PAWN * bando {
             return true;
         }
-        // Se ha encontrado una pieza en el camino, por lo que retornamos false
-        if board.board_array[delta_index as usize] != defs::EMPTY_SQUARE{
-            return false;
+    }
+    
+    // rey, dama, torres y alfiles: la primera pieza en cada direccion
+    for &delta in defs::QUEEN_DELTA.iter() {
+        let diagonal = delta.abs() == 15 || delta.abs() == 17;
+        let mut casilla = attacked + delta;
+        let mut distancia = 1;
+        
+        while (casilla & 0x88) == 0 {
+            let pieza = casillas[casilla as usize] * bando;
+            if pieza != defs::EMPTY_SQUARE {
+                if pieza == defs::W_QUEEN ||
+                            (diagonal && pieza == defs::W_BISHOP) ||
+                            (!diagonal && pieza == defs::W_ROOK) ||
+                            (distancia == 1 && pieza == defs::W_KING) {
+                    return true;
+                }
+                break;
+            }
+            casilla += delta;
+            distancia += 1;
+        }
+    }
+   
+    false
+}
+
+
+// Como es_atacada pero retorna todas las casillas de las piezas del bando
+// que atacan la casilla. Es mas lenta; no sirve para el generador
+fn atacantes(casillas: &[i16; 128], attacked: i16, bando: i16) -> Vec<i16> {
+    let mut encontradas = Vec::new();
+    for &delta in defs::KNIGHT_DELTA.iter() {
+        let casilla = attacked + delta;
+        if (casilla & 0x88) == 0 && casillas[casilla as usize] == defs::W_KNIGHT * bando {
+            encontradas.push(casilla);
+        }
+    }
+    
+    for &delta in [15, 17].iter() {
+        let casilla = attacked - delta * bando;
+        if (casilla & 0x88) == 0 && casillas[casilla as usize] == defs::W_PAWN * bando {
+            encontradas.push(casilla);
+        }
+    }
+    
+    for &delta in defs::QUEEN_DELTA.iter() {
+        let diagonal = delta.abs() == 15 || delta.abs() == 17;
+        let mut casilla = attacked + delta;
+        let mut distancia = 1;
+        
+        while (casilla & 0x88) == 0 {
+            let pieza = casillas[casilla as usize] * bando;
+            if pieza != defs::EMPTY_SQUARE {
+                if pieza == defs::W_QUEEN ||
+                            (diagonal && pieza == defs::W_BISHOP) ||
+                            (!diagonal && pieza == defs::W_ROOK) ||
+                            (distancia == 1 && pieza == defs::W_KING) {
+                    encontradas.push(casilla);
+                }
+                break;
+            }
+            casilla += delta;
+            distancia += 1;
         }
     }
+    
+    encontradas
 }
 
 
@@ -1300,7 +1455,7 @@ fn traverse_delta(board: &mut Tablero, attacker: i16, attacked: i16) -> bool {
  * @return Vector   Todas las jugadas pseudo-legales sobre el tablero
  */
 
-fn generate_pseudo_moves(board: &mut Tablero, pseudo_moves: &mut Vec<Movim>) {
+fn generate_pseudo_moves(board: &Tablero, pseudo_moves: &mut ListaJugadas) {
     let mut index: i16 = 0;
     
     while index < 120 {
@@ -1358,7 +1513,7 @@ fn generate_pseudo_moves(board: &mut Tablero, pseudo_moves: &mut Vec<Movim>) {
  *  @return List pseudoMoves    La pseudoMoves actualizada
 */
 
-fn gen_moves(board: &mut Tablero, index: i16, delta: [i16;8], sliding: bool, pseudo_moves: &mut Vec<Movim>){
+fn gen_moves(board: &Tablero, index: i16, delta: [i16;8], sliding: bool, pseudo_moves: &mut ListaJugadas){
     /* 
     # Registra la casilla al-paso del tablero, los derechos de enroque blanco / negro y 
     # los medios movimientos.
@@ -1376,7 +1531,7 @@ fn gen_moves(board: &mut Tablero, index: i16, delta: [i16;8], sliding: bool, pse
                 // 1.- La casilla objetivo está vacía
                 if board.board_array[delta_index as usize] == defs::EMPTY_SQUARE{
                     //# Añade la jugada a las pseudoMoves
-                    let mut movim = Movim::init(board.board_array[index as usize], index as usize, 
+                    let movim = Movim::init(board.board_array[index as usize], index as usize, 
                                 delta_index as usize, 0, defs::ORDINARY_MOVE, prev_pos);
                     pseudo_moves.push(movim);
                     //# Si la pieza en movimiento es deslizante, añadimos la siguiente casilla
@@ -1397,7 +1552,7 @@ fn gen_moves(board: &mut Tablero, index: i16, delta: [i16;8], sliding: bool, pse
                         # Agrega el movimiento como captura a pseudoMoves 
                         # (es decir, envía la pieza int como captura)
                         */
-                        let mut movim = Movim::init(board.board_array[index as usize], index as usize, 
+                        let movim = Movim::init(board.board_array[index as usize], index as usize, 
                                     delta_index as usize, board.board_array[delta_index as usize], 
                                     defs::ORDINARY_MOVE, prev_pos);
                         pseudo_moves.push(movim);
@@ -1427,7 +1582,7 @@ fn gen_moves(board: &mut Tablero, index: i16, delta: [i16;8], sliding: bool, pse
  *  @param Vector       la lista de pseudoMoves a rellenarse
 */
 
-fn gen_pawn(board: &mut Tablero, index: i16, pseudo_moves: &mut Vec<Movim>){
+fn gen_pawn(board: &Tablero, index: i16, pseudo_moves: &mut ListaJugadas){
     let prev_pos = [board.en_passant, board.white_castle, board.black_castle, board.moves_fifty];
     
     if board.to_move == defs::WHITE_TO_MOVE{
@@ -1601,7 +1756,7 @@ fn gen_pawn(board: &mut Tablero, index: i16, pseudo_moves: &mut Vec<Movim>){
  *  @param Struct   - El tablero
  *  @param Vector  - La lista de pseudomoves para rellenarse
 */
-fn gen_castling(board: &mut Tablero, pseudo_moves: &mut Vec<Movim>){
+fn gen_castling(board: &Tablero, pseudo_moves: &mut ListaJugadas){
 
     // # el rey blanco está moviendo
     if board.to_move == defs::WHITE_TO_MOVE{
@@ -1791,7 +1946,8 @@ fn crea_uci(movim: Movim) -> String{
     # ================================================
 */
 
-fn crea_san (board: Tablero, movim: Movim) -> String{
+// el tablero tiene que estar en la posicion de despues de la jugada
+fn crea_san (board: &mut Tablero, movim: Movim) -> String{
     let mut _san = "".to_string();
     let pieza = movim.piece_moving;
     
@@ -1819,6 +1975,33 @@ fn crea_san (board: Tablero, movim: Movim) -> String{
         _ => (),
     };
     
+    // Si otra pieza igual puede ir a la misma casilla hay que decir cual se mueve:
+    // primero por la columna, si no basta por la fila y si no por ambas
+    if pieza.abs() != defs::W_KING && pieza.abs() != defs::W_PAWN {
+        unmake_movim(board, movim);
+        let rivales: Vec<Movim> = generate_moves(board).into_iter()
+                    .filter(|m| m.piece_moving == pieza && m.to_index == movim.to_index &&
+                                m.from_index != movim.from_index)
+                    .collect();
+        make_movim(board, movim);
+        if !rivales.is_empty() {
+            let columna = movim.from_index % 16;
+            let fila = movim.from_index / 16;
+            let letra_columna = ["a", "b", "c", "d", "e", "f", "g", "h"][columna];
+            let num_fila = (fila + 1).to_string();
+            if rivales.iter().all(|m| m.from_index % 16 != columna) {
+                _san.push_str(letra_columna);
+            }
+            else if rivales.iter().all(|m| m.from_index / 16 != fila) {
+                _san.push_str(&num_fila);
+            }
+            else {
+                _san.push_str(letra_columna);
+                _san.push_str(&num_fila);
+            }
+        }
+    }
+    
     if movim.capture != 0 {      // El movimiento es una captura
         // Si la pieza es un peón, necesitamos la columna de origen
         if (pieza == defs::W_PAWN) || (pieza == defs::B_PAWN) {
@@ -1851,10 +2034,6 @@ fn crea_san (board: Tablero, movim: Movim) -> String{
     
     _san.push_str(&((movim.to_index-(movim.to_index%16))/16 + 1).to_string()); // Añadimos la fila
 		
-    if movim.move_type == defs::EN_PASSANT {
-        _san.push_str(" e.p.");
-    }
-    
     // Se añade la coronacion si la hubiere
     match movim.move_type {
         defs::PROMOTION_QUEEN   => _san.push_str("=Q"),
@@ -1864,11 +2043,10 @@ fn crea_san (board: Tablero, movim: Movim) -> String{
         _ => (),
     };
     // esto es para ver si hay puntuacion de jaques
-    let mut tablero = board.clone();
-    if rey_en_mate(&mut tablero) {
+    if rey_en_mate(board) {
         _san.push_str("#");
     }
-    else if rey_en_jaque(&mut tablero){
+    else if rey_en_jaque(board){
         _san.push_str("+");
     }
     
@@ -1896,524 +2074,102 @@ fn stripped_san(san_vestida: String) -> String{
 
 
 
-fn move_from_san(board: &mut Tablero, san: &str) -> Movim{
-    let _piezas = "RNBQK";
-    let _columnas = "abcdefgh".to_string();
-    let _filas = "12345678";
-    let _enroques = ("O-O", "O-O-O");
-    
-    let mut _pieza: i16 = 0;
-    let mut _desde: i16 = 0;
-    let mut _hasta: i16 = 0;
-    let mut _captura: i16 = 0;
-    let mut _tipo_movim = 0;
-    let mut origen_desambiguado: i16 = -1;
-    
-    
+// Busca la jugada legal que corresponde a la SAN.
+// Si no hay ninguna, o si hay mas de una, retorna el error
+fn move_from_san(board: &Tablero, san: &str) -> Result<Movim, ErrorAjedrez> {
     let legales = generate_moves(board);
     
-    // ahora vamos a examinar la SAN y averiguar todos los datos
-    
     let clean_move = stripped_san(san.to_string());
     
-    //let mut piece = "";
-    let mut _from = ""; 
-    //let mut to = "";
-    let promotion: i16;     // = 0;
-    let coinci_re: bool;    // = false;
+    // los enroques
+    if clean_move == "O-O" || clean_move == "O-O-O" {
+        let tipo = if clean_move == "O-O" { defs::SHORT_CASTLE } else { defs::LONG_CASTLE };
+        return match legales.iter().find(|m| m.move_type == tipo) {
+            Some(&enroque) => Ok(enroque),
+            None => Err(ErrorAjedrez::JugadaIlegal(san.to_string())),
+        };
+    }
     
     /*
-    matches.get(0)  --> es la clean_move -- p.ej Rg3f4Q
-    matches.get(1)  --> es la pieza que se mueve --> en este caso es "R"
-    matches.get(2)  --> es la columna desde --> en este caso "g"
-    matches.get(3)  --> es la fila desde --> en este caso "3"
-    matches.get(4)  --> es la casilla destino --> en este caso "f4"
-    matches.get(5)  --> es la pieza que se corona --> en este caso "Q"
-    matches.get(6)  --> será siempre None, porque he limpiado la jugada
+    coincide.get(0)  --> es la clean_move -- p.ej Rg3f4Q
+    coincide.get(1)  --> es la pieza que se mueve --> en este caso es "R" (None si es un peon)
+    coincide.get(2)  --> es la columna desde --> en este caso "g"
+    coincide.get(3)  --> es la fila desde --> en este caso "3"
+    coincide.get(4)  --> es la casilla destino --> en este caso "f4"
+    coincide.get(5)  --> es la pieza que se corona --> en este caso "Q"
     */
+    let re = Regex::new(r"^([NBKRQ])?([a-h])?([1-8])?x?([a-h][1-8])([nbrqNBRQ])?$").unwrap();
+    let coincide = match re.captures(&clean_move) {
+        Some(coincide) => coincide,
+        None => return Err(ErrorAjedrez::SanMalFormada(san.to_string())),
+    };
     
-    let re = Regex::new(r"^([NBKRQ])?([a-h])?([1-8])?x?([a-h][1-8])(=?[nbrqkNBRQK])?(\\+|#)?$").unwrap();
+    // la pieza que se mueve, con el signo del bando que juega
+    let pieza = match coincide.get(1).map(|m| m.as_str()) {
+        Some("K") => defs::W_KING,
+        Some("Q") => defs::W_QUEEN,
+        Some("R") => defs::W_ROOK,
+        Some("B") => defs::W_BISHOP,
+        Some("N") => defs::W_KNIGHT,
+        _ => defs::W_PAWN,
+    } * board.to_move;
+    
+    let hasta = indice_casilla(coincide.get(4).unwrap().as_str())?;
+    
+    // el posible desambiguador. Nc7b4 o Nc2b4, N3e2 etc...
+    let mut columna = coincide.get(2).map(|m| (m.as_str().as_bytes()[0] - b'a') as i16);
+    let fila = coincide.get(3).map(|m| (m.as_str().as_bytes()[0] - b'1') as i16);
+    // un peon que no captura sale de la columna de destino
+    if pieza.abs() == defs::W_PAWN && columna.is_none() {
+        columna = Some(fn_columna(hasta));
+    }
+    
+    // ahora, por ultimo, vamos con la posible promocion
+    let promocion = match coincide.get(5).map(|m| m.as_str().to_uppercase()) {
+        Some(ref p) if p == "Q" => Some(defs::PROMOTION_QUEEN),
+        Some(ref p) if p == "R" => Some(defs::PROMOTION_ROOK),
+        Some(ref p) if p == "B" => Some(defs::PROMOTION_BISHOP),
+        Some(_) => Some(defs::PROMOTION_KNIGHT),
+        None => None,
+    };
     
-    let coincide = re.captures(&clean_move);  //.unwrap();
-    match coincide{
-        Some(_n) => coinci_re = true,
-        None => coinci_re = false,
-    }
-    if !coinci_re {
-        if clean_move == _enroques.0 || clean_move == _enroques.1 {
-            if board.to_move == 1{
-                _pieza = 1;
-                if clean_move == "O-O"{
-                    _desde = 4;
-                    _hasta = 6;
-                }
-                else if clean_move == "O-O-O"{
-                    _desde = 4;
-                    _hasta = 2;
-                }
+    let candidatas: Vec<Movim> = legales.into_iter().filter(|m| {
+        m.piece_moving == pieza &&
+            m.to_index == hasta as usize &&
+            columna.map_or(true, |c| fn_columna(m.from_index as i16) == c) &&
+            fila.map_or(true, |f| fn_fila(m.from_index as i16) == f) &&
+            match promocion {
+                Some(p) => m.move_type == p,
+                None => m.move_type < defs::PROMOTION_QUEEN,
             }
-            else{       //juega el negro
-                _pieza = -1;
-                if clean_move == "O-O"{
-                    _desde = 116;
-                    _hasta = 118;
-                }
-                else if clean_move == "O-O-O"{
-                    _desde = 116;
-                    _hasta = 114;
-                }
-            }
-            
-            for k in legales{
-                if k.piece_moving == _pieza &&
-                        k.from_index == _desde as usize &&
-                        k.to_index == _hasta as usize{
-                    return k;
-                }
-            }
-        }
-        else{
-            return Movim::init(0,0,0,0,0,[0,0,0,0]);
-        }
-    }
+    }).collect();
     
-    else if coinci_re{
-        // ha encontrado algo el regex
-        let coincide1 = re.captures(&clean_move).unwrap();
-        // una variable de control
-        let mut retorno: bool;      // = true;
-        // primero a ver si existe la casilla destino
-        let mut valor: &str = "";
-        
-        match coincide1.get(4){
-            Some(_n) => {
-                        valor = coincide1.get(4).unwrap().as_str();
-                        
-                        //ahora vamos a comprobar que la casilla existe. Convertimos en un vector
-                        let char_vec: Vec<char> = valor.chars().collect();
-                        let _col = _columnas.find(char_vec[0]);
-                        let _fil = _filas.find(char_vec[1]);
-                        if _fil == None || _col == None{
-                            retorno = false;
-                        }
-                        else{
-                            let index: i16 = defs::SQUARE_NAMES.iter().position(|&r| r == valor).unwrap() as i16;
-                            if index >= 0{
-                                _hasta = defs::CASILLAS_VALOR[index as usize].indice;
-                                retorno = true;
-                            }
-                            else {
-                                retorno = false;
-                            }
-                        }
-                       },
-            None => {
-                        retorno = false;
-                    },
-        };
-        
-        if !retorno{
-            return Movim::init(0,0,0,0,0,[0,0,0,0]);
-        }
-        
-        // la pieza que se mueve
-        match coincide1.get(1){
-            Some(_n) => {
-                            let piece_type = coincide1.get(1).unwrap().as_str();
-                            // verificamos que la pieza existe
-                            let encontrada = _piezas.find(piece_type);
-                            
-                            if encontrada == None{
-                                retorno = false;
-                            }
-                            else{
-                                if board.to_move == defs::WHITE_TO_MOVE{
-                                    if piece_type == "N"{
-                                        _pieza = 5;
-                                    }
-                                    else if piece_type == "B"{
-                                        _pieza = 4;
-                                    }
-                                    else if piece_type == "R"{
-                                        _pieza = 3;
-                                    }
-                                    else if piece_type == "Q"{
-                                        _pieza = 2;
-                                    }
-                                    else if piece_type == "K"{
-                                        _pieza = 1;
-                                    }
-                                }
-                                else{
-                                    if piece_type == "N"{
-                                        _pieza = -5;
-                                    }
-                                    else if piece_type == "B"{
-                                        _pieza = -4;
-                                    }
-                                    else if piece_type == "R"{
-                                        _pieza = -3;
-                                    }
-                                    else if piece_type == "Q"{
-                                        _pieza = -2;
-                                    }
-                                    else if piece_type == "K"{
-                                        _pieza = -1;
-                                    }
-                                }
-                                retorno = true;
-                            }
-                        },
-            None => { if board.to_move == defs::WHITE_TO_MOVE{
-                            _pieza = 6;
-                        }
-                        else{
-                            _pieza = -6
-                        }
-                        retorno = true;
-                    }
-        }
-        
-        if !retorno{
-            return Movim::init(0,0,0,0,0,[0,0,0,0]);
-        }
-        
-        // ya tenemos la pieza y la casilla de destino. Nos falta la casilla origen
-        // vamos a dfinir el array desplazamiento de forma implicita
-        
-        let mut desplazamiento = defs::PAWN_DELTA;
-        
-        if _pieza.abs() == 6{       // o _pieza == -6
-            desplazamiento = defs::PAWN_DELTA;
-        }
-        else if _pieza.abs() == 5{   
-            desplazamiento = defs::KNIGHT_DELTA;
-        }
-        else if _pieza.abs() == 4{
-            desplazamiento = defs::BISHOP_DELTA;
-        }
-        else if _pieza.abs() == 3{
-            desplazamiento = defs::ROOK_DELTA;
-        }
-        else if _pieza.abs() == 2{
-            desplazamiento = defs::QUEEN_DELTA;
-        }
-        else if _pieza.abs() == 1{
-            desplazamiento = defs::KING_DELTA;
-        }
-        
-        let mut posible_origen: Vec<i16> = Vec::new();
-        
-        // calculamos las posibles casillas desde donde puede haber movido la pieza
-        for j in 0..desplazamiento.len(){
-            let mut orig = 0;
-            // la variable valor se ha definido arriba en el compares(4)
-            // peones
-            // el desplazamiento de peones depende
-            // si es el blanco se resta, si es negro se suma
-            if _pieza.abs() == 6 && desplazamiento[j] != 0{
-                let index: usize = defs::SQUARE_NAMES.iter().position(|&r| r == valor).unwrap();
-                if board.to_move == 1{        // blancas mueven
-                    orig = defs::CASILLAS_VALOR[index].indice - desplazamiento[j];
-                    if orig & 0x88 == 0 {
-                        if board.board_array[orig as usize] != 6 {
-                            continue; 
-                        }
-                    }
-                    else { continue; }
-                }
-                else{
-                    orig = defs::CASILLAS_VALOR[index].indice + desplazamiento[j];
-                    if orig & 0x88 == 0 {
-                        if board.board_array[orig as usize] != -6 { 
-                            continue; 
-                        }
-                    }
-                    else { continue;}
-                }
-            }
-            
-            // caballo o rey
-            if (_pieza.abs() == 5 || _pieza.abs() == 1) && desplazamiento[j] != 0 {
-                let index: usize = defs::SQUARE_NAMES.iter().position(|&r| r == valor).unwrap();
-                orig = desplazamiento[j] + defs::CASILLAS_VALOR[index].indice;
-                
-                if orig & 0x88 == 0 {
-                    if board.board_array[orig as usize] == 0 { continue; }
-                }
-                else { continue; }
-            }
-            
-            //solo para las piezas no deslizantes
-            if orig & 0x88 == 0 && (_pieza.abs() == 6 ||
-                        _pieza.abs() == 5 || _pieza.abs() == 1){
-                posible_origen.push(orig);
-            }
-            
-            // ahora las piezas deslizantes; Alfil, Dama, Torre
-            if (_pieza.abs() == 4 || _pieza.abs() == 3 || 
-                        _pieza.abs() == 2) && desplazamiento[j] != 0{
-                let index: usize = defs::SQUARE_NAMES.iter().position(|&r| r == valor).unwrap();
-                let mut acumula_desp = desplazamiento[j] + defs::CASILLAS_VALOR[index].indice;
-                
-                for _contador in 0..desplazamiento.len(){     //# es max. 8
-                    if acumula_desp & 0x88 == 0{
-                        if es_atacada(board, defs::CASILLAS_VALOR[index].indice) {
-                            //# traverseDelta retorna True si la casilla destino/origen está libre
-                            //# si hay una pieza retorna False
-                            posible_origen.push(acumula_desp);
-                        }
-                    }
-                    acumula_desp += desplazamiento[j];
-                }
-            }
-        }
-        
-        //# necesitamos un posible desambiguador. Nc7b4 o Nc2b4, N3e2 etc...
-        //# group(2) es la columna, group(3) es la fila
-        let mut confusa = false;
-        //desambiguamos
-        if coincide1.get(2) != None || coincide1.get(3) != None{
-            let col_char: &str;
-            let fila_char: &str;
-            match coincide1.get(2){
-                Some(_n) => col_char = coincide1.get(2).unwrap().as_str(),
-                None => col_char = "None",
-            }
-            match coincide1.get(3){
-                Some(_n) => fila_char = coincide1.get(3).unwrap().as_str(),
-                None => fila_char = "None",
-            }
-                 
-            let mut columna: Vec<i16>;  // = Vec::new();
-            let mut fila: Vec<i16>;     // = Vec::new();
-            let tupla = desambigua(col_char, fila_char);
-            columna = tupla.0;
-            fila = tupla.1;
-            if columna.len() > 0 && fila.len() > 0 {
-                for i in 0..columna.len(){
-                    for j in 0..fila.len(){
-                        if fila[j] == columna[i]{
-                            origen_desambiguado = fila[j];
-                        }
-                    }
-                }
-            }
-            else {
-                if columna.len() > 0{
-                    // todo esto es para cuando se toma con un peon
-                    // cuando hay captura desde la columna adyacente
-                    if _pieza.abs() == 6 {
-                        let ind_col = defs::COLUMNAS.iter().position(|&r| r.col == col_char).unwrap();
-                        if ind_col == 0 {
-                            let nuevo_arr = defs::COLUMNAS[ind_col+1];
-                            for i in 0..nuevo_arr.inds.len(){
-                                columna.push(nuevo_arr.inds[i]);
-                            }
-                        }
-                        else if ind_col == 7 {
-                            let nuevo_arr = defs::COLUMNAS[ind_col-1];
-                            for i in 0..nuevo_arr.inds.len(){
-                                columna.push(nuevo_arr.inds[i]);
-                            }
-                        }
-                        else {
-                            let mut nuevo_arr = defs::COLUMNAS[ind_col+1];
-                            for i in 0..nuevo_arr.inds.len(){
-                                columna.push(nuevo_arr.inds[i]);
-                            }
-                            nuevo_arr = defs::COLUMNAS[ind_col-1];
-                            for i in 0..nuevo_arr.inds.len(){
-                                columna.push(nuevo_arr.inds[i]);
-                            }
-                        }
-                    }
-                    if fila.len() > 0{
-                        let fil = fila_char.parse::<i16>().unwrap() - 1;    //# la fila/columna 1 es el indice 0
-                        origen_desambiguado = columna[fil as usize];        //# es un entero
-                    }
-                    else{
-                        let mut _coinci: bool = false;
-                        for x in 0..columna.len(){
-                            if _coinci { break; }
-                            if board.board_array[columna[x] as usize] == _pieza{
-                                for y in 0..posible_origen.len(){
-                                    if columna[x] == posible_origen[y]{
-                                        origen_desambiguado = columna[x] as i16;
-                                        _coinci = true;
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                if fila.len() > 0{
-                    if columna.len() > 0{
-                        let col = col_char.parse::<i16>().unwrap() - 1;    //# la columna/fila 1 es el indice 0
-                        origen_desambiguado = fila[col as usize];          //# es un entero
-                    }
-                    else{
-                        for x in 0..fila.len(){
-                            if board.board_array[fila[x] as usize] == _pieza{
-                                origen_desambiguado = fila[x] as i16;
-                                break;
-                            }
-                        }
-                    }
-                }
-                
-                /*
-                if fila.len() < 1 && columna.len() > 0{
-                    //#origen_desambiguado = fila
-                    for x in columna{
-                        if board.board_array[x as usize] == _pieza{
-                            // aqui hay un problema cuando hay peones doblados...
-                            origen_desambiguado = x;
-                            break;
-                        }
-                    }
-                }
-                */
-            }
-            confusa = true;
-        }
-        
-        // ahora, por ultimo, vamos con la posible promocion
-        /*
-        Las promociones las tenemos en el modulo defs
-        PROMOTION_QUEEN: i16 = 4;
-        PROMOTION_ROOK: i16 = 5;
-        PROMOTION_BISHOP: i16 = 6;
-        PROMOTION_KNIGHT: i16 = 7;
-        */
-        
-        let prom_san: &str;
-        
-        match coincide1.get(5){
-            Some(_n) => prom_san = coincide1.get(5).unwrap().as_str(),
-            None => prom_san = "None",
-        }
-        
-        if prom_san == "None"{
-            promotion = 0;
-        }
-        else{
-            if prom_san == "Q"{
-                promotion = defs::PROMOTION_QUEEN;
-            }
-            else if prom_san == "R"{
-                promotion = defs::PROMOTION_ROOK;
-            }
-            else if prom_san == "B"{
-                promotion = defs::PROMOTION_BISHOP;
-            }
-            else if prom_san == "N"{
-                promotion = defs::PROMOTION_KNIGHT;
-            }
-            else{
-                // no coincide con ninguna promocion
-                return Movim::init(0,0,0,0,0,[0,0,0,0]);
-                
-            }
-        }
-        
-        // construimos los posibles dict de la jugada san
-        for origen in posible_origen{
-            for k in 0..legales.len(){
-                if legales[k].piece_moving == _pieza &&
-                        legales[k].from_index == origen as usize &&
-                        legales[k].to_index == _hasta as usize{
-                    if confusa {
-                        if origen == origen_desambiguado{
-                            if promotion < defs::PROMOTION_QUEEN{       // no hay promocion de peon
-                                return legales[k];
-                            }
-                            else if legales[k].move_type == promotion{
-                                return legales[k];
-                            }
-                        }
-                        else{
-                            continue;
-                        }
-                    }
-                    else{
-                        if legales[k].move_type == promotion{
-                            return legales[k];
-                        }
-                    }
-                        
-                }
-            }
-        }
+    match candidatas.len() {
+        0 => Err(ErrorAjedrez::JugadaIlegal(san.to_string())),
+        1 => Ok(candidatas[0]),
+        _ => Err(ErrorAjedrez::SanAmbigua(san.to_string())),
     }
-    
-    // si hemos llegado aqui el san es erroneo
-    Movim::init(0,0,0,0,0,[0,0,0,0])
 }
 
 
-
-fn desambigua(grupo2: &str , grupo3: &str) -> (Vec<i16>, Vec<i16>){
-    let mut columna: Vec<i16> = Vec::new();
-    let mut fila: Vec<i16> = Vec::new();
-    
-    if grupo2 != "None"{
-        for i in 0..defs::COLUMNAS.len(){
-            if defs::COLUMNAS[i].col == grupo2{
-                let col_posibles = defs::COLUMNAS[i].inds;
-                for j in 0..col_posibles.len(){
-                    columna.push(col_posibles[j]);
-                }
-                //columna.extend(defs::COLUMNAS[i].inds);
-                break;
-            }
-        }
+// el indice 0x88 de una casilla en algebraico ("e4")
+fn indice_casilla(casilla: &str) -> Result<i16, ErrorAjedrez> {
+    match defs::SQUARE_NAMES.iter().position(|&r| r == casilla) {
+        Some(idx) => Ok(defs::CASILLAS_VALOR[idx].indice),
+        None => Err(ErrorAjedrez::CasillaDesconocida(casilla.to_string())),
     }
-    if grupo3 != "None"{
-        for i in 0..defs::FILAS.len(){
-            // convierto la fila "3" a entero
-            if defs::FILAS[i].fila == grupo3.parse::<i16>().unwrap(){
-                let fil_posibles = defs::FILAS[i].inds;
-                for j in 0..fil_posibles.len(){
-                    fila.push(fil_posibles[j]);
-                }
-                //columna.extend(defs::FILAS[i].inds);
-                break;
-            }
-        }
-    }
-    
-    return (columna, fila);
 }
 
 
 
-fn crea_algebra(board: &mut Tablero, (desde, hasta, promo): (&str, &str, &str)) -> Movim {
+fn crea_algebra(board: &Tablero, (desde, hasta, promo): (&str, &str, &str)) -> Result<Movim, ErrorAjedrez> {
     //let mut pieza_movida: &str;
     let mut candidatas: Vec<Movim> = Vec::new();
     let mut coronacion: i16 = 256;
     
     // obtenemos las casillas numericas
-    let idx1: usize;
-    let idx2: usize;
-    let idx_temp1 = defs::SQUARE_NAMES.iter().position(|&r| r == desde);
-    match idx_temp1 {
-        // si lo ha encontrado
-        Some(x) => idx1 = x as usize,
-        // no lo ha encontrado
-        None    => return Movim::init(0,0,0,0,0,[0,0,0,0]) ,
-    }
-    let idx_temp2 = defs::SQUARE_NAMES.iter().position(|&r| r == hasta);
-    match idx_temp2 {
-        // si lo ha encontrado
-        Some(x) => {idx2 = x as usize},
-        // no lo ha encontrado
-        None    => return Movim::init(0,0,0,0,0,[0,0,0,0]) ,
-    }
-    let idx_desde = defs::CASILLAS_VALOR[idx1].indice;
-    let idx_hasta = defs::CASILLAS_VALOR[idx2].indice;
+    let idx_desde = indice_casilla(desde)?;
+    let idx_hasta = indice_casilla(hasta)?;
     
     // ¿Hay coronacion? Si corona_1 y corona_8 son false no hay coronacion
     let corona_1: bool;
@@ -2468,24 +2224,22 @@ fn crea_algebra(board: &mut Tablero, (desde, hasta, promo): (&str, &str, &str))
     }
     
     if candidatas.len() == 1 {
-        return candidatas[0];
+        return Ok(candidatas[0]);
     }
     
     // el moimiento es erroneo
-    Movim::init(0,0,0,0,0,[0,0,0,0])
+    Err(ErrorAjedrez::JugadaIlegal(format!("{}{}{}", desde, hasta, promo.trim().to_lowercase())))
 }
 
 
 
-fn busca_rey(board: &mut Tablero, bando: i16) -> i16 {
+// la casilla del rey del bando, o -1 si no esta en el tablero
+fn busca_rey(board: &Tablero, bando: i16) -> i16 {
     let mut casilla: i16 = 0;
     while casilla < 120{
         if (casilla & 0x88) == 0 {      // Si estamos en las casillas del tablero real
-            if board.board_array[casilla as usize] == defs::W_KING && bando > 0 {
-                return casilla as i16;
-            }
-            if board.board_array[casilla as usize] == defs::B_KING && bando < 0 {
-                return casilla as i16
+            if board.board_array[casilla as usize] == defs::W_KING * bando {
+                return casilla;
             }
         }
         else{ 
@@ -2493,8 +2247,7 @@ fn busca_rey(board: &mut Tablero, bando: i16) -> i16 {
         }                          // +7 ya que en el bucle general sumo +1 tambien
         casilla += 1;
     }
-    1024    // obligatorio poner un return al final, aunque nunca llegaremos aqui 
-            // ya que siempre habrá dos reyes en el tablero
+    -1
 }
 
 
@@ -2532,167 +2285,136 @@ fn fn_fila(i: i16) -> i16 {
 }
 
 
-fn fen_mutilada(board : &mut Tablero) -> String {
-    // esta funcion se crea para controla la repeticion tres jugadas
-    let mut truncada: String = "".to_string();
-    let entera = get_fen(board);
-    let dividida = entera.split(" ");
-    let vec: Vec<&str> = dividida.collect();
-    
-    truncada.push_str(vec[0]);
-    truncada.push_str(" ");
-    truncada.push_str(vec[1]);
-    truncada.push_str(" ");
-    truncada.push_str(vec[2]);
-    truncada.push_str(" ");
-    truncada.push_str(vec[3]);
-    
-    truncada
+/* ============================================
+# funciones publicas accesorias
+==============================================*/
+
+pub fn mueve_algebra(board: &mut Tablero, (desde, hasta, promo): (&str, &str, &str)) 
+                        -> Result<MoveResult, ErrorAjedrez> {
+    let movim = crea_algebra(board, (desde, hasta, promo))?;
+    Ok(hace_jugada(board, movim))
 }
 
 
-fn crea_flags(flag: i16) -> String {
-    let mut s: String = "".to_string();
-    match flag {
-        defs::ORDINARY_MOVE   => s.push_str("O"),
-        defs::SHORT_CASTLE    => s.push_str("S"),
-        defs::LONG_CASTLE     => s.push_str("L"),
-        defs::EN_PASSANT      => s.push_str("E"),
-        defs::PROMOTION_QUEEN => s.push_str("Q"),
-        defs::PROMOTION_ROOK  => s.push_str("R"),
-        defs::PROMOTION_BISHOP => s.push_str("B"),
-        defs::PROMOTION_KNIGHT => s.push_str("N"),
-        _ => (),
-    };
-    
-    s
+pub fn mueve_san(board: &mut Tablero, san: &str) -> Result<MoveResult, ErrorAjedrez> {
+    let jugada = move_from_san(board, san)?;
+    Ok(hace_jugada(board, jugada))
 }
 
 
+// hace una jugada ya construida, p.ej. con Move::from_uci o Move::from_san
+pub fn mueve(board: &mut Tablero, jugada: Move) -> Result<MoveResult, ErrorAjedrez> {
+    let movim = jugada.movim(board)?;
+    Ok(hace_jugada(board, movim))
+}
 
 
-/* ============================================
-# funciones publicas accesorias
-==============================================*/
+// las jugadas legales de la posicion
+pub fn jugadas_legales(board: &mut Tablero) -> Vec<Move> {
+    generate_moves(board).into_iter().filter_map(Move::from_movim).collect()
+}
 
-pub fn mueve_algebra(board: &mut Tablero, (desde, hasta, promo): (&str, &str, &str)) 
-                                        -> (String, String, String, String, String) {
-    //la variable tablero se utiliza para manipulaciones temporales
-    let mut tablero = board.clone();
-    let movim = crea_algebra (&mut tablero, (desde, hasta, promo));
+
+// hace en el tablero una jugada legal, la guarda en el historico y retorna su descripcion
+fn hace_jugada(board: &mut Tablero, movim: Movim) -> MoveResult {
+    let clave = board.zobrist;
     
-    if movim.piece_moving != 0{
-        //let san = crea_san(tablero, movim);
-        let uci = crea_uci(movim);
-        let turno: &str; 
-        if board.to_move == 1{
-            turno = "w";
-        }
-        else { turno = "b"; }
-        let posic = fen_mutilada(&mut tablero);
-        
-        let jug = make_movim(board, movim);
-        
-        //para el historico 
-        let hist = Historia {
-                posicion: posic,
-                mov: jug
-        };
-        board.anade_historico(hist);
-        
-        // despues de hacer el movim para sacar la san con los signos de posibles jaque
-        tablero = board.clone();
-        let san = crea_san(tablero, movim);
-        
-        // simplemente retornamos una tupla con SAN, UCI, turno, pieza (may/min), flag
-        return (san.to_string(), uci.to_string(), turno.to_string(), pieza_char(jug.piece_moving).to_string(), crea_flags(jug.move_type).to_string());
-    }
+    let jug = make_movim(board, movim);
+    
+    //para el historico 
+    let hist = Historia {
+            clave,
+            mov: jug
+    };
+    board.anade_historico(hist);
     
-    // si hemos llegado hasta aqui es que el movimiento es erroneo
-    ("None".to_string(), "None".to_string(), "None".to_string(), "None".to_string(), "None".to_string()) 
+    // despues de hacer el movim para sacar la san con los signos de posibles jaque
+    let san = crea_san(board, movim);
+    MoveResult::from_movim(jug, &san).unwrap()
 }
 
 
-pub fn mueve_san(board: &mut Tablero, san: &str) 
-                    -> (String, String, String, String, String){
-    let mut tablero = board.clone();    // solo para manipulaciones internas
-    let jugada = move_from_san(board, san);
-    
-    if jugada.piece_moving != 0{
-        let uci = crea_uci(jugada);
-        let turno: &str;
-        if board.to_move == 1{
-            turno = "w";
-        }
-        else { turno = "b"; }
-        let posic = fen_mutilada(&mut tablero);
-        
-        let jug = make_movim(board, jugada);
-        
-        //para el historico 
-        let hist = Historia {
-                posicion: posic,
-                mov: jug
-        };
-        board.anade_historico(hist);
-        
-        // simplemente retornamos una tupla con SAN, UCI, turno, pieza (may/min), flag
-        return (san.to_string(), uci.to_string(), turno.to_string(), pieza_char(jug.piece_moving).to_string(), crea_flags(jug.move_type).to_string());
-    }
-    
-    // si hemos llegado hasta aqui es que el movimiento es erroneo
-    ("None".to_string(), "None".to_string(), "None".to_string(), "None".to_string(), "None".to_string()) 
+// la descripcion de una jugada ya hecha, igual que la que retornan mueve_algebra
+// y mueve_san. None si es la jugada vacia
+pub fn describe_jugada(movim: Movim, san: &str) -> Option<MoveResult> {
+    MoveResult::from_movim(movim, san)
 }
 
 
-pub fn mueve_atras(board: &mut Tablero) -> bool {
+pub fn mueve_atras(board: &mut Tablero) -> Result<(), ErrorAjedrez> {
     let quitada = board.quita_historico();
     if quitada.mov.piece_moving != 0 {
         unmake_movim(board, quitada.mov);
-        return true;
+        return Ok(());
     }
-    false
+    Err(ErrorAjedrez::HistoricoVacio)
+}
+
+
+// Rehace una jugada deshecha antes con mueve_atras.
+// Solo se hace si la jugada es legal en la posicion actual
+pub fn mueve_adelante(board: &mut Tablero, jugada: Movim) -> Result<(), ErrorAjedrez> {
+    let legales = generate_moves(board);
+    let es_legal = legales.iter().any(|m| m.from_index == jugada.from_index &&
+                                        m.to_index == jugada.to_index &&
+                                        m.move_type == jugada.move_type);
+    if !es_legal {
+        return Err(ErrorAjedrez::JugadaIlegal(crea_uci(jugada)));
+    }
+    
+    let clave = board.zobrist;
+    let jug = make_movim(board, jugada);
+    board.anade_historico(Historia {
+            clave,
+            mov: jug
+    });
+    Ok(())
 }
 
 
 pub fn rey_en_jaque(board: &mut Tablero) -> bool {
-    let mut tablero = board.clone();
-    let casilla_rey: i16;
-    let turno: i16;
-    
-    if board.to_move == defs::WHITE_TO_MOVE{
-        turno = defs::WHITE_TO_MOVE;
-    }
-    else { 
-        turno = defs::BLACK_TO_MOVE; 
-    }
-    
-    casilla_rey = busca_rey(&mut tablero, turno);
-    // una vez localizada l casilla del rey del bando que le toca mover
-    // cambiamos de bando en el tablero, para que las piezas contrarias sean las atacantes
-    tablero.modif_bando(turno *-1);
-    
-    let atacada = es_atacada(&mut tablero, casilla_rey);
-    // retornamos el turno a su situación original
-    tablero.modif_bando(turno *-1);
-    atacada
+    let casilla_rey = busca_rey(board, board.to_move);
+    casilla_rey != -1 && es_atacada(&board.board_array, casilla_rey, -board.to_move)
 }
 
 
+// las casillas de las piezas que dan jaque al rey del bando que mueve
+pub fn dan_jaque(board: &Tablero) -> Vec<Square> {
+    let casilla_rey = busca_rey(board, board.to_move);
+    if casilla_rey == -1 {
+        return Vec::new();
+    }
+    atacantes(&board.board_array, casilla_rey, -board.to_move).into_iter()
+            .filter_map(|casilla| Square::from_088(casilla as usize))
+            .collect()
+}
 
 
+// la casilla del rey del color, si lo tiene
+pub fn casilla_rey(board: &Tablero, color: Color) -> Option<Square> {
+    let casilla = busca_rey(board, color.to_i16());
+    if casilla == -1 {
+        return None;
+    }
+    Square::from_088(casilla as usize)
+}
 
 
+// el bando que mueve no tiene ninguna jugada legal
+fn sin_jugadas(board: &Tablero) -> bool {
+    let mut legales = ListaJugadas::init();
+    genera_jugadas(board, &mut legales);
+    legales.is_empty()
+}
+
 
 pub fn rey_ahogado(board: &mut Tablero) -> bool {
-    let mut tablero = board.clone();
-    !rey_en_jaque(&mut tablero) &&  generate_moves(&mut tablero).len() == 0
+    !rey_en_jaque(board) && sin_jugadas(board)
 }
 
 
 pub fn rey_en_mate(board: &mut Tablero) -> bool {
-    let mut tablero = board.clone();
-    rey_en_jaque(&mut tablero) &&  generate_moves(&mut tablero).len() == 0
+    rey_en_jaque(board) && sin_jugadas(board)
 }
 
 
@@ -2838,8 +2560,9 @@ pub fn material_insuficiente(board: &mut Tablero) -> bool {
     if num_piezas == 2 { return true; }
     
     /* k vs. kn .... or .... k vs. kb */
-    else if num_piezas == 3 && (piezas[&defs::W_BISHOP] == 1 ||
-                    piezas[&defs::W_KNIGHT] == 1) { return true; }
+    // con get: en k vs. kr o k vs. kp no hay alfil ni caballo que contar
+    else if num_piezas == 3 && (piezas.get(&defs::W_BISHOP) == Some(&1) ||
+                    piezas.get(&defs::W_KNIGHT) == Some(&1)) { return true; }
                     
     /* kb vs. kb donde cualquier numero de alfiles estan en el mismo color de casilla */
     else if piezas.contains_key(&defs::W_BISHOP) {
@@ -2858,106 +2581,134 @@ pub fn material_insuficiente(board: &mut Tablero) -> bool {
 
 
 pub fn repeticion_triple(board: &mut Tablero) -> bool {
-    let historico = board.history.clone();
-    if historico.len() == 0 { 
-        // no ha empezado la partida
-        return false; 
-    }
-    let pos_ultima = &historico[historico.len() - 1].posicion;
-    let ocurrencias = historico.iter().filter(|n| n.posicion.as_str() == pos_ultima).count();
-    if ocurrencias >= 3 {
-        return true;
+    let historico = &board.history;
+    // una posicion solo puede repetirse desde la ultima captura o jugada de peon,
+    // que son las que ponen a cero el contador de la regla de 50 jugadas
+    let reversibles = (board.moves_fifty as usize).min(historico.len());
+    
+    // el historico guarda la clave de antes de cada jugada, asi que la posicion
+    // actual se cuenta aparte; y solo miramos las que tenian el mismo turno
+    let ocurrencias = historico[historico.len() - reversibles..].iter().rev()
+                        .skip(1).step_by(2)
+                        .filter(|h| h.clave == board.zobrist)
+                        .count() + 1;
+    ocurrencias >= 3
+}
+
+
+// ========= el final de una partida ==================
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FinPartida {
+    JaqueMate { ganador: Color },
+    Ahogado,
+    MaterialInsuficiente,
+    RepeticionTriple,
+    CincuentaJugadas,
+}
+
+impl FinPartida {
+    // el resultado para la cabecera Result del PGN
+    pub fn resultado(&self) -> &'static str {
+        match *self {
+            FinPartida::JaqueMate { ganador: Color::White } => "1-0",
+            FinPartida::JaqueMate { ganador: Color::Black } => "0-1",
+            _ => "1/2-1/2",
+        }
     }
     
-    false
+    // el motivo por el que ha terminado la partida
+    pub fn motivo(&self) -> &'static str {
+        match *self {
+            FinPartida::JaqueMate { .. } => "Jaque mate",
+            FinPartida::Ahogado => "Rey ahogado",
+            FinPartida::MaterialInsuficiente => "Material insuficiente",
+            FinPartida::RepeticionTriple => "Triple repeticion",
+            FinPartida::CincuentaJugadas => "Regla de las cincuenta jugadas",
+        }
+    }
+}
+
+
+// Como ha terminado la partida en la posicion del tablero,
+// o None si todavia se puede seguir jugando
+pub fn resultado_partida(board: &mut Tablero) -> Option<FinPartida> {
+    if rey_en_mate(board) {
+        // pierde el bando que tiene que mover
+        return Some(FinPartida::JaqueMate { ganador: Color::from_i16(board.to_move).opposite() });
+    }
+    if rey_ahogado(board) {
+        return Some(FinPartida::Ahogado);
+    }
+    if material_insuficiente(board) {
+        return Some(FinPartida::MaterialInsuficiente);
+    }
+    if repeticion_triple(board) {
+        return Some(FinPartida::RepeticionTriple);
+    }
+    if board.moves_fifty >= 100 {
+        return Some(FinPartida::CincuentaJugadas);
+    }
+    None
 }
 
 
 pub fn game_over(board: &mut Tablero) -> bool {
-    board.moves_fifty >= 100 ||
-        rey_en_mate(board) ||
-        rey_ahogado(board) ||
-        material_insuficiente(board) ||
-        repeticion_triple(board)
+    resultado_partida(board).is_some()
 }
 
 
-pub fn get_pieza(board: &mut Tablero, casilla: &str) -> String {
-    let s: String;
+// La clave zobrist de la posicion (piezas, turno, enroques y casilla al paso).
+// Dos posiciones iguales tienen la misma clave aunque se llegue por distintas
+// jugadas, asi que sirve para buscar en libros de aperturas o en tablas de motor
+pub fn clave_zobrist(board: &Tablero) -> u64 {
+    board.zobrist
+}
+
+
+// el bando que tiene que mover: defs::WHITE_TO_MOVE o defs::BLACK_TO_MOVE
+pub fn turno(board: &Tablero) -> i16 {
+    board.to_move
+}
+
+
+pub fn get_pieza(board: &mut Tablero, casilla: &str) -> Result<String, ErrorAjedrez> {
     // averiguamos si existe la casilla realmente
-    let idx1:usize;
-    let idx_temp1 = defs::SQUARE_NAMES.iter().position(|&r| r == casilla);
-    match idx_temp1 {
-        // si lo ha encontrado
-        Some(x) => idx1 = x as usize,
-        // no lo ha encontrado
-        None    => return "None".to_string(),
-    }
-    let idx_casilla = defs::CASILLAS_VALOR[idx1].indice;
-    s = pieza_char(board.board_array[idx_casilla as usize]).to_string();
-    
-    s
+    let idx_casilla = indice_casilla(casilla)?;
+    Ok(pieza_char(board.board_array[idx_casilla as usize]).to_string())
 }
 
 
+// La partida del historico del tablero en formato PGN de exportacion.
+// El resultado es "*" salvo que la partida haya terminado
 pub fn pgn(board: &mut Tablero) -> String {
-    let historico = board.history.clone();
-    if historico.len() == 0 { 
-        // no ha empezado la partida
-        println!("2873 --> No hay partida en curso");
-    }
-    let mut pgn_s: String = "".to_string();
     let mut tablero = Tablero::init();
-    let arr_jugadas: Vec<String> = Vec::new();
-    let mut num_jugada: i16 = 1;
-    let pos_inicial = format!("{}{}", historico[0].posicion, " 0 1");
-    
-    let fen = pos_inicial;
-    let fen_valida = set_fen(&fen, &mut tablero);
-    if !fen_valida{
-        return "None".to_string();
-    }
-    pgn_s.push_str("[FEN \"");
-    pgn_s.push_str(fen.as_str());
-    pgn_s.push_str("\"]\n\n");
-    
-    for movim in historico {
-        let mov = make_movim(&mut tablero, movim.mov);
-        let tablero1 = tablero.clone();
-        let v_san = crea_san(tablero1, mov);
-        // primera jugada
-        if num_jugada == 1 && mov.piece_moving < 0 {    //empiezan las negras
-            pgn_s.push_str("1. ... ");
-            pgn_s.push_str(v_san.as_str());
-            pgn_s.push_str(" ");
-            num_jugada += 1;
-            continue;
-        }
-        
-        else if num_jugada == 1 && mov.piece_moving > 0 {   // empiezan las blancas
-            pgn_s.push_str("1. ");
-            pgn_s.push_str(v_san.as_str());
-            pgn_s.push_str(" ");
-            num_jugada += 1;
-            continue;
-        }
-        // resto de jugadas
-        if num_jugada != 1 {
-            if mov.piece_moving > 0 {
-                pgn_s.push_str((num_jugada).to_string().as_str());
-                pgn_s.push_str(". ");
-                pgn_s.push_str(v_san.as_str());
-                num_jugada += 1;
-            }
-            else {
-                pgn_s.push_str(v_san.as_str());
-            }
-        }
-        pgn_s.push_str(" ");
+    // la FEN sale de un tablero valido
+    set_fen(&fen_inicial(board), &mut tablero).unwrap();
+    let mut arbol = arbol::ArbolPartida::nuevo(&mut tablero);
+    for hist in board.history.iter() {
+        arbol.anade_jugada(&mut tablero, hist.mov);
     }
-    pgn_s.push_str("\n");
     
-    pgn_s
+    let resultado = match resultado_partida(board) {
+        Some(fin) => fin.resultado(),
+        None => "*",
+    };
+    
+    let partida = partida::Partida {
+        cabeceras: Vec::new(),
+        arbol,
+        tablero,
+        resultado: resultado.to_string(),
+    };
+    partida::escribe_pgn(&partida)
+}
+
+
+// La FEN de la posicion en la que empieza el historico del tablero
+pub fn fen_inicial(board: &Tablero) -> String {
+    let mut tablero = board.clone();
+    while mueve_atras(&mut tablero).is_ok() {}
+    get_fen(&mut tablero)
 }
 
 
@@ -2972,9 +2723,56 @@ pub fn jugadas_posibles(board: &mut Tablero) -> Vec<String> {
     posibles
 }
 
+/*
+ *  Cuenta los nodos del arbol de jugadas legales hasta la profundidad dada
+ *  (perft). Sirve para comprobar el generador de jugadas contra los
+ *  numeros conocidos de las posiciones de prueba
+ *
+ *  @param board        la posicion. Se deja como estaba
+ *  @param profundidad  medias jugadas
+ *  @return u64         el numero de posiciones a esa profundidad
+*/
+pub fn perft(board: &mut Tablero, profundidad: u32) -> u64 {
+    if profundidad == 0 {
+        return 1;
+    }
+    let mut legales = ListaJugadas::init();
+    genera_jugadas(board, &mut legales);
+    if profundidad == 1 {
+        return legales.len() as u64;
+    }
+    
+    let mut nodos: u64 = 0;
+    for &movim in legales.iter() {
+        let hecha = make_movim(board, movim);
+        nodos += perft(board, profundidad - 1);
+        unmake_movim(board, hecha);
+    }
+    nodos
+}
+
+
+// El perft de cada jugada legal por separado, en UCI y ordenado,
+// para localizar en que jugada falla el generador
+pub fn divide(board: &mut Tablero, profundidad: u32) -> Vec<(String, u64)> {
+    let mut parciales: Vec<(String, u64)> = Vec::new();
+    if profundidad == 0 {
+        return parciales;
+    }
+    for movim in generate_moves(board) {
+        let hecha = make_movim(board, movim);
+        parciales.push((crea_uci(movim), perft(board, profundidad - 1)));
+        unmake_movim(board, hecha);
+    }
+    parciales.sort();
+    parciales
+}
+
+
 pub fn reset() -> Tablero {
     let mut board = Tablero::init();
-    let fen_valida = setup_inicio(&mut board);
+    // la FEN de inicio es una constante valida
+    setup_inicio(&mut board).unwrap();
     
     board
 }
\ No newline at end of file
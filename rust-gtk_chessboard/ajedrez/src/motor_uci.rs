@@ -0,0 +1,355 @@
+/***************************************************************
+*
+* Cliente del protocolo UCI
+*
+* Arranca un motor de ajedrez externo (Stockfish o cualquier otro
+* programa UCI), le envia la posicion de un Tablero y lee sus
+* respuestas. La lectura se hace en un hilo aparte que manda los
+* mensajes por un canal, de modo que quien use el motor solo tiene
+* que consultar de vez en cuando los mensajes pendientes sin bloquearse.
+*
+***************************************************************/
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Tablero, Move, defs, crea_uci, fen_inicial, mueve};
+
+
+// tiempo maximo de espera a que el motor responda a "uci" e "isready"
+const ESPERA_MOTOR: Duration = Duration::from_secs(10);
+
+
+// ========= la evaluacion de una linea ==================
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Puntuacion {
+    Centipeones(i32),       // desde el punto de vista del bando que mueve
+    Mate(i32),              // mate en n jugadas; negativo si el que mueve recibe mate
+}
+
+
+// ========= una linea "info" del motor ==================
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InfoMotor {
+    pub profundidad: Option<u32>,
+    pub puntuacion: Option<Puntuacion>,
+    pub multipv: u32,               // 1 si el motor no lo indica
+    pub nodos: Option<u64>,
+    pub nps: Option<u64>,
+    pub tiempo: Option<u64>,        // milisegundos
+    pub pv: Vec<String>,            // la variante principal en UCI
+}
+
+
+// ========= lo que puede decir el motor ==================
+#[derive(Clone, Debug, PartialEq)]
+pub enum MensajeMotor {
+    Nombre(String),                                     // id name ...
+    UciOk,
+    ReadyOk,
+    Info(InfoMotor),
+    MejorJugada { jugada: String, ponder: Option<String> },
+    Terminado,                                          // el proceso ha terminado
+}
+
+
+// ========= hasta donde tiene que buscar el motor ==================
+#[derive(Clone, Copy, Debug)]
+pub enum Limite {
+    Infinito,                       // hasta que se le mande parar
+    Tiempo(u64),                    // milisegundos por jugada
+    Profundidad(u32),
+    Reloj { blancas: u64, negras: u64, inc_blancas: u64, inc_negras: u64 },    // milisegundos
+}
+
+
+// ========= el proceso del motor ==================
+pub struct MotorUci {
+    pub nombre: String,
+    proceso: Child,
+    entrada: ChildStdin,
+    receptor: Mutex<Receiver<MensajeMotor>>,    // Mutex para poder guardar el motor en un static
+}
+
+impl MotorUci {
+    /*
+     *  Arranca el motor y espera a que termine la negociacion UCI
+     *
+     *  @param ruta             el ejecutable del motor
+     *  @return Result          el motor listo para recibir posiciones o el motivo del error
+    */
+    pub fn arranca(ruta: &Path) -> Result<MotorUci, String> {
+        let mut proceso = match Command::new(ruta)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn() {
+            Ok(proceso) => proceso,
+            Err(err) => return Err(format!("No se puede arrancar {}: {}", ruta.display(), err)),
+        };
+        let entrada = proceso.stdin.take().ok_or("No se puede escribir al motor")?;
+        let salida = proceso.stdout.take().ok_or("No se puede leer del motor")?;
+
+        let (emisor, receptor) = channel();
+        thread::spawn(move || {
+            for linea in BufReader::new(salida).lines() {
+                let linea = match linea {
+                    Ok(linea) => linea,
+                    Err(_) => break,
+                };
+                if let Some(mensaje) = lee_linea(&linea) {
+                    if emisor.send(mensaje).is_err() {
+                        // ya nadie escucha al motor
+                        return;
+                    }
+                }
+            }
+            let _ = emisor.send(MensajeMotor::Terminado);
+        });
+
+        let mut motor = MotorUci {
+            nombre: ruta.display().to_string(),
+            proceso,
+            entrada,
+            receptor: Mutex::new(receptor),
+        };
+
+        motor.envia("uci")?;
+        for mensaje in motor.espera(MensajeMotor::UciOk)? {
+            if let MensajeMotor::Nombre(nombre) = mensaje {
+                motor.nombre = nombre;
+            }
+        }
+        motor.listo()?;
+
+        Ok(motor)
+    }
+
+    // manda una orden al motor
+    pub fn envia(&mut self, orden: &str) -> Result<(), String> {
+        writeln!(self.entrada, "{}", orden)
+                    .and_then(|_| self.entrada.flush())
+                    .map_err(|err| format!("Error escribiendo al motor: {}", err))
+    }
+
+    // isready / readyok: espera a que el motor haya procesado todo lo anterior
+    pub fn listo(&mut self) -> Result<(), String> {
+        self.envia("isready")?;
+        self.espera(MensajeMotor::ReadyOk)?;
+        Ok(())
+    }
+
+    pub fn opcion(&mut self, nombre: &str, valor: &str) -> Result<(), String> {
+        self.envia(&format!("setoption name {} value {}", nombre, valor))
+    }
+
+    pub fn nueva_partida(&mut self) -> Result<(), String> {
+        self.envia("ucinewgame")?;
+        self.listo()
+    }
+
+    // la posicion del tablero: la inicial del historico y las jugadas hechas desde ella
+    pub fn posicion(&mut self, board: &Tablero) -> Result<(), String> {
+        let orden = orden_posicion(board);
+        self.envia(&orden)
+    }
+
+    // empieza a buscar en la ultima posicion enviada
+    pub fn busca(&mut self, limite: Limite) -> Result<(), String> {
+        let orden = match limite {
+            Limite::Infinito => "go infinite".to_string(),
+            Limite::Tiempo(ms) => format!("go movetime {}", ms),
+            Limite::Profundidad(prof) => format!("go depth {}", prof),
+            Limite::Reloj { blancas, negras, inc_blancas, inc_negras } =>
+                format!("go wtime {} btime {} winc {} binc {}", blancas, negras, inc_blancas, inc_negras),
+        };
+        self.envia(&orden)
+    }
+
+    // para la busqueda. El motor contestara con un bestmove
+    pub fn para(&mut self) -> Result<(), String> {
+        self.envia("stop")
+    }
+
+    // los mensajes que ha mandado el motor desde la ultima consulta. No se bloquea
+    pub fn mensajes(&self) -> Vec<MensajeMotor> {
+        let receptor = self.receptor.lock().unwrap();
+        receptor.try_iter().collect()
+    }
+
+    // espera hasta recibir el mensaje. Retorna los mensajes recibidos mientras tanto
+    fn espera(&self, esperado: MensajeMotor) -> Result<Vec<MensajeMotor>, String> {
+        let limite = Instant::now() + ESPERA_MOTOR;
+        let receptor = self.receptor.lock().unwrap();
+        let mut recibidos: Vec<MensajeMotor> = Vec::new();
+        loop {
+            let ahora = Instant::now();
+            if ahora >= limite {
+                return Err("El motor no responde".to_string());
+            }
+            match receptor.recv_timeout(limite - ahora) {
+                Ok(MensajeMotor::Terminado) | Err(RecvTimeoutError::Disconnected) =>
+                    return Err("El motor ha terminado inesperadamente".to_string()),
+                Ok(mensaje) => {
+                    if mensaje == esperado {
+                        return Ok(recibidos);
+                    }
+                    recibidos.push(mensaje);
+                },
+                Err(RecvTimeoutError::Timeout) =>
+                    return Err("El motor no responde".to_string()),
+            }
+        }
+    }
+}
+
+impl Drop for MotorUci {
+    fn drop(&mut self) {
+        let _ = self.envia("quit");
+        // le damos un momento para salir por su cuenta
+        let limite = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < limite {
+            if let Ok(Some(_)) = self.proceso.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = self.proceso.kill();
+        let _ = self.proceso.wait();
+    }
+}
+
+
+// la orden "position fen ... moves ..." para el tablero
+pub fn orden_posicion(board: &Tablero) -> String {
+    let mut orden = format!("position fen {}", fen_inicial(board));
+    if !board.history.is_empty() {
+        orden.push_str(" moves");
+        for hist in board.history.iter() {
+            orden.push(' ');
+            orden.push_str(&crea_uci(hist.movim()));
+        }
+    }
+    orden
+}
+
+
+/*
+ *  Interpreta una linea de la salida del motor
+ *
+ *  @param linea            la linea tal como la escribe el motor
+ *  @return Option          el mensaje, o None si la linea no interesa
+*/
+pub fn lee_linea(linea: &str) -> Option<MensajeMotor> {
+    let mut palabras = linea.split_whitespace();
+    match palabras.next()? {
+        "id" => {
+            if palabras.next()? != "name" {
+                return None;
+            }
+            Some(MensajeMotor::Nombre(palabras.collect::<Vec<&str>>().join(" ")))
+        },
+        "uciok" => Some(MensajeMotor::UciOk),
+        "readyok" => Some(MensajeMotor::ReadyOk),
+        "bestmove" => {
+            let jugada = palabras.next()?.to_string();
+            let ponder = match palabras.next() {
+                Some("ponder") => palabras.next().map(|p| p.to_string()),
+                _ => None,
+            };
+            Some(MensajeMotor::MejorJugada { jugada, ponder })
+        },
+        "info" => {
+            let mut info = InfoMotor { multipv: 1, ..Default::default() };
+            while let Some(clave) = palabras.next() {
+                match clave {
+                    "depth" => info.profundidad = palabras.next().and_then(|v| v.parse().ok()),
+                    "multipv" => info.multipv = palabras.next().and_then(|v| v.parse().ok()).unwrap_or(1),
+                    "nodes" => info.nodos = palabras.next().and_then(|v| v.parse().ok()),
+                    "nps" => info.nps = palabras.next().and_then(|v| v.parse().ok()),
+                    "time" => info.tiempo = palabras.next().and_then(|v| v.parse().ok()),
+                    "score" => {
+                        let tipo = palabras.next();
+                        let valor = palabras.next().and_then(|v| v.parse::<i32>().ok());
+                        info.puntuacion = match (tipo, valor) {
+                            (Some("cp"), Some(v)) => Some(Puntuacion::Centipeones(v)),
+                            (Some("mate"), Some(v)) => Some(Puntuacion::Mate(v)),
+                            _ => None,
+                        };
+                    },
+                    "pv" => {
+                        // la variante ocupa el resto de la linea
+                        info.pv = palabras.by_ref().map(|j| j.to_string()).collect();
+                    },
+                    "string" => break,
+                    _ => (),
+                }
+            }
+            // las lineas info sin variante (currmove, hashfull...) no nos interesan
+            if info.pv.is_empty() {
+                return None;
+            }
+            Some(MensajeMotor::Info(info))
+        },
+        _ => None,
+    }
+}
+
+
+/*
+ *  Convierte una variante en UCI a SAN, jugandola sobre una copia del tablero
+ *
+ *  @param board            la posicion desde la que empieza la variante
+ *  @param pv               las jugadas UCI (e2e4, e7e8q...)
+ *  @return Vec             las jugadas en SAN, hasta la primera que no sea legal
+*/
+pub fn pv_a_san(board: &Tablero, pv: &[String]) -> Vec<String> {
+    let mut tablero = board.clone();
+    let mut sans: Vec<String> = Vec::new();
+    for jugada in pv.iter() {
+        let hecha = Move::from_uci(&tablero, jugada).and_then(|jug| mueve(&mut tablero, jug));
+        match hecha {
+            Ok(result) => sans.push(result.san),
+            Err(_) => break,
+        }
+    }
+    sans
+}
+
+
+// la variante en SAN con los numeros de jugada: "12... Nf6 13. Bg5 Be7"
+pub fn texto_variante(board: &Tablero, pv: &[String]) -> String {
+    let mut texto = "".to_string();
+    let mut num_jugada = board.moves_full;
+    let mut blancas = board.to_move == defs::WHITE_TO_MOVE;
+    for (i, san) in pv_a_san(board, pv).iter().enumerate() {
+        if blancas {
+            texto.push_str(&format!("{}. ", num_jugada));
+        }
+        else if i == 0 {
+            texto.push_str(&format!("{}... ", num_jugada));
+        }
+        texto.push_str(san);
+        texto.push(' ');
+        if !blancas {
+            num_jugada += 1;
+        }
+        blancas = !blancas;
+    }
+    texto.trim_end().to_string()
+}
+
+
+// la puntuacion desde el punto de vista de las blancas, como "+0.31" o "#-3"
+pub fn texto_puntuacion(board: &Tablero, puntuacion: Puntuacion) -> String {
+    let signo = if board.to_move == defs::WHITE_TO_MOVE { 1 } else { -1 };
+    match puntuacion {
+        Puntuacion::Centipeones(cp) => format!("{:+.2}", (cp * signo) as f64 / 100.0),
+        Puntuacion::Mate(n) => format!("#{}", n * signo),
+    }
+}
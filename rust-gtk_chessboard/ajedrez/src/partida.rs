@@ -0,0 +1,472 @@
+/***************************************************************
+*
+* Lectura y escritura de partidas en formato PGN
+*
+* Se procesa la seccion de cabeceras (Seven Tag Roster, [FEN] y [SetUp])
+* y el texto de jugadas (numeros de jugada, comentarios, NAGs, variantes
+* y resultado), reproduciendo cada jugada SAN sobre un Tablero con mueve_san
+* y guardandola en el arbol de la partida.
+* La escritura sigue el formato de exportacion: Seven Tag Roster,
+* lineas de como mucho 80 caracteres y marca de terminacion.
+* Si la partida no trae las cabeceras ECO y Opening se rellenan con
+* la apertura de la linea principal
+*
+***************************************************************/
+
+use super::{Tablero, setup_inicio, set_fen, mueve_san};
+use super::defs;
+use super::arbol::{ArbolPartida, nag_de_sufijo, extrae_marcas};
+use super::eco;
+
+
+// los posibles resultados / marcas de terminacion de la partida
+pub const RESULTADOS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// las cabeceras obligatorias, en el orden en que se exportan, y su valor si no se conocen
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"), ("Site", "?"), ("Date", "????.??.??"), ("Round", "?"),
+    ("White", "?"), ("Black", "?"), ("Result", "*"),
+];
+
+// longitud maxima de las lineas del texto de las jugadas
+const MAX_LINEA: usize = 80;
+
+
+// ========= la estructura de la partida ==================
+#[derive(Clone)]
+pub struct Partida {
+    pub cabeceras: Vec<(String, String)>,       // las cabeceras en el orden del fichero
+    pub arbol: ArbolPartida,                    // las jugadas, con sus variantes y comentarios
+    pub tablero: Tablero,                       // la posicion del nodo actual del arbol
+                                                // (el final de la linea principal)
+    pub resultado: String,                      // "1-0", "0-1", "1/2-1/2" o "*"
+}
+
+impl Partida {
+    pub fn cabecera(&self, nombre: &str) -> Option<&str> {
+        for (clave, valor) in self.cabeceras.iter() {
+            if clave == nombre {
+                return Some(valor.as_str());
+            }
+        }
+        None
+    }
+}
+
+
+/*
+ *  Convierte el texto de una partida PGN en una Partida
+ *
+ *  @param texto            el texto PGN de una sola partida
+ *  @return Result          la partida, o el motivo por el que no se ha podido leer
+*/
+pub fn lee_pgn(texto: &str) -> Result<Partida, String> {
+    let (cabeceras, texto_jugadas) = separa_cabeceras(texto)?;
+
+    let mut tablero = Tablero::init();
+
+    // la posicion inicial: si hay una etiqueta FEN la usamos,
+    // salvo que SetUp diga expresamente que no
+    let mut fen: Option<&str> = None;
+    let mut setup = true;
+    for (clave, valor) in cabeceras.iter() {
+        if clave == "FEN" {
+            fen = Some(valor.as_str());
+        }
+        if clave == "SetUp" && valor.trim() == "0" {
+            setup = false;
+        }
+    }
+
+    match fen {
+        Some(fen) if setup => {
+            if let Err(e) = set_fen(fen, &mut tablero) {
+                return Err(format!("{} ({})", e, fen));
+            }
+        },
+        _ => {
+            if let Err(e) = setup_inicio(&mut tablero) {
+                return Err(e.to_string());
+            }
+        },
+    }
+
+    let arbol = ArbolPartida::nuevo(&mut tablero);
+    let mut partida = Partida {
+        cabeceras,
+        arbol,
+        tablero,
+        resultado: "*".to_string(),
+    };
+
+    let mut pila: Vec<usize> = Vec::new();      // el nodo al que volver al cerrar cada variante
+    let mut inicio_variante = false;            // acabamos de abrir una variante
+    let mut previo = "".to_string();            // comentario antes de la primera jugada de una variante
+
+    for token in tokeniza_jugadas(&texto_jugadas)? {
+        match token {
+            Token::Comentario(txt) => {
+                // las flechas y circulos son de la posicion en la que esta el arbol,
+                // tambien en el comentario de antes de una variante
+                let (txt, flechas, circulos) = extrae_marcas(&txt);
+                let actual = partida.arbol.actual;
+                partida.arbol.nodos[actual].flechas.extend(flechas);
+                partida.arbol.nodos[actual].circulos.extend(circulos);
+                if txt.is_empty() {
+                    continue;
+                }
+                let comentario = if inicio_variante {
+                    &mut previo
+                }
+                else {
+                    let actual = partida.arbol.actual;
+                    &mut partida.arbol.nodos[actual].comentario
+                };
+                if !comentario.is_empty() {
+                    comentario.push(' ');
+                }
+                comentario.push_str(&txt);
+            },
+            Token::Nag(nag) => {
+                let actual = partida.arbol.actual;
+                if actual != 0 {
+                    partida.arbol.nodos[actual].nags.push(nag);
+                }
+            },
+            Token::AbreVariante => {
+                // la variante es una alternativa a la ultima jugada
+                let actual = partida.arbol.actual;
+                if actual == 0 {
+                    return Err("Variante antes de la primera jugada".to_string());
+                }
+                pila.push(actual);
+                partida.arbol.retrocede(&mut partida.tablero);
+                inicio_variante = true;
+                previo.clear();
+            },
+            Token::CierraVariante => {
+                if let Some(nodo) = pila.pop() {
+                    partida.arbol.ve_a(&mut partida.tablero, nodo);
+                }
+                inicio_variante = false;
+            },
+            Token::Resultado(res) => {
+                // algunos programas ponen marcas de resultado dentro de las variantes
+                if pila.is_empty() {
+                    partida.resultado = res;
+                    break;
+                }
+            },
+            Token::Jugada(san) => {
+                if let Err(e) = mueve_san(&mut partida.tablero, &san) {
+                    return Err(format!("{} (jugada {})", e, partida.tablero.moves_full));
+                }
+                let nodo = partida.arbol.anade_hecha(&partida.tablero);
+                if inicio_variante && !previo.is_empty() {
+                    partida.arbol.nodos[nodo].comentario_previo = previo.clone();
+                }
+                inicio_variante = false;
+            },
+        }
+    }
+
+    // dejamos el tablero al final de la linea principal
+    let ultimo = partida.arbol.final_linea(0);
+    partida.arbol.ve_a(&mut partida.tablero, ultimo);
+
+    // si no hay marca de terminacion en el texto usamos la de la cabecera
+    if partida.resultado == "*" {
+        if let Some(res) = partida.cabecera("Result") {
+            if RESULTADOS.contains(&res) {
+                partida.resultado = res.to_string();
+            }
+        }
+    }
+
+    Ok(partida)
+}
+
+
+/*
+ *  Escribe la partida en formato PGN de exportacion
+ *
+ *  @param partida          la partida con sus cabeceras, variantes y resultado
+ *  @return String          el texto PGN, terminado en una linea en blanco
+*/
+pub fn escribe_pgn(partida: &Partida) -> String {
+    let mut texto = "".to_string();
+
+    // el Seven Tag Roster, siempre y en este orden
+    for (clave, defecto) in SEVEN_TAG_ROSTER.iter() {
+        let valor = match *clave {
+            "Result" => partida.resultado.as_str(),
+            _ => partida.cabecera(clave).unwrap_or(defecto),
+        };
+        texto.push_str(&escribe_cabecera(clave, valor));
+    }
+
+    // la posicion inicial solo si no es la normal
+    let inicio_normal = partida.arbol.fen_inicial == defs::FEN_INICIO;
+    if !inicio_normal {
+        texto.push_str(&escribe_cabecera("SetUp", "1"));
+        texto.push_str(&escribe_cabecera("FEN", &partida.arbol.fen_inicial));
+    }
+
+    // el resto de cabeceras en el orden en que estaban, con la apertura si falta
+    let mut cabeceras = partida.cabeceras.clone();
+    if let Some(apertura) = eco::clasifica_linea(&partida.arbol, partida.arbol.final_linea(0)) {
+        completa_cabecera(&mut cabeceras, "ECO", &apertura.eco);
+        completa_cabecera(&mut cabeceras, "Opening", &apertura.nombre);
+    }
+    for (clave, valor) in cabeceras.iter() {
+        if clave == "FEN" || clave == "SetUp" ||
+                    SEVEN_TAG_ROSTER.iter().any(|(c, _)| c == clave) {
+            continue;
+        }
+        texto.push_str(&escribe_cabecera(clave, valor));
+    }
+    texto.push('\n');
+
+    // las jugadas, partiendo las lineas en los espacios
+    let mut simbolos = partida.arbol.simbolos_pgn();
+    simbolos.push(partida.resultado.clone());
+    let mut linea = "".to_string();
+    for simbolo in simbolos.iter() {
+        // los comentarios pueden partirse entre palabras
+        for palabra in simbolo.split_whitespace() {
+            if !linea.is_empty() && linea.len() + 1 + palabra.len() >= MAX_LINEA {
+                texto.push_str(&linea);
+                texto.push('\n');
+                linea.clear();
+            }
+            if !linea.is_empty() {
+                linea.push(' ');
+            }
+            linea.push_str(palabra);
+        }
+    }
+    texto.push_str(&linea);
+    texto.push_str("\n\n");
+
+    texto
+}
+
+
+// pone el valor a la cabecera si no la hay o esta vacia ("" o "?")
+fn completa_cabecera(cabeceras: &mut Vec<(String, String)>, clave: &str, valor: &str) {
+    match cabeceras.iter_mut().find(|(c, _)| c == clave) {
+        Some((_, actual)) => {
+            if actual.is_empty() || actual == "?" {
+                *actual = valor.to_string();
+            }
+        },
+        None => cabeceras.push((clave.to_string(), valor.to_string())),
+    }
+}
+
+
+// una linea [Clave "valor"], escapando las comillas y las barras del valor
+fn escribe_cabecera(clave: &str, valor: &str) -> String {
+    let valor = valor.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("[{} \"{}\"]\n", clave, valor)
+}
+
+
+/*
+ *  Lee una linea de cabecera del tipo [Clave "valor"]
+ *
+ *  @param linea            la linea sin espacios al principio ni al final
+ *  @return Option          la clave y el valor (sin escapes), None si la linea no es una cabecera
+*/
+pub fn lee_cabecera(linea: &str) -> Option<(String, String)> {
+    if !linea.starts_with('[') || !linea.ends_with(']') {
+        return None;
+    }
+    let interior = linea[1..linea.len() - 1].trim();
+    let fin_clave = interior.find(|c: char| c.is_whitespace())?;
+    let clave = &interior[..fin_clave];
+    if clave.is_empty() || !clave.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let resto = interior[fin_clave..].trim();
+    if resto.len() < 2 || !resto.starts_with('"') || !resto.ends_with('"') {
+        return None;
+    }
+
+    // quitamos los escapes \" y \\ del valor
+    let mut valor = "".to_string();
+    let mut escape = false;
+    for c in resto[1..resto.len() - 1].chars() {
+        if escape {
+            valor.push(c);
+            escape = false;
+        }
+        else if c == '\\' {
+            escape = true;
+        }
+        else {
+            valor.push(c);
+        }
+    }
+
+    Some((clave.to_string(), valor))
+}
+
+
+// separa las cabeceras [Clave "valor"] del texto de las jugadas
+fn separa_cabeceras(texto: &str) -> Result<(Vec<(String, String)>, String), String> {
+    let mut cabeceras: Vec<(String, String)> = Vec::new();
+    let mut texto_jugadas = "".to_string();
+    let mut en_cabeceras = true;
+
+    for linea in texto.lines() {
+        let linea = linea.trim();
+        // lineas de escape del formato de exportacion
+        if linea.starts_with('%') {
+            continue;
+        }
+        if en_cabeceras {
+            if linea.is_empty() {
+                continue;
+            }
+            if linea.starts_with('[') {
+                match lee_cabecera(linea) {
+                    Some(cabecera) => cabeceras.push(cabecera),
+                    None => return Err(format!("Cabecera PGN mal formada: {}", linea)),
+                }
+                continue;
+            }
+            en_cabeceras = false;
+        }
+        texto_jugadas.push_str(linea);
+        texto_jugadas.push('\n');
+    }
+
+    Ok((cabeceras, texto_jugadas))
+}
+
+
+enum Token {
+    Jugada(String),
+    Comentario(String),
+    Nag(u8),
+    AbreVariante,
+    CierraVariante,
+    Resultado(String),
+}
+
+
+// divide el texto de las jugadas en jugadas SAN, comentarios, NAGs, variantes y resultado
+// Se descartan los numeros de jugada. Los sufijos !, ?, !? ... se convierten en NAGs
+fn tokeniza_jugadas(texto: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let car: Vec<char> = texto.chars().collect();
+    let mut nivel_variante = 0;
+    let mut i: usize = 0;
+
+    while i < car.len() {
+        let c = car[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        }
+        else if c == '{' {
+            // comentario hasta la llave de cierre
+            let mut txt = "".to_string();
+            i += 1;
+            while i < car.len() && car[i] != '}' {
+                txt.push(car[i]);
+                i += 1;
+            }
+            if i >= car.len() {
+                return Err("Comentario sin cerrar en el texto de la partida".to_string());
+            }
+            i += 1;
+            let txt = txt.split_whitespace().collect::<Vec<&str>>().join(" ");
+            tokens.push(Token::Comentario(txt));
+        }
+        else if c == ';' {
+            // comentario hasta el final de la linea
+            let mut txt = "".to_string();
+            i += 1;
+            while i < car.len() && car[i] != '\n' {
+                txt.push(car[i]);
+                i += 1;
+            }
+            tokens.push(Token::Comentario(txt.trim().to_string()));
+        }
+        else if c == '(' {
+            nivel_variante += 1;
+            tokens.push(Token::AbreVariante);
+            i += 1;
+        }
+        else if c == ')' {
+            if nivel_variante == 0 {
+                return Err("Paréntesis de cierre sin variante abierta".to_string());
+            }
+            nivel_variante -= 1;
+            tokens.push(Token::CierraVariante);
+            i += 1;
+        }
+        else {
+            // un simbolo: numero de jugada, NAG, resultado o jugada SAN
+            let mut simbolo = "".to_string();
+            while i < car.len() && !car[i].is_whitespace() &&
+                        !"{};()".contains(car[i]) {
+                simbolo.push(car[i]);
+                i += 1;
+            }
+            if let Some(numero) = simbolo.strip_prefix('$') {
+                match numero.parse::<u8>() {
+                    Ok(nag) => tokens.push(Token::Nag(nag)),
+                    Err(_) => return Err(format!("NAG no válido: {}", simbolo)),
+                }
+                continue;
+            }
+            if let Some(nag) = nag_de_sufijo(&simbolo) {
+                // anotacion separada de la jugada por un espacio
+                tokens.push(Token::Nag(nag));
+                continue;
+            }
+            if RESULTADOS.contains(&simbolo.as_str()) {
+                tokens.push(Token::Resultado(simbolo));
+                continue;
+            }
+            // quitamos el numero de jugada, que puede ir pegado a la jugada: "1.e4", "12...Nf6".
+            // Solo son numero las cifras seguidas de puntos: "0-0" es un enroque
+            let jugada = match simbolo.find(|c: char| !c.is_ascii_digit()) {
+                Some(i) if simbolo[i..].starts_with('.') => simbolo[i..].trim_start_matches('.'),
+                Some(_) => simbolo.as_str(),
+                None => "",
+            };
+            // "e.p." detras de una captura al paso no forma parte de la jugada
+            if jugada.is_empty() || simbolo == "e.p." {
+                continue;
+            }
+            // algunos programas escriben los enroques con ceros. El largo va
+            // primero para que "0-0-0" no se quede en "O-O-0"
+            let jugada = if let Some(resto) = jugada.strip_prefix("0-0-0") {
+                format!("O-O-O{}", resto)
+            }
+            else if let Some(resto) = jugada.strip_prefix("0-0") {
+                format!("O-O{}", resto)
+            }
+            else {
+                jugada.to_string()
+            };
+            // la anotacion pegada a la jugada: "e4!", "Nf3?!"
+            let sin_sufijo = jugada.trim_end_matches(['!', '?']);
+            let sufijo = jugada[sin_sufijo.len()..].to_string();
+            tokens.push(Token::Jugada(sin_sufijo.to_string()));
+            if let Some(nag) = nag_de_sufijo(&sufijo) {
+                tokens.push(Token::Nag(nag));
+            }
+        }
+    }
+
+    if nivel_variante != 0 {
+        return Err("Variante sin cerrar en el texto de la partida".to_string());
+    }
+
+    Ok(tokens)
+}
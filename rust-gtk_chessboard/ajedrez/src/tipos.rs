@@ -0,0 +1,390 @@
+/***************************************************************
+*
+* Los tipos publicos de la libreria: casillas, piezas, bandos
+* y jugadas.
+*
+* Por dentro el tablero sigue trabajando con los codigos i16 de
+* defs.rs y los indices 0x88; estos tipos son la cara publica,
+* para que quien use la libreria no pueda confundir una casilla
+* con una pieza o el turno con el tipo de jugada.
+*
+***************************************************************/
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{Tablero, Movim, ErrorAjedrez, defs, generate_moves, crea_san, make_movim};
+
+
+// ========= el bando ==================
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    // a partir de defs::WHITE_TO_MOVE / BLACK_TO_MOVE, o del signo de una pieza
+    pub fn from_i16(bando: i16) -> Color {
+        if bando > 0 { Color::White } else { Color::Black }
+    }
+
+    pub fn to_i16(self) -> i16 {
+        match self {
+            Color::White => defs::WHITE_TO_MOVE,
+            Color::Black => defs::BLACK_TO_MOVE,
+        }
+    }
+
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    // 'w' o 'b', como en la FEN
+    pub fn fen_char(self) -> char {
+        match self {
+            Color::White => 'w',
+            Color::Black => 'b',
+        }
+    }
+
+    pub fn from_fen_char(c: char) -> Option<Color> {
+        match c {
+            'w' => Some(Color::White),
+            'b' => Some(Color::Black),
+            _ => None,
+        }
+    }
+}
+
+
+// ========= el tipo de pieza, sin color ==================
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PieceKind {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+}
+
+impl PieceKind {
+    // el codigo de la pieza blanca de defs.rs
+    pub fn code(self) -> i16 {
+        match self {
+            PieceKind::King => defs::W_KING,
+            PieceKind::Queen => defs::W_QUEEN,
+            PieceKind::Rook => defs::W_ROOK,
+            PieceKind::Bishop => defs::W_BISHOP,
+            PieceKind::Knight => defs::W_KNIGHT,
+            PieceKind::Pawn => defs::W_PAWN,
+        }
+    }
+
+    // vale tanto el codigo de la pieza blanca como el de la negra
+    pub fn from_code(codigo: i16) -> Option<PieceKind> {
+        match codigo.abs() {
+            defs::W_KING => Some(PieceKind::King),
+            defs::W_QUEEN => Some(PieceKind::Queen),
+            defs::W_ROOK => Some(PieceKind::Rook),
+            defs::W_BISHOP => Some(PieceKind::Bishop),
+            defs::W_KNIGHT => Some(PieceKind::Knight),
+            defs::W_PAWN => Some(PieceKind::Pawn),
+            _ => None,
+        }
+    }
+
+    // la letra en mayusculas: K, Q, R, B, N o P
+    pub fn letter(self) -> char {
+        match self {
+            PieceKind::King => 'K',
+            PieceKind::Queen => 'Q',
+            PieceKind::Rook => 'R',
+            PieceKind::Bishop => 'B',
+            PieceKind::Knight => 'N',
+            PieceKind::Pawn => 'P',
+        }
+    }
+
+    // acepta mayusculas y minusculas
+    pub fn from_letter(c: char) -> Option<PieceKind> {
+        match c.to_ascii_uppercase() {
+            'K' => Some(PieceKind::King),
+            'Q' => Some(PieceKind::Queen),
+            'R' => Some(PieceKind::Rook),
+            'B' => Some(PieceKind::Bishop),
+            'N' => Some(PieceKind::Knight),
+            'P' => Some(PieceKind::Pawn),
+            _ => None,
+        }
+    }
+}
+
+
+// ========= una pieza ==================
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Piece {
+    pub color: Color,
+    pub kind: PieceKind,
+}
+
+impl Piece {
+    pub fn new(color: Color, kind: PieceKind) -> Piece {
+        Piece { color, kind }
+    }
+
+    // None si el codigo es el de una casilla vacia
+    pub fn from_code(codigo: i16) -> Option<Piece> {
+        PieceKind::from_code(codigo).map(|kind| Piece::new(Color::from_i16(codigo), kind))
+    }
+
+    // el codigo de defs.rs: positivo para las blancas, negativo para las negras
+    pub fn code(self) -> i16 {
+        self.kind.code() * self.color.to_i16()
+    }
+
+    // la letra de la FEN: mayusculas las blancas, minusculas las negras
+    pub fn fen_char(self) -> char {
+        match self.color {
+            Color::White => self.kind.letter(),
+            Color::Black => self.kind.letter().to_ascii_lowercase(),
+        }
+    }
+
+    pub fn from_fen_char(c: char) -> Option<Piece> {
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        PieceKind::from_letter(c).map(|kind| Piece::new(color, kind))
+    }
+}
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.fen_char())
+    }
+}
+
+
+// ========= una casilla del tablero ==================
+// Guarda el indice 0x88, asi que siempre es una casilla real
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Square(u8);
+
+impl Square {
+    // columna y fila de 0 a 7 (a1 es 0, 0)
+    pub fn new(file: u8, rank: u8) -> Option<Square> {
+        if file < 8 && rank < 8 {
+            Some(Square(rank * 16 + file))
+        }
+        else {
+            None
+        }
+    }
+
+    // None si el indice cae fuera del tablero
+    pub fn from_088(indice: usize) -> Option<Square> {
+        if indice < 128 && indice & 0x88 == 0 {
+            Some(Square(indice as u8))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn to_088(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> u8 {
+        self.0 & 7
+    }
+
+    pub fn rank(self) -> u8 {
+        self.0 >> 4
+    }
+
+    // "e4" -> e4
+    pub fn from_algebraic(casilla: &str) -> Result<Square, ErrorAjedrez> {
+        let letras = casilla.as_bytes();
+        if letras.len() == 2 && (b'a'..=b'h').contains(&letras[0]) && (b'1'..=b'8').contains(&letras[1]) {
+            return Ok(Square((letras[1] - b'1') * 16 + (letras[0] - b'a')));
+        }
+        Err(ErrorAjedrez::CasillaDesconocida(casilla.to_string()))
+    }
+
+    pub fn algebraic(self) -> String {
+        format!("{}{}", (b'a' + self.file()) as char, (b'1' + self.rank()) as char)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.algebraic())
+    }
+}
+
+impl FromStr for Square {
+    type Err = ErrorAjedrez;
+
+    fn from_str(casilla: &str) -> Result<Square, ErrorAjedrez> {
+        Square::from_algebraic(casilla)
+    }
+}
+
+
+// ========= el tipo de jugada ==================
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum MoveKind {
+    Normal,
+    ShortCastle,
+    LongCastle,
+    EnPassant,
+    Promotion(PieceKind),
+}
+
+impl MoveKind {
+    // a partir del move_type de defs.rs
+    pub fn from_code(tipo: i16) -> Option<MoveKind> {
+        match tipo {
+            defs::ORDINARY_MOVE => Some(MoveKind::Normal),
+            defs::SHORT_CASTLE => Some(MoveKind::ShortCastle),
+            defs::LONG_CASTLE => Some(MoveKind::LongCastle),
+            defs::EN_PASSANT => Some(MoveKind::EnPassant),
+            defs::PROMOTION_QUEEN => Some(MoveKind::Promotion(PieceKind::Queen)),
+            defs::PROMOTION_ROOK => Some(MoveKind::Promotion(PieceKind::Rook)),
+            defs::PROMOTION_BISHOP => Some(MoveKind::Promotion(PieceKind::Bishop)),
+            defs::PROMOTION_KNIGHT => Some(MoveKind::Promotion(PieceKind::Knight)),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> i16 {
+        match self {
+            MoveKind::Normal => defs::ORDINARY_MOVE,
+            MoveKind::ShortCastle => defs::SHORT_CASTLE,
+            MoveKind::LongCastle => defs::LONG_CASTLE,
+            MoveKind::EnPassant => defs::EN_PASSANT,
+            MoveKind::Promotion(PieceKind::Rook) => defs::PROMOTION_ROOK,
+            MoveKind::Promotion(PieceKind::Bishop) => defs::PROMOTION_BISHOP,
+            MoveKind::Promotion(PieceKind::Knight) => defs::PROMOTION_KNIGHT,
+            MoveKind::Promotion(_) => defs::PROMOTION_QUEEN,
+        }
+    }
+
+    // la pieza a la que se corona, si es una coronacion
+    pub fn promotion(self) -> Option<PieceKind> {
+        match self {
+            MoveKind::Promotion(pieza) => Some(pieza),
+            _ => None,
+        }
+    }
+}
+
+
+// ========= una jugada ==================
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Move {
+    pub piece: Piece,               // la pieza que se mueve
+    pub from: Square,
+    pub to: Square,
+    pub captured: Option<Piece>,
+    pub kind: MoveKind,
+}
+
+impl Move {
+    // None para la jugada vacia (la raiz del arbol, el historico vacio...)
+    pub fn from_movim(movim: Movim) -> Option<Move> {
+        Some(Move {
+            piece: Piece::from_code(movim.piece_moving)?,
+            from: Square::from_088(movim.from_index)?,
+            to: Square::from_088(movim.to_index)?,
+            captured: Piece::from_code(movim.capture),
+            kind: MoveKind::from_code(movim.move_type)?,
+        })
+    }
+
+    // la jugada del generador que corresponde a esta, si es legal en el tablero
+    pub(crate) fn movim(&self, board: &Tablero) -> Result<Movim, ErrorAjedrez> {
+        generate_moves(board).into_iter()
+            .find(|m| m.from_index == self.from.to_088() &&
+                        m.to_index == self.to.to_088() &&
+                        m.move_type == self.kind.code())
+            .ok_or_else(|| ErrorAjedrez::JugadaIlegal(self.uci()))
+    }
+
+    // la jugada legal del tablero que corresponde a la UCI ("e2e4", "e7e8q")
+    pub fn from_uci(board: &Tablero, uci: &str) -> Result<Move, ErrorAjedrez> {
+        if uci.len() < 4 || uci.len() > 5 || !uci.is_ascii() {
+            return Err(ErrorAjedrez::UciMalFormada(uci.to_string()));
+        }
+        let desde = Square::from_algebraic(&uci[0..2])?;
+        let hasta = Square::from_algebraic(&uci[2..4])?;
+        let promocion = match uci[4..].chars().next() {
+            Some(c) => match PieceKind::from_letter(c) {
+                Some(pieza) if pieza != PieceKind::King && pieza != PieceKind::Pawn => Some(pieza),
+                _ => return Err(ErrorAjedrez::UciMalFormada(uci.to_string())),
+            },
+            None => None,
+        };
+
+        generate_moves(board).into_iter()
+            .filter(|m| m.from_index == desde.to_088() && m.to_index == hasta.to_088())
+            .filter_map(Move::from_movim)
+            .find(|m| m.kind.promotion() == promocion)
+            .ok_or_else(|| ErrorAjedrez::JugadaIlegal(uci.to_string()))
+    }
+
+    // la jugada legal del tablero que corresponde a la SAN
+    pub fn from_san(board: &Tablero, san: &str) -> Result<Move, ErrorAjedrez> {
+        let movim = super::move_from_san(board, san)?;
+        Move::from_movim(movim).ok_or_else(|| ErrorAjedrez::JugadaIlegal(san.to_string()))
+    }
+
+    pub fn uci(&self) -> String {
+        let mut uci = format!("{}{}", self.from, self.to);
+        if let Some(pieza) = self.kind.promotion() {
+            uci.push(pieza.letter().to_ascii_lowercase());
+        }
+        uci
+    }
+
+    // la SAN de la jugada en el tablero, que tiene que estar en la posicion de antes de hacerla
+    pub fn san(&self, board: &Tablero) -> Result<String, ErrorAjedrez> {
+        let movim = self.movim(board)?;
+        let mut tablero = board.clone();
+        make_movim(&mut tablero, movim);
+        Ok(crea_san(&mut tablero, movim))
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.uci())
+    }
+}
+
+
+// ========= el resultado de hacer una jugada ==================
+#[derive(Clone, PartialEq, Debug)]
+pub struct MoveResult {
+    pub mov: Move,
+    pub san: String,
+}
+
+impl MoveResult {
+    pub(crate) fn from_movim(movim: Movim, san: &str) -> Option<MoveResult> {
+        Move::from_movim(movim).map(|mov| MoveResult { mov, san: san.to_string() })
+    }
+
+    pub fn uci(&self) -> String {
+        self.mov.uci()
+    }
+
+    // el bando que ha hecho la jugada
+    pub fn color(&self) -> Color {
+        self.mov.piece.color
+    }
+}
@@ -0,0 +1,99 @@
+/***************************************************************
+*
+* Claves Zobrist de las posiciones
+*
+* Cada posicion se resume en un numero de 64 bits: el xor de una
+* clave aleatoria por cada pieza en su casilla, por cada derecho
+* de enroque, por la columna de la casilla al paso (solo si algun
+* peon puede capturar al paso, como en Polyglot) y por el turno
+* cuando juegan las negras. make_movim y unmake_movim la mantienen
+* al dia quitando y poniendo solo lo que cambia.
+*
+* Las claves se generan al compilar con splitmix64 desde una
+* semilla fija, asi que una posicion tiene siempre la misma clave.
+*
+***************************************************************/
+
+use super::{Tablero, defs};
+
+
+const ENROQUES: usize = 12 * 64;        // K, Q, k, q
+const AL_PASO: usize = ENROQUES + 4;    // una por columna
+const TURNO: usize = AL_PASO + 8;       // juegan las negras
+const NUM_CLAVES: usize = TURNO + 1;
+
+const CLAVES: [u64; NUM_CLAVES] = genera_claves(0x2545_F491_4F6C_DD1D);
+
+
+const fn genera_claves(semilla: u64) -> [u64; NUM_CLAVES] {
+    let mut claves = [0u64; NUM_CLAVES];
+    let mut estado = semilla;
+    let mut i = 0;
+    while i < NUM_CLAVES {
+        // splitmix64
+        estado = estado.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = estado;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        claves[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    claves
+}
+
+
+// la clave de una pieza (con el codigo de defs) en una casilla 0x88.
+// Una casilla vacia no cambia la clave
+pub(crate) fn pieza(pieza: i16, casilla: usize) -> u64 {
+    if pieza == defs::EMPTY_SQUARE {
+        return 0;
+    }
+    // de 0 a 5 las piezas blancas y de 6 a 11 las negras
+    let tipo = if pieza > 0 { pieza - 1 } else { 5 - pieza } as usize;
+    let casilla64 = (casilla >> 4) * 8 + (casilla & 7);
+    CLAVES[tipo * 64 + casilla64]
+}
+
+
+// la parte de la clave que no son las piezas: turno, enroques y al paso.
+// Como el al paso depende de los peones, se calcula con las piezas de la
+// misma posicion que el resto del estado
+pub(crate) fn estado(board: &Tablero) -> u64 {
+    let mut clave = 0;
+    if board.to_move == defs::BLACK_TO_MOVE {
+        clave ^= CLAVES[TURNO];
+    }
+    // CASTLE_SHORT es el bit 0 y CASTLE_LONG el bit 1
+    for bit in 0..2 {
+        if board.white_castle & (1 << bit) != 0 {
+            clave ^= CLAVES[ENROQUES + bit];
+        }
+        if board.black_castle & (1 << bit) != 0 {
+            clave ^= CLAVES[ENROQUES + 2 + bit];
+        }
+    }
+    // la casilla al paso solo cuenta si se puede capturar: si no, la
+    // posicion es la misma que sin ella y se repite igual
+    if board.en_passant != -1 {
+        let peon = board.en_passant - 16 * board.to_move;
+        let propio = defs::W_PAWN * board.to_move;
+        let captura = [peon - 1, peon + 1].iter()
+                    .any(|&c| c & 0x88 == 0 && board.board_array[c as usize] == propio);
+        if captura {
+            clave ^= CLAVES[AL_PASO + (board.en_passant & 7) as usize];
+        }
+    }
+    clave
+}
+
+
+// la clave completa, calculada desde cero
+pub(crate) fn calcula(board: &Tablero) -> u64 {
+    let mut clave = estado(board);
+    for casilla in 0..128 {
+        if casilla & 0x88 == 0 {
+            clave ^= pieza(board.board_array[casilla], casilla);
+        }
+    }
+    clave
+}
@@ -0,0 +1,62 @@
+/***************************************************************
+*
+* Comprobacion de la clasificacion ECO
+*
+***************************************************************/
+
+use ajedrez::{reset, mueve_san};
+use ajedrez::eco::{aperturas, clasifica, clasifica_linea};
+use ajedrez::partida::{lee_pgn, escribe_pgn};
+
+
+#[test]
+fn la_tabla_es_legal() {
+    // las lineas con jugadas ilegales se descartan al leer la tabla
+    let lineas = include_str!("../src/eco.txt").lines()
+                .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+                .count();
+    assert_eq!(aperturas().len(), lineas);
+    for apertura in aperturas() {
+        let mut board = reset();
+        for san in apertura.jugadas.split_whitespace().filter(|s| !s.ends_with('.')) {
+            mueve_san(&mut board, san).unwrap();
+        }
+        assert!(clasifica(&board).is_some(), "{}", apertura);
+    }
+}
+
+#[test]
+fn reconoce_las_transposiciones() {
+    let mut board = reset();
+    for san in ["d4", "Nf6", "c4", "e6", "Nc3", "d5", "cxd5"].iter() {
+        mueve_san(&mut board, san).unwrap();
+    }
+    assert_eq!(clasifica(&board).unwrap().to_string(),
+               "D35 Queen's Gambit Declined: Exchange Variation");
+}
+
+#[test]
+fn clasifica_la_linea_hasta_el_nodo() {
+    let partida = lee_pgn("1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6 6. h3 e5 *").unwrap();
+    let arbol = &partida.arbol;
+    let fin = arbol.final_linea(0);
+    assert_eq!(clasifica_linea(arbol, fin).unwrap().eco, "B90");
+    // a mitad de la partida
+    let nodo = arbol.linea_principal()[2];
+    assert_eq!(clasifica_linea(arbol, nodo).unwrap().eco, "B27");
+    assert!(clasifica_linea(arbol, 0).is_none());
+}
+
+#[test]
+fn rellena_las_cabeceras_al_exportar() {
+    let partida = lee_pgn("1. d4 d5 2. c4 c6 3. Nf3 Nf6 4. Nc3 e6 5. e3 Nbd7 *").unwrap();
+    let texto = escribe_pgn(&partida);
+    assert!(texto.contains("[ECO \"D45\"]"));
+    assert!(texto.contains("[Opening \"Semi-Slav Defense: Normal Variation\"]"));
+
+    // la cabecera que ya trae la partida no se toca
+    let partida = lee_pgn("[ECO \"D46\"]\n\n1. d4 d5 2. c4 c6 3. Nf3 Nf6 4. Nc3 e6 5. e3 *").unwrap();
+    let texto = escribe_pgn(&partida);
+    assert!(texto.contains("[ECO \"D46\"]"));
+    assert!(!texto.contains("[ECO \"D45\"]"));
+}
@@ -0,0 +1,85 @@
+/***************************************************************
+*
+* Comprobacion de la lectura y escritura de EPD
+*
+* Las posiciones son las primeras de las baterias WAC y STS
+*
+***************************************************************/
+
+use std::fs;
+
+use ajedrez::{reset, mueve_san, get_fen, ErrorAjedrez, Move};
+use ajedrez::epd::{self, Epd};
+
+
+const WAC_001: &str = "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";";
+const STS_001: &str = "1kr5/3n4/q3p2p/p2n2p1/PppB1P2/5BP1/1P2Q2P/3R2K1 w - - bm f5; \
+                       id \"STS(v1.0) Undermine.001\"; c0 \"f5=10, Be5+=2, Bf2=3, Bg4=2\";";
+
+
+#[test]
+fn lee_y_escribe_una_posicion() {
+    let epd = Epd::lee(WAC_001).unwrap();
+    assert_eq!(epd.id(), Some("WAC.001"));
+    assert_eq!(epd.fen(), "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+    let board = epd.tablero().unwrap();
+    assert_eq!(epd.mejores().unwrap(), vec![Move::from_san(&board, "Qg6").unwrap()]);
+    assert!(epd.evitar().unwrap().is_empty());
+    assert_eq!(epd.to_string(), WAC_001);
+
+    // los operandos entre comillas pueden llevar espacios y comas
+    let epd = Epd::lee(STS_001).unwrap();
+    assert_eq!(epd.id(), Some("STS(v1.0) Undermine.001"));
+    assert_eq!(epd.operando("c0"), Some("f5=10, Be5+=2, Bf2=3, Bg4=2"));
+    assert_eq!(epd.to_string(), STS_001);
+}
+
+#[test]
+fn contadores_y_posicion_del_tablero() {
+    let epd = Epd::lee("4k3/8/8/8/8/8/8/4K2R w K - hmvc 12; fmvn 40").unwrap();
+    assert_eq!(epd.fen(), "4k3/8/8/8/8/8/8/4K2R w K - 12 40");
+    let mut board = epd.tablero().unwrap();
+    assert_eq!(get_fen(&mut board), epd.fen());
+
+    let mut board = reset();
+    mueve_san(&mut board, "e4").unwrap();
+    let mut epd = Epd::desde_tablero(&mut board);
+    epd.pon("am", vec!["Qh4".to_string(), "f6".to_string()]);
+    epd.pon("id", vec!["prueba".to_string()]);
+    epd.pon("am", vec!["f6".to_string()]);
+    assert_eq!(epd.to_string(),
+               "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 am f6; id \"prueba\";");
+    assert_eq!(Epd::lee(&epd.to_string()).unwrap(), epd);
+}
+
+#[test]
+fn rechaza_las_epd_mal_formadas() {
+    match Epd::lee("4k3/8/8/8/8/8/8/4K3 w - - id \"sin cerrar;") {
+        Err(ErrorAjedrez::EpdMalFormada(_)) => (),
+        otro => panic!("{:?}", otro),
+    }
+    match Epd::lee("4k3/8/8/8/8/8/8/4K3 w - - 3bm e4;") {
+        Err(ErrorAjedrez::EpdMalFormada(_)) => (),
+        otro => panic!("{:?}", otro),
+    }
+    assert!(Epd::lee("4k3/8/8/8/8/8/8/4K3 w -").is_err());
+    // un peon en la octava
+    assert!(Epd::lee("4k2P/8/8/8/8/8/8/4K3 w - - id \"x\";").is_err());
+    // la mejor jugada no es legal
+    let epd = Epd::lee("4k3/8/8/8/8/8/8/4K3 w - - bm Qd1;").unwrap();
+    assert!(epd.mejores().is_err());
+}
+
+#[test]
+fn lee_un_fichero() {
+    let ruta = std::env::temp_dir().join("ajedrez_prueba.epd");
+    fs::write(&ruta, format!("{}\n\n{}\n", WAC_001, STS_001)).unwrap();
+    let posiciones = epd::abre(&ruta).unwrap();
+    assert_eq!(posiciones.len(), 2);
+    assert_eq!(posiciones[1].id(), Some("STS(v1.0) Undermine.001"));
+
+    fs::write(&ruta, format!("{}\n4k3/8/8 w - -\n", WAC_001)).unwrap();
+    let error = epd::abre(&ruta).unwrap_err();
+    assert!(error.contains("linea 2"), "{}", error);
+    fs::remove_file(&ruta).unwrap();
+}
@@ -0,0 +1,39 @@
+/***************************************************************
+*
+* Comprobacion de valida_fen con posiciones imposibles
+*
+***************************************************************/
+
+use ajedrez::{valida_fen, CampoFen, ErrorAjedrez};
+
+
+// el campo de la FEN que valida_fen dice que esta mal
+fn campo_erroneo(fen: &str) -> Option<CampoFen> {
+    match valida_fen(fen) {
+        Err(ErrorAjedrez::FenInvalida { campo, .. }) => Some(campo),
+        _ => None,
+    }
+}
+
+#[test]
+fn posiciones_validas() {
+    assert!(valida_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_ok());
+    assert!(valida_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3").is_ok());
+    assert!(valida_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").is_ok());
+}
+
+#[test]
+fn posiciones_imposibles() {
+    // dos reyes blancos
+    assert_eq!(campo_erroneo("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"), Some(CampoFen::Piezas));
+    // peon en la ultima fila
+    assert_eq!(campo_erroneo("4k2P/8/8/8/8/8/8/4K3 w - - 0 1"), Some(CampoFen::Piezas));
+    // nueve peones
+    assert_eq!(campo_erroneo("4k3/8/8/8/8/1P6/PPPPPPPP/4K3 w - - 0 1"), Some(CampoFen::Piezas));
+    // juegan las blancas con el rey negro en jaque
+    assert_eq!(campo_erroneo("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), Some(CampoFen::Turno));
+    // enroque sin la torre
+    assert_eq!(campo_erroneo("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), Some(CampoFen::Enroques));
+    // casilla al paso sin el peon que ha avanzado
+    assert_eq!(campo_erroneo("4k3/8/8/8/8/8/8/4K3 w - d6 0 1"), Some(CampoFen::AlPaso));
+}
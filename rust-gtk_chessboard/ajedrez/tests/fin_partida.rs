@@ -0,0 +1,50 @@
+/***************************************************************
+*
+* Comprobacion de como termina una partida
+*
+* material_insuficiente es una regresion: con rey y torre contra
+* rey no hay alfil ni caballo en la cuenta de piezas y se caia.
+*
+***************************************************************/
+
+use ajedrez::{Tablero, Color, FinPartida, set_fen, material_insuficiente, resultado_partida};
+
+
+fn tablero(fen: &str) -> Tablero {
+    let mut board = Tablero::init();
+    set_fen(fen, &mut board).unwrap();
+    board
+}
+
+
+#[test]
+fn material() {
+    for fen in &["4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                 "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+                 "4k3/8/8/8/8/8/8/2n1K3 w - - 0 1",
+                 "2b1k3/8/8/8/8/8/8/3BK3 w - - 0 1"] {
+        assert!(material_insuficiente(&mut tablero(fen)), "{}", fen);
+    }
+    // con una torre, una dama o un peon se puede dar mate
+    for fen in &["4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+                 "4k3/8/8/8/8/8/8/3qK3 w - - 0 1",
+                 "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+                 "4k3/8/8/8/8/8/8/2BBK3 w - - 0 1"] {
+        assert!(!material_insuficiente(&mut tablero(fen)), "{}", fen);
+    }
+}
+
+#[test]
+fn mate_y_ganador() {
+    // mate del pastor: ganan las blancas
+    let mut board = tablero("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4");
+    let fin = resultado_partida(&mut board).unwrap();
+    assert_eq!(fin, FinPartida::JaqueMate { ganador: Color::White });
+    assert_eq!(fin.resultado(), "1-0");
+
+    // mate del loco: ganan las negras
+    let mut board = tablero("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+    let fin = resultado_partida(&mut board).unwrap();
+    assert_eq!(fin, FinPartida::JaqueMate { ganador: Color::Black });
+    assert_eq!(fin.resultado(), "0-1");
+}
@@ -0,0 +1,32 @@
+/***************************************************************
+*
+* Comprobacion de las piezas que dan jaque
+*
+***************************************************************/
+
+use ajedrez::{Tablero, set_fen, reset, dan_jaque, casilla_rey, rey_en_jaque, Color, Square};
+
+
+fn casillas(nombres: &[&str]) -> Vec<Square> {
+    nombres.iter().map(|c| Square::from_algebraic(c).unwrap()).collect()
+}
+
+#[test]
+fn jaque_doble_y_sin_jaque() {
+    let mut board = Tablero::init();
+    set_fen("4k3/8/3N4/8/8/8/8/4RK2 b - - 0 1", &mut board).unwrap();
+    assert!(rey_en_jaque(&mut board));
+    assert_eq!(dan_jaque(&board), casillas(&["d6", "e1"]));
+    assert_eq!(casilla_rey(&board, Color::Black), Some(Square::from_algebraic("e8").unwrap()));
+    assert_eq!(casilla_rey(&board, Color::White), Some(Square::from_algebraic("f1").unwrap()));
+
+    let board = reset();
+    assert!(dan_jaque(&board).is_empty());
+}
+
+#[test]
+fn jaque_de_peon() {
+    let mut board = Tablero::init();
+    set_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1", &mut board).unwrap();
+    assert_eq!(dan_jaque(&board), casillas(&["d2"]));
+}
@@ -0,0 +1,65 @@
+/***************************************************************
+*
+* Comprobacion de las flechas y circulos de los comentarios
+* ([%cal ...] y [%csl ...])
+*
+***************************************************************/
+
+use ajedrez::Square;
+use ajedrez::arbol::{extrae_marcas, ColorMarca, Flecha, Circulo};
+use ajedrez::partida::{lee_pgn, escribe_pgn};
+
+
+fn casilla(nombre: &str) -> Square {
+    Square::from_algebraic(nombre).unwrap()
+}
+
+#[test]
+fn separa_las_ordenes_del_texto() {
+    let (texto, flechas, circulos) =
+            extrae_marcas("buena [%csl Rd5,Gf3] jugada [%clk 0:05:00] [%cal Ge2e4,Xa1a2,Bd1h5]");
+    assert_eq!(texto, "buena jugada [%clk 0:05:00]");
+    assert_eq!(flechas, vec![
+        Flecha { color: ColorMarca::Verde, desde: casilla("e2"), hasta: casilla("e4") },
+        Flecha { color: ColorMarca::Azul, desde: casilla("d1"), hasta: casilla("h5") },
+    ]);
+    assert_eq!(circulos, vec![
+        Circulo { color: ColorMarca::Rojo, casilla: casilla("d5") },
+        Circulo { color: ColorMarca::Verde, casilla: casilla("f3") },
+    ]);
+}
+
+#[test]
+fn sobreviven_a_leer_y_escribir() {
+    let pgn = "{[%csl Ye4]} 1. e4 {[%cal Gd7d5] la mas comun} e5 \
+               (1... c5 {[%csl Rd4]}) 2. Nf3 *";
+    let partida = lee_pgn(pgn).unwrap();
+    let arbol = &partida.arbol;
+    assert_eq!(arbol.nodos[0].circulos.len(), 1);
+    let e4 = arbol.linea_principal()[0];
+    assert_eq!(arbol.nodos[e4].comentario, "la mas comun");
+    assert_eq!(arbol.nodos[e4].flechas[0].to_string(), "Gd7d5");
+
+    let texto = escribe_pgn(&partida);
+    assert!(texto.contains("{[%csl Ye4]} 1. e4 {[%cal Gd7d5] la mas comun} 1... e5 ( 1... c5 {[%csl Rd4]} )"),
+            "{}", texto);
+    let otra = lee_pgn(&texto).unwrap();
+    assert_eq!(escribe_pgn(&otra), texto);
+}
+
+#[test]
+fn cambiar_una_marca_la_pone_o_la_quita() {
+    let mut partida = lee_pgn("1. e4 *").unwrap();
+    let nodo = &mut partida.arbol.nodos[1];
+    let verde = Flecha { color: ColorMarca::Verde, desde: casilla("g1"), hasta: casilla("f3") };
+    let roja = Flecha { color: ColorMarca::Rojo, ..verde };
+    nodo.cambia_flecha(verde);
+    nodo.cambia_flecha(roja);
+    assert_eq!(nodo.flechas, vec![roja]);
+    nodo.cambia_flecha(roja);
+    assert!(nodo.flechas.is_empty());
+
+    let circulo = Circulo { color: ColorMarca::Amarillo, casilla: casilla("e4") };
+    nodo.cambia_circulo(circulo);
+    assert_eq!(nodo.comentario_pgn(), "[%csl Ye4]");
+}
@@ -0,0 +1,140 @@
+/***************************************************************
+*
+* Comprobacion del cliente UCI
+*
+* Las lineas del motor se prueban sueltas con lee_linea. Para el
+* proceso se usa un motor falso: un script de shell que contesta
+* uciok, readyok y bestmove como lo haria un motor de verdad.
+*
+***************************************************************/
+
+use std::time::{Duration, Instant};
+
+use ajedrez::motor_uci::{lee_linea, MensajeMotor, InfoMotor, Puntuacion};
+
+
+#[test]
+fn lineas_info() {
+    let linea = "info depth 12 seldepth 18 multipv 2 score cp -35 nodes 154032 nps 1203375 \
+                 time 128 pv e7e5 g1f3 b8c6";
+    assert_eq!(lee_linea(linea), Some(MensajeMotor::Info(InfoMotor {
+        profundidad: Some(12),
+        puntuacion: Some(Puntuacion::Centipeones(-35)),
+        multipv: 2,
+        nodos: Some(154032),
+        nps: Some(1203375),
+        tiempo: Some(128),
+        pv: vec!["e7e5".to_string(), "g1f3".to_string(), "b8c6".to_string()],
+    })));
+
+    // sin multipv es la primera linea
+    match lee_linea("info depth 20 score mate -3 pv h7h6 d8h4") {
+        Some(MensajeMotor::Info(info)) => {
+            assert_eq!(info.multipv, 1);
+            assert_eq!(info.puntuacion, Some(Puntuacion::Mate(-3)));
+            assert_eq!(info.pv.len(), 2);
+        },
+        otro => panic!("{:?}", otro),
+    }
+
+    // las info sin variante no interesan
+    assert_eq!(lee_linea("info depth 5 currmove e2e4 currmovenumber 1"), None);
+    assert_eq!(lee_linea("info string NNUE evaluation using nn-xxx.nnue pv e2e4"), None);
+}
+
+#[test]
+fn lineas_bestmove_y_otras() {
+    assert_eq!(lee_linea("bestmove e2e4 ponder e7e5"),
+               Some(MensajeMotor::MejorJugada { jugada: "e2e4".to_string(), ponder: Some("e7e5".to_string()) }));
+    assert_eq!(lee_linea("bestmove a7a8q"),
+               Some(MensajeMotor::MejorJugada { jugada: "a7a8q".to_string(), ponder: None }));
+    assert_eq!(lee_linea("id name Stockfish 16"), Some(MensajeMotor::Nombre("Stockfish 16".to_string())));
+    assert_eq!(lee_linea("uciok"), Some(MensajeMotor::UciOk));
+    assert_eq!(lee_linea("  readyok  "), Some(MensajeMotor::ReadyOk));
+
+    // lo que no se entiende se ignora
+    for linea in &["", "   ", "bestmove", "id author alguien", "option name Hash type spin default 16",
+                   "Stockfish 16 by the Stockfish developers", "info score cp pv", "\u{0}\u{1}basura"] {
+        assert_eq!(lee_linea(linea), None, "{:?}", linea);
+    }
+}
+
+
+#[cfg(unix)]
+mod proceso {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    use ajedrez::motor_uci::{MotorUci, Limite};
+
+    // el motor falso tarda un segundo en dar la jugada despues de la primera info
+    const MOTOR_FALSO: &str = "#!/bin/sh
+while read orden resto; do
+    case \"$orden\" in
+        uci) echo 'id name Motor de prueba'; echo 'option name Hash type spin default 16'; echo uciok ;;
+        isready) echo readyok ;;
+        go) echo 'info depth 1 score cp 20 pv e2e4 e7e5'; sleep 1; echo 'bestmove e2e4 ponder e7e5' ;;
+        quit) exit 0 ;;
+    esac
+done
+";
+
+    fn escribe_motor(nombre: &str) -> PathBuf {
+        let ruta = std::env::temp_dir().join(format!("{}_{}.sh", nombre, std::process::id()));
+        fs::write(&ruta, MOTOR_FALSO).unwrap();
+        fs::set_permissions(&ruta, fs::Permissions::from_mode(0o755)).unwrap();
+        ruta
+    }
+
+    // espera hasta que llegue un mensaje que cumpla la condicion
+    fn espera(motor: &MotorUci, condicion: impl Fn(&MensajeMotor) -> bool) -> Vec<MensajeMotor> {
+        let limite = Instant::now() + Duration::from_secs(10);
+        let mut recibidos = Vec::new();
+        while Instant::now() < limite {
+            recibidos.extend(motor.mensajes());
+            if recibidos.iter().any(&condicion) {
+                return recibidos;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("el motor no ha contestado: {:?}", recibidos);
+    }
+
+    #[test]
+    fn negociacion_y_busqueda() {
+        let ruta = escribe_motor("motor_falso");
+        let mut motor = MotorUci::arranca(&ruta).unwrap();
+        assert_eq!(motor.nombre, "Motor de prueba");
+        // la negociacion se ha comido uciok y readyok
+        assert!(motor.mensajes().is_empty());
+
+        motor.nueva_partida().unwrap();
+        motor.posicion(&ajedrez::reset()).unwrap();
+        motor.busca(Limite::Tiempo(1000)).unwrap();
+
+        // la info llega enseguida y mientras se espera la jugada la consulta no se bloquea
+        let recibidos = espera(&motor, |m| matches!(m, MensajeMotor::Info(_)));
+        assert!(!recibidos.iter().any(|m| matches!(m, MensajeMotor::MejorJugada { .. })));
+        let inicio = Instant::now();
+        assert!(motor.mensajes().is_empty());
+        assert!(inicio.elapsed() < Duration::from_millis(100));
+
+        let recibidos = espera(&motor, |m| matches!(m, MensajeMotor::MejorJugada { .. }));
+        assert_eq!(recibidos.last(),
+                   Some(&MensajeMotor::MejorJugada { jugada: "e2e4".to_string(), ponder: Some("e7e5".to_string()) }));
+
+        drop(motor);
+        fs::remove_file(&ruta).unwrap();
+    }
+
+    #[test]
+    fn motor_que_no_existe() {
+        let ruta = std::env::temp_dir().join("no_existe_este_motor");
+        match MotorUci::arranca(&ruta) {
+            Err(msg) => assert!(msg.contains("No se puede arrancar"), "{}", msg),
+            Ok(_) => panic!("ha arrancado un motor que no existe"),
+        }
+    }
+}
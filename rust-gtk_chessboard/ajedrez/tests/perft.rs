@@ -0,0 +1,227 @@
+/***************************************************************
+*
+* Comprobacion del generador de jugadas con perft
+*
+* Los numeros de nodos de las posiciones 1 a 6 son los de
+* https://www.chessprogramming.org/Perft_Results y los casos
+* especiales (al paso con clavada, enroques, coronaciones...)
+* son los de la lista de Martin Sedlak.
+*
+* Los casos profundos tardan bastante sin optimizar y estan
+* marcados con #[ignore]:
+*     cargo test -p ajedrez --release -- --ignored
+*
+***************************************************************/
+
+use ajedrez::{Tablero, set_fen, get_fen, perft, divide};
+
+
+const INICIAL: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+const POSICION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+const POSICION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+const POSICION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+const POSICION_6: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+
+fn tablero(fen: &str) -> Tablero {
+    let mut board = Tablero::init();
+    set_fen(fen, &mut board).unwrap();
+    board
+}
+
+// comprueba los nodos de cada profundidad, empezando por la 1
+fn comprueba(fen: &str, nodos: &[u64]) {
+    let mut board = tablero(fen);
+    for (i, &esperados) in nodos.iter().enumerate() {
+        let profundidad = i as u32 + 1;
+        assert_eq!(perft(&mut board, profundidad), esperados,
+                    "perft({}) de {}", profundidad, fen);
+    }
+}
+
+// un unico perft a la profundidad dada
+fn comprueba_a(fen: &str, profundidad: u32, esperados: u64) {
+    let mut board = tablero(fen);
+    assert_eq!(perft(&mut board, profundidad), esperados, "perft({}) de {}", profundidad, fen);
+}
+
+
+// ========= las posiciones de la wiki ==================
+
+#[test]
+fn posicion_inicial() {
+    comprueba(INICIAL, &[20, 400, 8902]);
+}
+
+#[test]
+#[ignore]
+fn posicion_inicial_profunda() {
+    comprueba_a(INICIAL, 4, 197_281);
+    comprueba_a(INICIAL, 5, 4_865_609);
+}
+
+#[test]
+fn kiwipete() {
+    comprueba(KIWIPETE, &[48, 2039]);
+}
+
+#[test]
+#[ignore]
+fn kiwipete_profunda() {
+    comprueba_a(KIWIPETE, 3, 97_862);
+    comprueba_a(KIWIPETE, 4, 4_085_603);
+}
+
+#[test]
+fn posicion_3() {
+    comprueba(POSICION_3, &[14, 191, 2812, 43_238]);
+}
+
+#[test]
+#[ignore]
+fn posicion_3_profunda() {
+    comprueba_a(POSICION_3, 5, 674_624);
+}
+
+#[test]
+fn posicion_4() {
+    comprueba(POSICION_4, &[6, 264, 9467]);
+}
+
+#[test]
+#[ignore]
+fn posicion_4_profunda() {
+    comprueba_a(POSICION_4, 4, 422_333);
+}
+
+#[test]
+fn posicion_5() {
+    comprueba(POSICION_5, &[44, 1486]);
+}
+
+#[test]
+#[ignore]
+fn posicion_5_profunda() {
+    comprueba_a(POSICION_5, 3, 62_379);
+    comprueba_a(POSICION_5, 4, 2_103_487);
+}
+
+#[test]
+fn posicion_6() {
+    comprueba(POSICION_6, &[46, 2079]);
+}
+
+#[test]
+#[ignore]
+fn posicion_6_profunda() {
+    comprueba_a(POSICION_6, 3, 89_890);
+    comprueba_a(POSICION_6, 4, 3_894_594);
+}
+
+
+// ========= casos especiales ==================
+
+#[test]
+fn al_paso_ilegal_por_clavada() {
+    // el peon que capturaria al paso esta clavado contra el rey
+    let mut board = tablero("8/8/8/KPp4r/8/8/8/7k w - c6 0 2");
+    let jugadas: Vec<String> = divide(&mut board, 1).into_iter().map(|(m, _)| m).collect();
+    assert_eq!(jugadas.len(), 4);
+    assert!(!jugadas.contains(&"b5c6".to_string()));
+}
+
+#[test]
+#[ignore]
+fn al_paso_ilegal_profunda() {
+    comprueba_a("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6, 1_134_888);
+    comprueba_a("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6, 1_015_133);
+}
+
+#[test]
+#[ignore]
+fn al_paso_da_jaque() {
+    comprueba_a("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6, 1_440_467);
+}
+
+#[test]
+#[ignore]
+fn enroque_da_jaque() {
+    comprueba_a("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 6, 661_072);
+    comprueba_a("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 6, 803_711);
+}
+
+#[test]
+fn enroque_atravesando_jaque() {
+    // la torre de f8 ataca f1, asi que las blancas solo pueden enrocar largo
+    let mut board = tablero("r3kr2/8/8/8/8/8/8/R3K2R w KQq - 0 1");
+    let jugadas: Vec<String> = divide(&mut board, 1).into_iter().map(|(m, _)| m).collect();
+    assert!(jugadas.contains(&"e1c1".to_string()));
+    assert!(!jugadas.contains(&"e1g1".to_string()));
+}
+
+#[test]
+#[ignore]
+fn derechos_de_enroque() {
+    comprueba_a("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 4, 1_274_206);
+    comprueba_a("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", 4, 1_720_476);
+}
+
+#[test]
+#[ignore]
+fn coronar_para_salir_del_jaque() {
+    comprueba_a("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 6, 3_821_001);
+}
+
+#[test]
+#[ignore]
+fn jaque_descubierto() {
+    comprueba_a("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1", 5, 1_004_658);
+}
+
+#[test]
+fn coronar_dando_jaque() {
+    comprueba_a("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", 6, 217_342);
+}
+
+#[test]
+fn subpromocion_dando_jaque() {
+    comprueba_a("8/P1k5/K7/8/8/8/8/8 w - - 0 1", 6, 92_683);
+}
+
+#[test]
+fn ahogado_propio() {
+    comprueba_a("K1k5/8/P7/8/8/8/8/8 w - - 0 1", 6, 2217);
+}
+
+#[test]
+fn ahogado_y_mate() {
+    comprueba_a("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", 4, 23_527);
+}
+
+#[test]
+#[ignore]
+fn ahogado_y_mate_profunda() {
+    comprueba_a("8/k1P5/8/1K6/8/8/8/8 w - - 0 1", 7, 567_584);
+}
+
+
+// ========= divide ==================
+
+#[test]
+fn divide_suma_el_perft() {
+    let mut board = tablero(KIWIPETE);
+    let parciales = divide(&mut board, 2);
+    assert_eq!(parciales.len(), 48);
+    assert_eq!(parciales.iter().map(|&(_, n)| n).sum::<u64>(), 2039);
+    // los dos enroques tienen que estar entre las jugadas
+    assert!(parciales.iter().any(|(m, _)| m == "e1g1"));
+    assert!(parciales.iter().any(|(m, _)| m == "e1c1"));
+}
+
+#[test]
+fn perft_deja_el_tablero_igual() {
+    let mut board = tablero(KIWIPETE);
+    perft(&mut board, 3);
+    assert_eq!(get_fen(&mut board), KIWIPETE);
+}
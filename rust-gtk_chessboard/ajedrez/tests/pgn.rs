@@ -0,0 +1,67 @@
+/***************************************************************
+*
+* Comprobacion de la lectura y escritura de PGN
+*
+***************************************************************/
+
+use ajedrez::partida::{lee_pgn, escribe_pgn};
+
+
+const PARTIDA: &str = include_str!("data/unaPartida.pgn");
+
+
+// leer lo escrito da el mismo texto
+fn ida_y_vuelta(pgn: &str) -> String {
+    let texto = escribe_pgn(&lee_pgn(pgn).unwrap());
+    assert_eq!(escribe_pgn(&lee_pgn(&texto).unwrap()), texto);
+    texto
+}
+
+#[test]
+fn partida_de_ejemplo() {
+    let partida = lee_pgn(PARTIDA).unwrap();
+    assert_eq!(partida.cabecera("White"), Some("Ju Wenjun (CHN)"));
+    assert_eq!(partida.resultado, "1/2-1/2");
+    assert_eq!(partida.arbol.linea_principal().len(), 99);
+    let texto = ida_y_vuelta(PARTIDA);
+    assert!(texto.contains("11. O-O-O Nb6"));
+    assert!(texto.trim_end().ends_with("50. Rh7 1/2-1/2"));
+}
+
+#[test]
+fn enroques_con_ceros() {
+    let partida = lee_pgn("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. 0-0 Nf6 5.d3 0-0 *").unwrap();
+    let arbol = &partida.arbol;
+    let linea = arbol.linea_principal();
+    assert_eq!(arbol.nodos[linea[6]].san, "O-O");
+    assert_eq!(arbol.nodos[linea[9]].san, "O-O");
+
+    // el largo, tambien con la anotacion pegada
+    let partida = lee_pgn("1. d4 d5 2. Nc3 Nc6 3. Bf4 Bf5 4. Qd2 Qd7 5. 0-0-0 0-0-0!? *").unwrap();
+    let arbol = &partida.arbol;
+    let linea = arbol.linea_principal();
+    assert_eq!(arbol.nodos[linea[8]].san, "O-O-O");
+    assert_eq!(arbol.nodos[linea[9]].san, "O-O-O");
+    assert_eq!(arbol.nodos[linea[9]].nags, vec![5]);
+}
+
+#[test]
+fn comentarios_nags_y_variantes() {
+    let pgn = "{Apertura} 1. e4 e5 2. Nf3 {la mas jugada} Nc6 3. Bb5!? a6 $6 \
+               (3... Nf6 {la berlinesa} 4. O-O (4. d3) 4... Nxe4) 4. Ba4 *";
+    let partida = lee_pgn(pgn).unwrap();
+    let arbol = &partida.arbol;
+    assert_eq!(arbol.nodos[0].comentario, "Apertura");
+    let linea = arbol.linea_principal();
+    assert_eq!(linea.len(), 7);
+    assert_eq!(arbol.nodos[linea[2]].comentario, "la mas jugada");
+    assert_eq!(arbol.nodos[linea[4]].nags, vec![5]);
+    assert_eq!(arbol.nodos[linea[5]].nags, vec![6]);
+    // la variante sale del nodo de 3. Bb5
+    assert_eq!(arbol.nodos[linea[4]].hijos.len(), 2);
+
+    // las lineas se cortan a 80 caracteres
+    let texto = ida_y_vuelta(pgn).split_whitespace().collect::<Vec<&str>>().join(" ");
+    assert!(texto.contains("{Apertura} 1. e4 e5 2. Nf3 {la mas jugada} 2... Nc6 3. Bb5 $5 a6 $6"), "{}", texto);
+    assert!(texto.contains("( 3... Nf6 {la berlinesa} 4. O-O ( 4. d3 ) 4... Nxe4 ) 4. Ba4 *"), "{}", texto);
+}
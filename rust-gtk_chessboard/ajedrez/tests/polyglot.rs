@@ -0,0 +1,91 @@
+/***************************************************************
+*
+* Comprobacion de los libros Polyglot
+*
+* Las claves son las de ejemplo de la especificacion del formato
+* (http://hgm.nubati.net/book_format.html), jugando las mismas
+* jugadas desde la posicion inicial.
+*
+***************************************************************/
+
+use std::fs;
+
+use ajedrez::{reset, mueve_san};
+use ajedrez::polyglot::{clave, Libro};
+
+
+#[test]
+fn claves_de_la_especificacion() {
+    let casos: [(&[&str], u64); 9] = [
+        (&[], 0x463b96181691fc9c),
+        (&["e4"], 0x823c9b50fd114196),
+        (&["e4", "d5"], 0x0756b94461c50fb0),
+        (&["e4", "d5", "e5"], 0x662fafb965db29d4),
+        (&["e4", "d5", "e5", "f5"], 0x22a48b5a8e47ff78),
+        (&["e4", "d5", "e5", "f5", "Ke2"], 0x652a607ca3f242c1),
+        (&["e4", "d5", "e5", "f5", "Ke2", "Kf7"], 0x00fdd303c946bdd9),
+        (&["a4", "b5", "h4", "b4", "c4"], 0x3c8123ea7b067637),
+        (&["a4", "b5", "h4", "b4", "c4", "bxc3", "Ra3"], 0x5c3f9b829b279560),
+    ];
+    for (jugadas, esperada) in casos.iter() {
+        let mut board = reset();
+        for san in jugadas.iter() {
+            mueve_san(&mut board, san).unwrap();
+        }
+        assert_eq!(clave(&board), *esperada, "clave tras {:?}", jugadas);
+    }
+}
+
+
+// una entrada del libro: clave, jugada, peso y aprendizaje en big endian
+fn entrada(clave: u64, jugada: u16, peso: u16) -> Vec<u8> {
+    let mut bytes = clave.to_be_bytes().to_vec();
+    bytes.extend_from_slice(&jugada.to_be_bytes());
+    bytes.extend_from_slice(&peso.to_be_bytes());
+    bytes.extend_from_slice(&0u32.to_be_bytes());
+    bytes
+}
+
+// la jugada Polyglot de desde a hasta, con las casillas numeradas de a1 = 0 a h8 = 63
+fn jugada(desde: u16, hasta: u16) -> u16 {
+    (desde << 6) | hasta
+}
+
+#[test]
+fn lee_un_libro() {
+    let mut board = reset();
+    let inicial = clave(&board);
+    for san in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5"].iter() {
+        mueve_san(&mut board, san).unwrap();
+    }
+    let italiana = clave(&board);
+
+    let mut datos = Vec::new();
+    datos.extend(entrada(inicial, jugada(12, 28), 2));     // e2e4
+    datos.extend(entrada(inicial, jugada(11, 27), 1));     // d2d4
+    datos.extend(entrada(italiana, jugada(4, 7), 5));      // e1h1, el enroque corto
+    let ruta = std::env::temp_dir().join("ajedrez_prueba_polyglot.bin");
+    fs::write(&ruta, &datos).unwrap();
+    let libro = Libro::abre(&ruta).unwrap();
+    fs::remove_file(&ruta).unwrap();
+    assert_eq!(libro.len(), 3);
+
+    let jugadas = libro.jugadas(&reset());
+    let uci: Vec<String> = jugadas.iter().map(|j| j.jugada.uci()).collect();
+    assert_eq!(uci, vec!["e2e4", "d2d4"]);
+    assert_eq!(jugadas[0].peso, 2);
+    let elegida = libro.elige(&reset()).unwrap().uci();
+    assert!(elegida == "e2e4" || elegida == "d2d4");
+
+    let enroque = libro.jugadas(&board);
+    assert_eq!(enroque.len(), 1);
+    assert_eq!(enroque[0].jugada.uci(), "e1g1");
+}
+
+#[test]
+fn rechaza_un_fichero_que_no_es_un_libro() {
+    let ruta = std::env::temp_dir().join("ajedrez_prueba_no_libro.bin");
+    fs::write(&ruta, b"esto no es un libro").unwrap();
+    assert!(Libro::abre(&ruta).is_err());
+    fs::remove_file(&ruta).unwrap();
+}
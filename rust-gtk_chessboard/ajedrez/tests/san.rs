@@ -0,0 +1,47 @@
+/***************************************************************
+*
+* Comprobacion de la notacion SAN de las jugadas hechas
+*
+* Son regresiones: las jugadas que pueden hacer dos piezas iguales
+* salian sin desambiguar y la captura al paso llevaba " e.p." pegado.
+*
+***************************************************************/
+
+use ajedrez::{Tablero, Move, set_fen, mueve};
+
+
+fn tablero(fen: &str) -> Tablero {
+    let mut board = Tablero::init();
+    set_fen(fen, &mut board).unwrap();
+    board
+}
+
+fn san(fen: &str, uci: &str) -> String {
+    let mut board = tablero(fen);
+    let jugada = Move::from_uci(&board, uci).unwrap();
+    mueve(&mut board, jugada).unwrap().san
+}
+
+
+#[test]
+fn desambiguacion() {
+    // por la columna
+    assert_eq!(san("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", "b1d2"), "Nbd2");
+    assert_eq!(san("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", "f3d2"), "Nfd2");
+    // por la fila si estan en la misma columna
+    assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+    assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a5a3"), "R5a3");
+    // por ambas si hay rivales en la columna y en la fila
+    assert_eq!(san("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1"), "Qh4e1");
+    // una pieza clavada no cuenta como rival
+    assert_eq!(san("4k3/4r3/8/8/8/8/4N3/1N2K3 w - - 0 1", "b1c3"), "Nc3");
+    // y las capturas y los jaques siguen detras
+    assert_eq!(san("4k3/8/8/8/8/5N2/3p4/1N5K w - - 0 1", "b1d2"), "Nbxd2");
+    assert_eq!(san("8/8/8/8/2k5/5N2/8/1N2K3 w - - 0 1", "b1d2"), "Nbd2+");
+}
+
+#[test]
+fn captura_al_paso() {
+    assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+    assert_eq!(san("4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 1", "e4d3"), "exd3");
+}
@@ -0,0 +1,108 @@
+/***************************************************************
+*
+* Comprobacion de la clave zobrist incremental
+*
+* La clave que mantienen make_movim y unmake_movim tiene que ser
+* la misma que la calculada desde cero al cargar la FEN.
+*
+***************************************************************/
+
+use ajedrez::{Tablero, set_fen, get_fen, clave_zobrist, jugadas_legales, mueve, mueve_san,
+              mueve_atras, repeticion_triple};
+
+
+fn tablero(fen: &str) -> Tablero {
+    let mut board = Tablero::init();
+    set_fen(fen, &mut board).unwrap();
+    board
+}
+
+// recorre el arbol de jugadas comparando la clave con la de la FEN de cada nodo
+fn recorre(board: &mut Tablero, profundidad: u32) {
+    let fen = get_fen(board);
+    assert_eq!(clave_zobrist(board), clave_zobrist(&tablero(&fen)), "clave de {}", fen);
+    if profundidad == 0 {
+        return;
+    }
+    for jugada in jugadas_legales(board) {
+        mueve(board, jugada).unwrap();
+        recorre(board, profundidad - 1);
+        mueve_atras(board).unwrap();
+    }
+    assert_eq!(get_fen(board), fen);
+}
+
+
+#[test]
+fn clave_incremental() {
+    // enroques, al paso y coronaciones
+    recorre(&mut tablero("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"), 2);
+    recorre(&mut tablero("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"), 2);
+    recorre(&mut tablero("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"), 2);
+}
+
+#[test]
+fn transposicion() {
+    let mut uno = ajedrez::reset();
+    let mut otro = ajedrez::reset();
+    for san in &["Nf3", "Nf6", "Nc3"] {
+        mueve_san(&mut uno, san).unwrap();
+    }
+    for san in &["Nc3", "Nf6", "Nf3"] {
+        mueve_san(&mut otro, san).unwrap();
+    }
+    assert_eq!(clave_zobrist(&uno), clave_zobrist(&otro));
+
+    // la casilla al paso solo cuenta si algun peon puede capturar
+    let mut avance = ajedrez::reset();
+    mueve_san(&mut avance, "e4").unwrap();
+    assert_eq!(clave_zobrist(&avance),
+               clave_zobrist(&tablero("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")));
+    assert_ne!(clave_zobrist(&tablero("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")),
+               clave_zobrist(&tablero("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")));
+    // y el turno tambien
+    assert_ne!(clave_zobrist(&ajedrez::reset()),
+               clave_zobrist(&tablero("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1")));
+}
+
+#[test]
+fn triple_repeticion() {
+    let mut board = ajedrez::reset();
+    let vaiven = ["Nf3", "Nf6", "Ng1", "Ng8"];
+    for san in vaiven.iter().chain(vaiven.iter()) {
+        assert!(!repeticion_triple(&mut board));
+        mueve_san(&mut board, san).unwrap();
+    }
+    // la posicion inicial esta por tercera vez
+    assert!(repeticion_triple(&mut board));
+
+    // despues de una jugada de peon vuelve a contar desde cero
+    mueve_san(&mut board, "d4").unwrap();
+    let vaiven = ["Nf6", "Nf3", "Ng8", "Ng1"];
+    for san in vaiven.iter().chain(vaiven.iter()).take(7) {
+        mueve_san(&mut board, san).unwrap();
+        assert!(!repeticion_triple(&mut board));
+    }
+    mueve_san(&mut board, "Ng1").unwrap();
+    assert!(repeticion_triple(&mut board));
+}
+
+#[test]
+fn repeticion_con_casilla_al_paso() {
+    // ningun peon negro puede capturar en e3, asi que la posicion de
+    // despues de 1.e4 es la misma que la de despues de 3.Ng1 y 5.Ng1
+    let mut board = ajedrez::reset();
+    for san in &["e4", "Nf6", "Nf3", "Ng8", "Ng1", "Nf6", "Nf3", "Ng8"] {
+        mueve_san(&mut board, san).unwrap();
+        assert!(!repeticion_triple(&mut board));
+    }
+    mueve_san(&mut board, "Ng1").unwrap();
+    assert!(repeticion_triple(&mut board));
+
+    // si se puede capturar al paso, la casilla cuenta y no hay repeticion
+    let mut board = tablero("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1");
+    for san in &["e4", "Kd7", "Kd2", "Ke8", "Ke1", "Kd7", "Kd2", "Ke8", "Ke1"] {
+        mueve_san(&mut board, san).unwrap();
+    }
+    assert!(!repeticion_triple(&mut board));
+}
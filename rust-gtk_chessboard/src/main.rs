@@ -16,6 +16,7 @@ extern crate mut_static;
 
 use std::process;
 use std::env;
+use std::path::Path;
 
 mod ui;
 
@@ -34,18 +35,25 @@ fn main() {
     
     let mut current_board: ajedrez::Tablero = ajedrez::Tablero::init();;
     
+    let mut base_datos = None;
+    
     if env::args().len() > 1 {
-        // suponemos que hemos pasado una partida PGN como parametro
-        // TODO all
-    }
-    else {
-        let fen_valida = ajedrez::setup_inicio(&mut current_board);
-        if !fen_valida {
-            eprintln!("La FEN de inicio no es válida");
-            process::exit(1);
+        // suponemos que hemos pasado un fichero PGN como parametro
+        let ruta = env::args().nth(1).unwrap();
+        match ajedrez::base_datos::BaseDatos::abre(Path::new(&ruta)) {
+            Ok(base) => base_datos = Some(base),
+            Err(msg) => {
+                eprintln!("{}", msg);
+                process::exit(1);
+            },
         }
     }
     
-    inicia_gui::setup_gui(current_board);
+    if let Err(e) = ajedrez::setup_inicio(&mut current_board) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    
+    inicia_gui::setup_gui(current_board, base_datos);
     gtk::main();
 }
@@ -1,9 +1,14 @@
 use gtk::prelude::*;
+use std::fs;
 
+use super::ajedrez::base_datos::BaseDatos;
+use super::ajedrez::motor_uci::MotorUci;
+use super::ajedrez::polyglot::Libro;
 
-// Carga una partida nueva desde un fichero PGN
-// No está impentado. Falta ller el fichero y procesarlo
-pub fn open_pgn_callback(_file: &gtk::MenuItem , win_padre: &gtk::Window ) {
+
+// Abre un fichero PGN con una o varias partidas
+// Retorna la base de datos indexada, o None si se cancela o el fichero no es valido
+pub fn open_pgn_callback(_file: &gtk::MenuItem , win_padre: &gtk::Window ) -> Option<BaseDatos> {
     let dialog = gtk::FileChooserDialog::new(Some("Abrir PGN"), 
                 Some(win_padre), 
                 gtk::FileChooserAction::Open);
@@ -25,13 +30,230 @@ pub fn open_pgn_callback(_file: &gtk::MenuItem , win_padre: &gtk::Window ) {
     if result == gtk::ResponseType::Ok.into() {
         file = dialog.get_filename();
     }
+    dialog.destroy();
+    
+    match file {
+        Some(file) => {
+                    match BaseDatos::abre(&file) {
+                        Ok(base) => Some(base),
+                        Err(msg) => {
+                            muestra_error(win_padre, &msg);
+                            None
+                        },
+                    }
+                },
+        None => None,
+    }
+}
+
+
+// Guarda el texto PGN de la partida en el fichero que elija el usuario
+// Retorna false si se cancela o no se puede escribir el fichero
+pub fn save_pgn_callback(_file: &gtk::MenuItem, win_padre: &gtk::Window, texto_pgn: &str) -> bool {
+    let dialog = gtk::FileChooserDialog::new(Some("Guardar PGN"), 
+                Some(win_padre), 
+                gtk::FileChooserAction::Save);
+    dialog.add_buttons(&[
+                ("Guardar", gtk::ResponseType::Ok.into()),
+                ("Cancelar", gtk::ResponseType::Cancel.into())
+            ]);
+    let mut file = None; 
+    let filter = gtk::FileFilter::new();
+    filter.add_pattern("*.pgn");
+    filter.set_name("PGN chess file");
+    dialog.add_filter(&filter);
+    dialog.set_do_overwrite_confirmation(true);
+    dialog.set_current_name("partida.pgn");
+    
+    dialog.set_current_folder_uri("./partidas_pgn/"); 
+    
+    let result = dialog.run();
+    if result == gtk::ResponseType::Ok.into() {
+        file = dialog.get_filename();
+    }
+    dialog.destroy();
+    
+    match file {
+        Some(file) => {
+                    match fs::write(&file, texto_pgn) {
+                        Ok(_) => true,
+                        Err(err) => {
+                            muestra_error(win_padre, &format!("No se puede guardar {}: {}", file.display(), err));
+                            false
+                        },
+                    }
+                },
+        None => false,
+    }
+}
+
+
+// Abre un libro de aperturas Polyglot
+// Retorna el libro leido, o None si se cancela o el fichero no es valido
+pub fn open_book_callback(_file: &gtk::MenuItem, win_padre: &gtk::Window) -> Option<Libro> {
+    let dialog = gtk::FileChooserDialog::new(Some("Abrir libro de aperturas"), 
+                Some(win_padre), 
+                gtk::FileChooserAction::Open);
+    dialog.add_buttons(&[
+                ("Abrir", gtk::ResponseType::Ok.into()),
+                ("Cancelar", gtk::ResponseType::Cancel.into())
+            ]);
+    let mut file = None; 
+    let filter = gtk::FileFilter::new();
+    filter.add_pattern("*.bin");
+    filter.set_name("Polyglot opening book");
+    dialog.add_filter(&filter);
+    
+    let result = dialog.run();
+    if result == gtk::ResponseType::Ok.into() {
+        file = dialog.get_filename();
+    }
+    dialog.destroy();
     
     match file {
         Some(file) => {
-                    println!("{:?}", file);
-                    // TODO abrir convertir la partida pgn a "current_game"
-                    },
-        None => {println!("None");},
+                    match Libro::abre(&file) {
+                        Ok(libro) => Some(libro),
+                        Err(msg) => {
+                            muestra_error(win_padre, &msg);
+                            None
+                        },
+                    }
+                },
+        None => None,
     }
+}
+
+
+// Elige el ejecutable de un motor UCI y lo arranca
+// Retorna el motor listo para usar, o None si se cancela o no arranca
+pub fn load_engine_callback(_file: &gtk::MenuItem, win_padre: &gtk::Window) -> Option<MotorUci> {
+    let dialog = gtk::FileChooserDialog::new(Some("Abrir motor UCI"), 
+                Some(win_padre), 
+                gtk::FileChooserAction::Open);
+    dialog.add_buttons(&[
+                ("Abrir", gtk::ResponseType::Ok.into()),
+                ("Cancelar", gtk::ResponseType::Cancel.into())
+            ]);
+    let mut file = None; 
+    
+    let result = dialog.run();
+    if result == gtk::ResponseType::Ok.into() {
+        file = dialog.get_filename();
+    }
+    dialog.destroy();
+    
+    match file {
+        Some(file) => {
+                    match MotorUci::arranca(&file) {
+                        Ok(motor) => Some(motor),
+                        Err(msg) => {
+                            muestra_error(win_padre, &msg);
+                            None
+                        },
+                    }
+                },
+        None => None,
+    }
+}
+
+
+// ========= la configuracion de una partida contra el motor ==================
+#[derive(Clone, Copy)]
+pub enum LimiteJuego {
+    Tiempo(u64),                                // milisegundos por jugada
+    Profundidad(u32),
+    Reloj { base: u64, incremento: u64 },       // milisegundos para cada jugador
+}
+
+#[derive(Clone, Copy)]
+pub struct ConfigJuego {
+    pub color_humano: i16,                      // WHITE_TO_MOVE o BLACK_TO_MOVE
+    pub limite: LimiteJuego,
+}
+
+
+// Pide el color del jugador y el limite de tiempo del motor
+// Retorna None si se cancela
+pub fn play_engine_dialog(win_padre: &gtk::Window) -> Option<ConfigJuego> {
+    let dialog = gtk::Dialog::new_with_buttons(Some("Jugar contra el motor"),
+                Some(win_padre),
+                gtk::DialogFlags::MODAL,
+                &[("Jugar", gtk::ResponseType::Ok.into()),
+                  ("Cancelar", gtk::ResponseType::Cancel.into())]);
+    
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(12);
+    grid.set_border_width(12);
+    
+    let blancas = gtk::RadioButton::new_with_label("White");
+    let negras = gtk::RadioButton::new_with_label_from_widget(&blancas, "Black");
+    grid.attach(&gtk::Label::new(Some("Play as")), 0, 0, 1, 1);
+    grid.attach(&blancas, 1, 0, 1, 1);
+    grid.attach(&negras, 2, 0, 1, 1);
+    
+    let tipo = gtk::ComboBoxText::new();
+    tipo.append_text("Seconds per move");
+    tipo.append_text("Depth");
+    tipo.append_text("Clock (minutes)");
+    tipo.set_active(Some(0));
+    let valor = gtk::SpinButton::new_with_range(1.0, 180.0, 1.0);
+    valor.set_value(2.0);
+    grid.attach(&gtk::Label::new(Some("Engine limit")), 0, 1, 1, 1);
+    grid.attach(&tipo, 1, 1, 1, 1);
+    grid.attach(&valor, 2, 1, 1, 1);
+    
+    let incremento = gtk::SpinButton::new_with_range(0.0, 60.0, 1.0);
+    incremento.set_value(0.0);
+    incremento.set_sensitive(false);
+    grid.attach(&gtk::Label::new(Some("Increment (seconds)")), 0, 2, 1, 1);
+    grid.attach(&incremento, 2, 2, 1, 1);
+    
+    let inc = incremento.clone();
+    tipo.connect_changed(move |combo| {
+        inc.set_sensitive(combo.get_active() == Some(2));
+    });
+    
+    dialog.get_content_area().add(&grid);
+    grid.show_all();
+    
+    let mut config = None;
+    let result = dialog.run();
+    if result == gtk::ResponseType::Ok.into() {
+        let color_humano = if negras.get_active() { -1 } else { 1 };
+        let valor = valor.get_value_as_int() as u64;
+        let limite = match tipo.get_active() {
+            Some(1) => LimiteJuego::Profundidad(valor as u32),
+            Some(2) => LimiteJuego::Reloj { base: valor * 60_000, incremento: incremento.get_value_as_int() as u64 * 1000 },
+            _ => LimiteJuego::Tiempo(valor * 1000),
+        };
+        config = Some(ConfigJuego { color_humano, limite });
+    }
+    dialog.destroy();
+    
+    config
+}
+
+
+// Muestra un mensaje informativo
+pub fn muestra_mensaje(win_padre: &gtk::Window, msg: &str) {
+    let dialog = gtk::MessageDialog::new(Some(win_padre),
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Info,
+                gtk::ButtonsType::Ok,
+                msg);
+    dialog.run();
+    dialog.destroy();
+}
+
+
+pub fn muestra_error(win_padre: &gtk::Window, msg: &str) {
+    let dialog = gtk::MessageDialog::new(Some(win_padre),
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Error,
+                gtk::ButtonsType::Ok,
+                msg);
+    dialog.run();
     dialog.destroy();
 }
@@ -0,0 +1,355 @@
+/***************************************************************
+*
+* Editor de posiciones
+*
+* Mientras esta activo el tablero muestra la posicion que se esta
+* montando en vez de la de la partida. Las piezas se ponen con la
+* paleta (pulsando en una casilla), se mueven arrastrandolas y se
+* quitan sacandolas del tablero o con el boton derecho. El turno,
+* los enroques y la casilla al paso se eligen en el panel.
+* La posicion solo se puede jugar si valida_fen la da por buena.
+*
+***************************************************************/
+
+use gtk::prelude::*;
+use mut_static::MutStatic;
+
+use super::ajedrez;
+use super::ajedrez::{ErrorAjedrez, Piece};
+use super::ajedrez::defs;
+use super::dialogos;
+use super::inicia_gui;
+use super::utils;
+
+
+// la posicion que se esta editando
+#[derive(Clone)]
+pub struct EstadoEditor {
+    pub activo: bool,
+    pub casillas: [i16; 128],       // el tablero 0x88
+    pub pieza: i16,                 // la pieza de la paleta (0 borra)
+    pub turno: i16,                 // WHITE_TO_MOVE o BLACK_TO_MOVE
+    pub enroques: [bool; 4],        // K, Q, k, q
+    pub al_paso: Option<u8>,        // la columna de la casilla al paso
+}
+
+impl EstadoEditor {
+    pub fn init() -> EstadoEditor {
+        EstadoEditor {
+            activo: false,
+            casillas: [defs::EMPTY_SQUARE; 128],
+            pieza: defs::W_KING,
+            turno: defs::WHITE_TO_MOVE,
+            enroques: [false; 4],
+            al_paso: None,
+        }
+    }
+
+    // la FEN de la posicion del editor, con los contadores de jugadas a cero
+    pub fn fen(&self) -> String {
+        let mut piezas = "".to_string();
+        for fila in (0..8).rev() {
+            let mut vacias = 0;
+            for columna in 0..8 {
+                match Piece::from_code(self.casillas[fila * 16 + columna]) {
+                    Some(pieza) => {
+                        if vacias > 0 {
+                            piezas.push_str(&vacias.to_string());
+                            vacias = 0;
+                        }
+                        piezas.push(pieza.fen_char());
+                    },
+                    None => vacias += 1,
+                }
+            }
+            if vacias > 0 {
+                piezas.push_str(&vacias.to_string());
+            }
+            if fila > 0 {
+                piezas.push('/');
+            }
+        }
+
+        let turno = if self.turno == defs::WHITE_TO_MOVE { "w" } else { "b" };
+        let mut enroques: String = "KQkq".chars().zip(self.enroques.iter())
+                    .filter(|&(_, &puede)| puede)
+                    .map(|(letra, _)| letra)
+                    .collect();
+        if enroques.is_empty() {
+            enroques.push('-');
+        }
+        // la casilla al paso esta detras del peon que acaba de avanzar
+        let al_paso = match self.al_paso {
+            Some(columna) => format!("{}{}", (b'a' + columna) as char,
+                        if self.turno == defs::WHITE_TO_MOVE { 6 } else { 3 }),
+            None => "-".to_string(),
+        };
+        format!("{} {} {} {} 0 1", piezas, turno, enroques, al_paso)
+    }
+
+    // la FEN si la posicion se puede jugar, o lo que esta mal
+    pub fn valida(&self) -> Result<String, ErrorAjedrez> {
+        let fen = self.fen();
+        ajedrez::valida_fen(&fen)?;
+        Ok(fen)
+    }
+}
+
+lazy_static! {
+    static ref EDITOR: MutStatic<EstadoEditor> = MutStatic::new();
+}
+
+
+pub fn activo() -> bool {
+    EDITOR.read().unwrap().activo
+}
+
+pub fn casillas() -> [i16; 128] {
+    EDITOR.read().unwrap().casillas
+}
+
+pub fn pieza_en(casilla: i16) -> i16 {
+    EDITOR.read().unwrap().casillas[casilla as usize]
+}
+
+// la pieza elegida en la paleta
+pub fn pieza_elegida() -> i16 {
+    EDITOR.read().unwrap().pieza
+}
+
+pub fn pon_pieza(casilla: i16, pieza: i16) {
+    EDITOR.write().unwrap().casillas[casilla as usize] = pieza;
+}
+
+pub fn valida() -> Result<String, ErrorAjedrez> {
+    EDITOR.read().unwrap().valida()
+}
+
+// la FEN de lo que se esta editando, aunque todavia no sea valida
+pub fn fen() -> String {
+    EDITOR.read().unwrap().fen()
+}
+
+
+// tamaño de las piezas de la paleta
+const TAM_PALETA: i32 = 36;
+
+
+// ========= el panel del editor ==================
+#[derive(Clone)]
+pub struct PanelEditor {
+    caja: gtk::Box,
+    blancas: gtk::RadioButton,
+    negras: gtk::RadioButton,
+    enroques: Vec<gtk::CheckButton>,
+    al_paso: gtk::ComboBoxText,
+}
+
+impl PanelEditor {
+    // Crea los controles del editor dentro de la caja (oculta hasta que se edita).
+    // Al terminar, la posicion se carga como una partida nueva
+    pub fn crea(caja: &gtk::Box, window: &gtk::Window, board: &gtk::DrawingArea,
+                notacion: &gtk::TreeView) -> PanelEditor {
+        let _obj = EDITOR.set(EstadoEditor::init()).unwrap();
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(4);
+        grid.set_column_spacing(4);
+        grid.set_border_width(4);
+
+        // la paleta: una fila de piezas blancas, otra de negras y el boton de borrar
+        let piezas = [defs::W_KING, defs::W_QUEEN, defs::W_ROOK, defs::W_BISHOP, defs::W_KNIGHT, defs::W_PAWN];
+        let mut primero: Option<gtk::RadioButton> = None;
+        for (fila, &color) in [1, -1].iter().enumerate() {
+            for (columna, &pieza) in piezas.iter().enumerate() {
+                let pieza = pieza * color;
+                let boton = match primero.as_ref() {
+                    Some(primero) => gtk::RadioButton::new_from_widget(primero),
+                    None => gtk::RadioButton::new(),
+                };
+                boton.set_mode(false);
+                let dibujo = gtk::DrawingArea::new();
+                dibujo.set_size_request(TAM_PALETA, TAM_PALETA);
+                dibujo.connect_draw(move |widget, ctx| {
+                    inicia_gui::draw_piece(ctx, pieza, widget.get_allocated_width(), utils::load_svgs());
+                    Inhibit(false)
+                });
+                boton.add(&dibujo);
+                boton.connect_toggled(move |boton| {
+                    if boton.get_active() {
+                        EDITOR.write().unwrap().pieza = pieza;
+                    }
+                });
+                grid.attach(&boton, columna as i32, fila as i32, 1, 1);
+                if primero.is_none() {
+                    primero = Some(boton);
+                }
+            }
+        }
+        let borrar = gtk::RadioButton::new_with_label_from_widget(primero.as_ref().unwrap(), "Erase");
+        borrar.set_mode(false);
+        borrar.connect_toggled(|boton| {
+            if boton.get_active() {
+                EDITOR.write().unwrap().pieza = defs::EMPTY_SQUARE;
+            }
+        });
+        grid.attach(&borrar, 0, 2, 6, 1);
+
+        let blancas = gtk::RadioButton::new_with_label("White to move");
+        let negras = gtk::RadioButton::new_with_label_from_widget(&blancas, "Black to move");
+        grid.attach(&blancas, 0, 3, 3, 1);
+        grid.attach(&negras, 3, 3, 3, 1);
+
+        let nombres = ["White O-O", "White O-O-O", "Black O-O", "Black O-O-O"];
+        let enroques: Vec<gtk::CheckButton> = nombres.iter()
+                    .map(|nombre| gtk::CheckButton::new_with_label(nombre))
+                    .collect();
+        for (i, check) in enroques.iter().enumerate() {
+            grid.attach(check, 3 * (i as i32 % 2), 4 + i as i32 / 2, 3, 1);
+        }
+
+        let al_paso = gtk::ComboBoxText::new();
+        al_paso.append_text("-");
+        for columna in "abcdefgh".chars() {
+            al_paso.append_text(&columna.to_string());
+        }
+        al_paso.set_active(Some(0));
+        grid.attach(&gtk::Label::new(Some("En passant file")), 0, 6, 3, 1);
+        grid.attach(&al_paso, 3, 6, 3, 1);
+
+        let vaciar = gtk::Button::new_with_label("Clear board");
+        let inicial = gtk::Button::new_with_label("Initial position");
+        let hecho = gtk::Button::new_with_label("Done");
+        let cancelar = gtk::Button::new_with_label("Cancel");
+        grid.attach(&vaciar, 0, 7, 3, 1);
+        grid.attach(&inicial, 3, 7, 3, 1);
+        grid.attach(&hecho, 0, 8, 3, 1);
+        grid.attach(&cancelar, 3, 8, 3, 1);
+        caja.pack_start(&grid, false, false, 0);
+
+        let panel = PanelEditor {
+            caja: caja.clone(),
+            blancas: blancas.clone(),
+            negras,
+            enroques: enroques.clone(),
+            al_paso: al_paso.clone(),
+        };
+
+        // cada cambio en los controles se guarda y se redibuja el tablero,
+        // que es lo que actualiza la barra de estado con lo que esta mal
+        let tablero = board.clone();
+        blancas.connect_toggled(move |boton| {
+            EDITOR.write().unwrap().turno = if boton.get_active() { defs::WHITE_TO_MOVE } else { defs::BLACK_TO_MOVE };
+            tablero.queue_draw();
+        });
+        for (i, check) in enroques.iter().enumerate() {
+            let tablero = board.clone();
+            check.connect_toggled(move |check| {
+                EDITOR.write().unwrap().enroques[i] = check.get_active();
+                tablero.queue_draw();
+            });
+        }
+        let tablero = board.clone();
+        al_paso.connect_changed(move |combo| {
+            EDITOR.write().unwrap().al_paso = match combo.get_active() {
+                Some(n) if n > 0 => Some(n as u8 - 1),
+                _ => None,
+            };
+            tablero.queue_draw();
+        });
+
+        let pan = panel.clone();
+        let tablero = board.clone();
+        vaciar.connect_clicked(move |_btn| {
+            pan.carga(&mut ajedrez::Tablero::init());
+            tablero.queue_draw();
+        });
+
+        let pan = panel.clone();
+        let tablero = board.clone();
+        inicial.connect_clicked(move |_btn| {
+            pan.carga(&mut ajedrez::reset());
+            tablero.queue_draw();
+        });
+
+        let weak_window = window.downgrade();
+        let pan = panel.clone();
+        let tablero = board.clone();
+        let notacion = notacion.clone();
+        hecho.connect_clicked(move |_btn| {
+            let window = match weak_window.upgrade() {
+                Some(window) => window,
+                None => return,
+            };
+            match valida() {
+                Ok(fen) => {
+                    juega_posicion(&fen);
+                    pan.cierra();
+                    inicia_gui::muestra_notacion(&notacion);
+                    tablero.queue_draw();
+                },
+                Err(err) => dialogos::muestra_error(&window, &err.to_string()),
+            }
+        });
+
+        let pan = panel.clone();
+        let tablero = board.clone();
+        cancelar.connect_clicked(move |_btn| {
+            pan.cierra();
+            tablero.queue_draw();
+        });
+
+        panel
+    }
+
+    // empieza a editar a partir de la posicion del tablero
+    pub fn abre(&self, tablero: &ajedrez::Tablero) {
+        self.carga(&mut tablero.clone());
+        EDITOR.write().unwrap().activo = true;
+        // la caja tiene no_show_all en el .ui y show_all no la mostraria
+        self.caja.set_no_show_all(false);
+        self.caja.show_all();
+    }
+
+    pub fn cierra(&self) {
+        EDITOR.write().unwrap().activo = false;
+        self.caja.hide();
+    }
+
+    // pone la posicion en el editor y sus datos en los controles
+    pub fn carga(&self, tablero: &mut ajedrez::Tablero) {
+        let fen = ajedrez::get_fen(tablero);
+        let campos: Vec<&str> = fen.split_whitespace().collect();
+        let enroques = [campos[2].contains('K'), campos[2].contains('Q'),
+                        campos[2].contains('k'), campos[2].contains('q')];
+        let al_paso = campos[3].bytes().next().filter(|&c| c != b'-').map(|c| c - b'a');
+        {
+            let mut estado = EDITOR.write().unwrap();
+            estado.casillas = tablero.board_array;
+            estado.turno = ajedrez::turno(tablero);
+            estado.enroques = enroques;
+            estado.al_paso = al_paso;
+        }
+        // los controles guardan su valor en EDITOR al cambiar, asi que
+        // el candado tiene que estar libre
+        if campos[1] == "w" {
+            self.blancas.set_active(true);
+        }
+        else {
+            self.negras.set_active(true);
+        }
+        for (check, &puede) in self.enroques.iter().zip(enroques.iter()) {
+            check.set_active(puede);
+        }
+        self.al_paso.set_active(Some(al_paso.map_or(0, |c| c as u32 + 1)));
+    }
+}
+
+
+// la posicion editada pasa a ser una partida nueva
+fn juega_posicion(fen: &str) {
+    let mut tablero = ajedrez::Tablero::init();
+    if ajedrez::set_fen(fen, &mut tablero).is_ok() {
+        inicia_gui::carga_posicion(tablero);
+    }
+}
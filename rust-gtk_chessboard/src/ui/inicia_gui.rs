@@ -3,9 +3,21 @@ use mut_static::MutStatic;  // https://github.com/tyleo/mut_static  // https://r
 use rsvg;
 
 use super::dialogos;
+use super::editor::{self, PanelEditor};
 #[allow(unused)]
 use super::ajedrez;
+use super::ajedrez::{FinPartida, MoveResult, Color};
+use super::ajedrez::base_datos::BaseDatos;
+use super::ajedrez::arbol::{ArbolPartida, nag_texto, ColorMarca, Flecha, Circulo};
+use super::ajedrez::partida::Partida;
+use super::ajedrez::polyglot::{self, Libro};
+use super::ajedrez::eco;
+use super::ajedrez::epd::Epd;
+use super::ajedrez::motor_uci::{MotorUci, MensajeMotor, InfoMotor, Limite, orden_posicion, texto_variante, texto_puntuacion};
 use super::utils;
+use super::dialogos::LimiteJuego;
+use std::time::{Duration, Instant};
+use super::ajedrez::defs;
 
 
 #[derive(Clone)]
@@ -18,6 +30,25 @@ pub struct Variables {
     pub casilla_hasta: i16,
     pub mouse_x : f64,
     pub mouse_y : f64,
+    pub promo_desde: i16,       // la coronacion que espera a que se elija la pieza
+    pub promo_hasta: i16,
+    pub texto_estado: String,   // lo que se muestra en la barra de estado
+    pub clave_libro: Option<u64>,   // la posicion que muestra la lista del libro
+    pub destinos: Vec<(i16, bool)>, // adonde puede ir la pieza arrastrada y si alli captura
+    pub vuelta: Option<VueltaPieza>,    // la pieza soltada en mala casilla que vuelve a la suya
+    pub seleccion: Option<(i16, u64)>,  // la pieza elegida con un clic y la clave de su posicion
+    pub muestra_jaques: bool,   // marcar las piezas que dan jaque
+    pub muestra_coordenadas: bool,  // las letras y numeros en las casillas del borde
+    pub marca_desde: i16,       // la casilla donde se pulso el boton derecho para dibujar
+}
+
+// una pieza que vuelve a su casilla desde donde se solto
+#[derive(Clone, Copy)]
+pub struct VueltaPieza {
+    pub casilla: i16,
+    pub x: f64,                 // donde se solto, como mouse_x y mouse_y
+    pub y: f64,
+    pub inicio: Instant,
 }
 
 #[allow(unused)]
@@ -31,6 +62,16 @@ impl Variables {
         let casilla_hasta = 999;
         let mouse_x = 0.0;
         let mouse_y = 0.0;
+        let promo_desde = 999;
+        let promo_hasta = 999;
+        let texto_estado = "".to_string();
+        let clave_libro = None;
+        let destinos = Vec::new();
+        let vuelta = None;
+        let seleccion = None;
+        let muestra_jaques = false;
+        let muestra_coordenadas = true;
+        let marca_desde = 999;
         Variables {
             board_size,
             board_flipped,
@@ -40,6 +81,16 @@ impl Variables {
             casilla_hasta,
             mouse_x,
             mouse_y,
+            promo_desde,
+            promo_hasta,
+            texto_estado,
+            clave_libro,
+            destinos,
+            vuelta,
+            seleccion,
+            muestra_jaques,
+            muestra_coordenadas,
+            marca_desde,
         }
     }
     pub fn set_casilla_desde(&mut self, value: i16) {
@@ -69,21 +120,147 @@ lazy_static! {
 
 #[derive(Clone)]
 pub struct TableroGrafico {
-    pub tablero_g: ajedrez::Tablero,
-    pub last_move: (String, String, String, String, String),
+    pub tablero_g: ajedrez::Tablero,                // la posicion del nodo actual del arbol
+    pub last_move: Option<MoveResult>,             // la jugada que lleva al nodo actual
+    pub arbol: ArbolPartida,                        // la partida, con todas sus variantes
+    pub cabeceras: Vec<(String, String)>,           // las cabeceras PGN de la partida
+    pub resultado: String,                          // "1-0", "0-1", "1/2-1/2" o "*"
+    // lo que se calcula una vez por posicion y no en cada redibujado
+    pub fin: Option<FinPartida>,                    // como ha terminado la partida en esta posicion
+    pub estado: String,                             // turno o final, jaque y apertura
+    pub clave_libro: u64,                           // la clave Polyglot de la posicion
 }
 
 impl TableroGrafico {
-    pub fn init(tablero_g: ajedrez::Tablero) -> TableroGrafico{
-        let last_move = ("None".to_string(), 
-                        "None".to_string(), 
-                        "None".to_string(), 
-                        "None".to_string(), 
-                        "None".to_string());
-        TableroGrafico {
+    pub fn init(mut tablero_g: ajedrez::Tablero) -> TableroGrafico{
+        let last_move = None;
+        let arbol = ArbolPartida::nuevo(&mut tablero_g);
+        let mut tab = TableroGrafico {
             tablero_g,
             last_move,
+            arbol,
+            cabeceras: Vec::new(),
+            resultado: "*".to_string(),
+            fin: None,
+            estado: "".to_string(),
+            clave_libro: 0,
+        };
+        tab.actualiza_posicion();
+        tab
+    }
+    
+    // añade una jugada recien hecha en la posicion del nodo actual.
+    // Si ya existia esa continuacion solo avanzamos; si no, se crea
+    // una variante nueva (o se alarga la linea si no habia continuacion)
+    pub fn anade_jugada(&mut self, posic: ajedrez::Tablero, result: MoveResult) {
+        self.arbol.anade_hecha(&posic);
+        self.tablero_g = posic;
+        self.last_move = Some(result);
+        self.actualiza_posicion();
+    }
+    
+    // retrocede una jugada. Retorna false si estamos al principio de la partida
+    pub fn atras(&mut self) -> bool {
+        if !self.arbol.retrocede(&mut self.tablero_g) {
+            return false;
         }
+        self.actualiza_ultima();
+        true
+    }
+    
+    // avanza una jugada por la linea principal. Retorna false si estamos al final de la linea
+    pub fn adelante(&mut self) -> bool {
+        if !self.arbol.avanza(&mut self.tablero_g, 0) {
+            return false;
+        }
+        self.actualiza_ultima();
+        true
+    }
+    
+    // lleva el tablero a cualquier nodo del arbol
+    pub fn ve_a(&mut self, nodo: usize) {
+        self.arbol.ve_a(&mut self.tablero_g, nodo);
+        self.actualiza_ultima();
+    }
+    
+    // convierte en principal la variante en la que esta el nodo actual
+    pub fn promueve_variante(&mut self) -> bool {
+        let actual = self.arbol.actual;
+        self.arbol.promueve_variante(actual)
+    }
+    
+    // borra la variante en la que esta el nodo actual
+    pub fn borra_variante(&mut self) -> bool {
+        let actual = self.arbol.actual;
+        if !self.arbol.borra_variante(&mut self.tablero_g, actual) {
+            return false;
+        }
+        self.actualiza_ultima();
+        true
+    }
+    
+    // casillas 0x88 origen y destino de la jugada que lleva al nodo actual
+    pub fn casillas_ultima(&self) -> (i16, i16) {
+        if self.arbol.actual == 0 {
+            return (999, 999);
+        }
+        let movim = self.arbol.nodos[self.arbol.actual].movim;
+        (movim.from_index as i16, movim.to_index as i16)
+    }
+    
+    // como ha terminado la partida en la posicion actual, o None si se puede seguir jugando
+    pub fn fin_partida(&self) -> Option<FinPartida> {
+        self.fin
+    }
+    
+    // la partida completa, para exportarla. Si la linea principal acaba
+    // en mate o tablas, ese es el resultado
+    pub fn partida(&self) -> Partida {
+        let final_linea = self.arbol.final_linea(0);
+        let mut tablero = self.arbol.tablero_en(final_linea);
+        let resultado = match ajedrez::resultado_partida(&mut tablero) {
+            Some(fin) => fin.resultado().to_string(),
+            None => self.resultado.clone(),
+        };
+        Partida {
+            cabeceras: self.cabeceras.clone(),
+            arbol: self.arbol.clone(),
+            tablero: self.tablero_g.clone(),
+            resultado,
+        }
+    }
+    
+    fn actualiza_ultima(&mut self) {
+        let nodo = &self.arbol.nodos[self.arbol.actual];
+        self.last_move = match self.arbol.actual {
+            0 => None,
+            _ => ajedrez::describe_jugada(nodo.movim, &nodo.san),
+        };
+        self.actualiza_posicion();
+    }
+    
+    // el final, el texto de la barra de estado y la clave del libro de la
+    // posicion nueva. El dibujo del tablero solo los lee
+    fn actualiza_posicion(&mut self) {
+        let mut tablero = self.tablero_g.clone();
+        self.fin = ajedrez::resultado_partida(&mut tablero);
+        let estado = match self.fin {
+            Some(fin) => format!("{}. {}", fin.motivo(), fin.resultado()),
+            None => {
+                let bando = if ajedrez::turno(&tablero) == defs::WHITE_TO_MOVE { "blancas" } else { "negras" };
+                if ajedrez::rey_en_jaque(&mut tablero) {
+                    format!("Juegan {}. Jaque", bando)
+                }
+                else {
+                    format!("Juegan {}", bando)
+                }
+            },
+        };
+        self.estado = match eco::clasifica_linea(&self.arbol, self.arbol.actual) {
+            Some(apertura) => format!("{}    {}", estado, apertura),
+            None => estado,
+        };
+        self.clave_libro = polyglot::clave(&self.tablero_g);
     }
 }
 
@@ -91,13 +268,135 @@ lazy_static! {
     static ref TABLERO_G: MutStatic<TableroGrafico> = MutStatic::new();
 }
 
+// el fichero PGN abierto, con el indice de todas sus partidas
+lazy_static! {
+    static ref BASE_DATOS: MutStatic<Option<BaseDatos>> = MutStatic::new();
+}
+
+// el motor UCI cargado desde el menu Engine
+lazy_static! {
+    static ref MOTOR: MutStatic<Option<MotorUci>> = MutStatic::new();
+}
+
+// el libro de aperturas abierto desde el menu File
+lazy_static! {
+    static ref LIBRO: MutStatic<Option<Libro>> = MutStatic::new();
+}
+
+// cada cuanto se leen los mensajes del motor (milisegundos)
+const INTERVALO_MOTOR: u32 = 100;
+
+// lo que tarda en volver a su casilla una pieza soltada donde no puede ir
+// y cada cuanto se redibuja mientras tanto (milisegundos)
+const DURACION_VUELTA: Duration = Duration::from_millis(150);
+const INTERVALO_ANIMACION: u32 = 15;
+
+// el analisis continuo de la posicion del tablero
+#[derive(Clone)]
+pub struct EstadoAnalisis {
+    pub activo: bool,
+    pub posicion: String,                   // la orden "position" de lo que se esta analizando
+    pub tablero: ajedrez::Tablero,          // y esa misma posicion
+    pub lineas: Vec<InfoMotor>,             // la ultima info de cada linea (multipv)
+    pub busquedas: u32,                     // busquedas lanzadas cuyo bestmove no ha llegado
+}
+
+impl EstadoAnalisis {
+    pub fn init(tablero: &ajedrez::Tablero) -> EstadoAnalisis {
+        EstadoAnalisis {
+            activo: false,
+            posicion: "".to_string(),
+            tablero: tablero.clone(),
+            lineas: Vec::new(),
+            busquedas: 0,
+        }
+    }
+}
+
+lazy_static! {
+    static ref ANALISIS: MutStatic<EstadoAnalisis> = MutStatic::new();
+}
+
+// la partida contra el motor
+#[derive(Clone)]
+pub struct EstadoJuego {
+    pub activo: bool,
+    pub color_humano: i16,                  // WHITE_TO_MOVE o BLACK_TO_MOVE
+    pub limite: LimiteJuego,
+    pub reloj: [i64; 2],                    // milisegundos que quedan a blancas y negras
+    pub turno: i16,                         // el bando cuyo reloj esta corriendo
+    pub inicio_turno: Instant,
+    pub pensando: bool,                     // se ha pedido jugada al motor
+    pub clave: u64,                         // la posicion por la que se le ha preguntado
+}
+
+impl EstadoJuego {
+    pub fn init() -> EstadoJuego {
+        EstadoJuego {
+            activo: false,
+            color_humano: defs::WHITE_TO_MOVE,
+            limite: LimiteJuego::Tiempo(1000),
+            reloj: [0, 0],
+            turno: defs::WHITE_TO_MOVE,
+            inicio_turno: Instant::now(),
+            pensando: false,
+            clave: 0,
+        }
+    }
+    
+    // milisegundos que le quedan al bando, descontando lo que lleva pensando si es su turno
+    pub fn tiempo_restante(&self, bando: i16) -> i64 {
+        let indice = if bando == defs::WHITE_TO_MOVE { 0 } else { 1 };
+        let mut restante = self.reloj[indice];
+        if bando == self.turno {
+            restante -= self.inicio_turno.elapsed().as_millis() as i64;
+        }
+        restante
+    }
+}
 
-pub fn setup_gui(current_board: ajedrez::Tablero) {
+lazy_static! {
+    static ref JUEGO: MutStatic<EstadoJuego> = MutStatic::new();
+}
+
+// los widgets que dependen del motor
+#[derive(Clone)]
+struct WidgetsMotor {
+    cerrar: gtk::MenuItem,
+    jugar: gtk::MenuItem,
+    dejar_jugar: gtk::MenuItem,
+    reloj: gtk::Label,
+    tablero: gtk::DrawingArea,
+    notacion: gtk::TreeView,
+    boton_analisis: gtk::ToggleToolButton,
+    caja: gtk::Box,
+    etiqueta: gtk::Label,
+    lista: gtk::TreeView,
+    multipv: gtk::SpinButton,
+    navegacion: Vec<gtk::ToolButton>,   // moverse por la partida y cambiar sus variantes
+}
+
+// columnas de la lista de partidas. La ultima, oculta, es el numero de partida en el fichero
+const COLUMNAS_PARTIDAS: [&str; 6] = ["White", "Black", "Result", "Date", "ECO", "Event"];
+const COL_NUM_PARTIDA: u32 = 6;
+
+// columnas del arbol de notacion: el texto de la jugada y el nodo del arbol de la partida
+const COL_TEXTO_NOTACION: u32 = 0;
+const COL_NODO_NOTACION: u32 = 1;
+
+
+pub fn setup_gui(current_board: ajedrez::Tablero, base_datos: Option<BaseDatos>) {
     /* iniciamos variables publicas */
     // Llamo a .set en el MutStatic para poner datos dentro de él. Aqui solo lo inicializo.
     let _obj = VARIABLES.set(Variables::init()).unwrap();
     // creamos el interno del tablero grafico
+    // el analisis guarda su copia antes de que el tablero grafico se quede con el original
+    let _obj = ANALISIS.set(EstadoAnalisis::init(&current_board)).unwrap();
     let _obj = TABLERO_G.set(TableroGrafico::init(current_board)).unwrap();
+    let _obj = BASE_DATOS.set(None).unwrap();
+    let _obj = MOTOR.set(None).unwrap();
+    let _obj = LIBRO.set(None).unwrap();
+    let _obj = JUEGO.set(EstadoJuego::init()).unwrap();
     
     let glade_src = include_str!("chessboard.ui");
     let builder = gtk::Builder::new_from_string(glade_src);
@@ -109,50 +408,459 @@ pub fn setup_gui(current_board: ajedrez::Tablero) {
         Inhibit(false)
     });
     
+    // la lista de partidas del fichero PGN, a la derecha del tablero
+    let games_window: gtk::ScrolledWindow = builder.get_object("games_scrolled_window").expect("No se puede crear el games_scrolled_window");
+    let games_tree: gtk::TreeView = builder.get_object("games_tree_view").expect("No se puede crear el games_tree_view");
+    let games_store = crea_lista_partidas(&games_tree);
+    
+    // la notacion de la partida, con las variantes como ramas del arbol
+    let notation_tree: gtk::TreeView = builder.get_object("notation_tree_view").expect("No se puede crear el notation_tree_view");
+    crea_columna_notacion(&notation_tree);
+    
     let open_pgn_item: gtk::MenuItem = builder.get_object("open_pgn_menu_item").expect("No se puede crear el open_pgn_menu_item");
-    gtk::WidgetExt::set_tooltip_markup(&open_pgn_item, Some("the implementation is not complete"));
+    gtk::WidgetExt::set_tooltip_markup(&open_pgn_item, Some("Abre un fichero PGN con una o varias partidas"));
     let weak_window = window.downgrade();
+    let store = games_store.clone();
+    let panel = games_window.clone();
+    let notacion = notation_tree.clone();
     open_pgn_item.connect_activate(move |mitem| {
         let window = match weak_window.upgrade() {
             Some(window) => window,
             None => return,
         };
-        dialogos::open_pgn_callback(mitem, &window);
+        if let Some(base) = dialogos::open_pgn_callback(mitem, &window) {
+            if let Err(msg) = abre_base_datos(base, &store, &panel) {
+                dialogos::muestra_error(&window, &msg);
+            }
+            muestra_notacion(&notacion);
+            window.queue_draw();
+        }
+    });
+    
+    let save_pgn_item: gtk::MenuItem = builder.get_object("save_pgn_menu_item").expect("No se puede crear el save_pgn_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&save_pgn_item, Some("Guarda la partida en un fichero PGN"));
+    let weak_window = window.downgrade();
+    save_pgn_item.connect_activate(move |mitem| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        let texto_pgn;
+        {
+            let tab = TABLERO_G.read().unwrap();
+            texto_pgn = ajedrez::partida::escribe_pgn(&tab.partida());
+        }
+        dialogos::save_pgn_callback(mitem, &window, &texto_pgn);
+    });
+    
+    // las jugadas del libro de aperturas para la posicion del tablero
+    let book_box: gtk::Box = builder.get_object("book_box").expect("No se puede crear el book_box");
+    let book_label: gtk::Label = builder.get_object("book_label").expect("No se puede crear el book_label");
+    let book_tree: gtk::TreeView = builder.get_object("book_tree_view").expect("No se puede crear el book_tree_view");
+    crea_lista_libro(&book_tree);
+    let open_book_item: gtk::MenuItem = builder.get_object("open_book_menu_item").expect("No se puede crear el open_book_menu_item");
+    let close_book_item: gtk::MenuItem = builder.get_object("close_book_menu_item").expect("No se puede crear el close_book_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&open_book_item, Some("Abre un libro de aperturas Polyglot (.bin)"));
+    let weak_window = window.downgrade();
+    let caja = book_box.clone();
+    let cerrar = close_book_item.clone();
+    open_book_item.connect_activate(move |mitem| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        if let Some(libro) = dialogos::open_book_callback(mitem, &window) {
+            {
+                let mut obj = LIBRO.write().unwrap();
+                *obj = Some(libro);
+            }
+            VARIABLES.write().unwrap().clave_libro = None;
+            cerrar.set_sensitive(true);
+            // la caja tiene no_show_all en el .ui y show_all no la mostraria
+            caja.set_no_show_all(false);
+            caja.show_all();
+            window.queue_draw();
+        }
+    });
+    
+    let caja = book_box.clone();
+    close_book_item.connect_activate(move |mitem| {
+        {
+            let mut obj = LIBRO.write().unwrap();
+            *obj = None;
+        }
+        mitem.set_sensitive(false);
+        caja.hide();
+    });
+    
+    let load_engine_item: gtk::MenuItem = builder.get_object("load_engine_menu_item").expect("No se puede crear el load_engine_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&load_engine_item, Some("Arranca un motor UCI (Stockfish...)"));
+    let analysis_tree: gtk::TreeView = builder.get_object("analysis_tree_view").expect("No se puede crear el analysis_tree_view");
+    crea_lista_analisis(&analysis_tree);
+    let widgets_motor = WidgetsMotor {
+        cerrar: builder.get_object("close_engine_menu_item").expect("No se puede crear el close_engine_menu_item"),
+        jugar: builder.get_object("play_engine_menu_item").expect("No se puede crear el play_engine_menu_item"),
+        dejar_jugar: builder.get_object("stop_playing_menu_item").expect("No se puede crear el stop_playing_menu_item"),
+        reloj: builder.get_object("clock_label").expect("No se puede crear el clock_label"),
+        tablero: builder.get_object("board_drawing_area").expect("No se puede crear el board_drawing_area"),
+        notacion: builder.get_object("notation_tree_view").expect("No se puede crear el notation_tree_view"),
+        boton_analisis: builder.get_object("analyse_button").expect("No se puede crear el analyse_button"),
+        caja: builder.get_object("analysis_box").expect("No se puede crear el analysis_box"),
+        etiqueta: builder.get_object("analysis_label").expect("No se puede crear el analysis_label"),
+        lista: analysis_tree,
+        multipv: builder.get_object("multipv_spin_button").expect("No se puede crear el multipv_spin_button"),
+        navegacion: ["go_start_button", "go_back_button", "go_next_button", "go_end_button",
+                     "promote_variation_button", "delete_variation_button"].iter()
+                .map(|id| builder.get_object(id).unwrap_or_else(|| panic!("No se puede crear el {}", id)))
+                .collect(),
+    };
+    
+    let weak_window = window.downgrade();
+    let widgets = widgets_motor.clone();
+    load_engine_item.connect_activate(move |mitem| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        if let Some(motor) = dialogos::load_engine_callback(mitem, &window) {
+            window.set_title(&format!("Tablero - {}", motor.nombre));
+            widgets.cerrar.set_sensitive(true);
+            {
+                let mut obj = MOTOR.write().unwrap();
+                *obj = Some(motor);
+            }
+            // si estabamos analizando, seguimos con el motor nuevo
+            let mut estado = ANALISIS.write().unwrap();
+            estado.busquedas = 0;
+            estado.posicion.clear();
+        }
+    });
+    
+    let weak_window = window.downgrade();
+    let widgets = widgets_motor.clone();
+    widgets_motor.cerrar.connect_activate(move |_mitem| {
+        if let Some(window) = weak_window.upgrade() {
+            cierra_motor(&window, &widgets);
+        }
+    });
+    
+    gtk::WidgetExt::set_tooltip_markup(&widgets_motor.boton_analisis, Some("Analiza la posicion con el motor"));
+    let weak_window = window.downgrade();
+    let widgets = widgets_motor.clone();
+    widgets_motor.boton_analisis.connect_toggled(move |boton| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        if boton.get_active() {
+            let jugando = JUEGO.read().unwrap().activo;
+            if jugando {
+                boton.set_active(false);
+                dialogos::muestra_error(&window, "No se puede analizar mientras se juega contra el motor");
+                return;
+            }
+            let hay_motor = MOTOR.read().unwrap().is_some();
+            if !hay_motor {
+                boton.set_active(false);
+                dialogos::muestra_error(&window, "Primero hay que cargar un motor (Engine > Load engine...)");
+                return;
+            }
+            empieza_analisis(&widgets);
+        }
+        else {
+            termina_analisis(&widgets);
+        }
+    });
+    
+    let weak_window = window.downgrade();
+    let widgets = widgets_motor.clone();
+    widgets_motor.jugar.connect_activate(move |_mitem| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        let hay_motor = MOTOR.read().unwrap().is_some();
+        if !hay_motor {
+            dialogos::muestra_error(&window, "Primero hay que cargar un motor (Engine > Load engine...)");
+            return;
+        }
+        if editor::activo() {
+            dialogos::muestra_error(&window, "Primero hay que terminar de editar la posicion");
+            return;
+        }
+        if let Some(config) = dialogos::play_engine_dialog(&window) {
+            empieza_juego(&window, &widgets, config);
+        }
+    });
+    
+    let widgets = widgets_motor.clone();
+    widgets_motor.dejar_jugar.connect_activate(move |_mitem| {
+        termina_juego(&widgets);
+    });
+    
+    // cambiar el numero de lineas reinicia el analisis
+    widgets_motor.multipv.connect_value_changed(move |_spin| {
+        let mut estado = ANALISIS.write().unwrap();
+        estado.posicion.clear();
+    });
+    
+    // los mensajes del motor se leen periodicamente, sin bloquear el bucle de GTK
+    let weak_window = window.downgrade();
+    let widgets = widgets_motor.clone();
+    gtk::timeout_add(INTERVALO_MOTOR, move || {
+        if let Some(window) = weak_window.upgrade() {
+            atiende_motor(&window, &widgets);
+        }
+        gtk::Continue(true)
+    });
+    
+    let board_display: gtk::DrawingArea = builder.get_object("board_drawing_area").expect("No se puede crear el board_drawing_area");
+    gtk::WidgetExtManual::add_events(&board_display, 
+                gdk::EventMask::POINTER_MOTION_MASK | 
+                gdk::EventMask::BUTTON_PRESS_MASK | 
+                gdk::EventMask::BUTTON_RELEASE_MASK);
+    
+    // el editor de posiciones, con su panel encima del libro de aperturas
+    let editor_box: gtk::Box = builder.get_object("editor_box").expect("No se puede crear el editor_box");
+    let panel_editor = PanelEditor::crea(&editor_box, &window, &board_display, &notation_tree);
+    let setup_item: gtk::MenuItem = builder.get_object("setup_position_menu_item").expect("No se puede crear el setup_position_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&setup_item, Some("Coloca las piezas de una posicion para jugarla o analizarla"));
+    let weak_window = window.downgrade();
+    let board = board_display.clone();
+    let panel = panel_editor.clone();
+    setup_item.connect_activate(move |_mitem| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        if JUEGO.read().unwrap().activo {
+            dialogos::muestra_error(&window, "No se puede editar la posicion mientras se juega contra el motor");
+            return;
+        }
+        let tablero = TABLERO_G.read().unwrap().tablero_g.clone();
+        VARIABLES.write().unwrap().set_drag_source(999);
+        panel.abre(&tablero);
+        board.queue_draw();
+    });
+    
+    // la FEN de la posicion por el portapapeles
+    let copy_fen_item: gtk::MenuItem = builder.get_object("copy_fen_menu_item").expect("No se puede crear el copy_fen_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&copy_fen_item, Some("Copia la FEN de la posicion del tablero"));
+    copy_fen_item.connect_activate(move |_mitem| {
+        let fen = if editor::activo() {
+            editor::fen()
+        }
+        else {
+            ajedrez::get_fen(&mut TABLERO_G.write().unwrap().tablero_g)
+        };
+        gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&fen);
+    });
+    
+    let paste_fen_item: gtk::MenuItem = builder.get_object("paste_fen_menu_item").expect("No se puede crear el paste_fen_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&paste_fen_item, Some("Pone en el tablero la posicion FEN o EPD del portapapeles"));
+    let weak_window = window.downgrade();
+    let board = board_display.clone();
+    let notacion = notation_tree.clone();
+    paste_fen_item.connect_activate(move |_mitem| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        if JUEGO.read().unwrap().activo {
+            dialogos::muestra_error(&window, "No se puede cambiar la posicion mientras se juega contra el motor");
+            return;
+        }
+        let texto = match gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).wait_for_text() {
+            Some(texto) => texto.to_string(),
+            None => {
+                dialogos::muestra_error(&window, "El portapapeles no tiene texto");
+                return;
+            },
+        };
+        let mut tablero = match lee_posicion(&texto) {
+            Ok(tablero) => tablero,
+            Err(err) => {
+                dialogos::muestra_error(&window, &err.to_string());
+                return;
+            },
+        };
+        // mientras se edita la posicion pegada va al editor
+        if editor::activo() {
+            panel_editor.carga(&mut tablero);
+        }
+        else {
+            carga_posicion(tablero);
+            muestra_notacion(&notacion);
+        }
+        board.queue_draw();
+    });
+    
+    // borrar las flechas y circulos de la posicion
+    let clear_marks_item: gtk::MenuItem = builder.get_object("clear_marks_menu_item").expect("No se puede crear el clear_marks_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&clear_marks_item, Some("Borra las flechas y circulos dibujados con el boton derecho en esta posicion"));
+    let board = board_display.clone();
+    clear_marks_item.connect_activate(move |_mitem| {
+        {
+            let mut tab = TABLERO_G.write().unwrap();
+            let actual = tab.arbol.actual;
+            tab.arbol.nodos[actual].flechas.clear();
+            tab.arbol.nodos[actual].circulos.clear();
+        }
+        board.queue_draw();
+    });
+    
+    // las coordenadas del tablero
+    let show_coordinates_item: gtk::CheckMenuItem = builder.get_object("show_coordinates_menu_item").expect("No se puede crear el show_coordinates_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&show_coordinates_item, Some("Muestra las letras de las columnas y los numeros de las filas"));
+    let board = board_display.clone();
+    show_coordinates_item.connect_toggled(move |mitem| {
+        VARIABLES.write().unwrap().muestra_coordenadas = mitem.get_active();
+        board.queue_draw();
+    });
+    
+    // marcar las piezas que dan jaque
+    let show_checkers_item: gtk::CheckMenuItem = builder.get_object("show_checkers_menu_item").expect("No se puede crear el show_checkers_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&show_checkers_item, Some("Marca las piezas que dan jaque y su linea hasta el rey"));
+    let board = board_display.clone();
+    show_checkers_item.connect_toggled(move |mitem| {
+        VARIABLES.write().unwrap().muestra_jaques = mitem.get_active();
+        board.queue_draw();
     });
     
     let go_end_button: gtk::ToolButton = builder.get_object("go_end_button").expect("No se puede crear el go_end_button");
-    gtk::WidgetExt::set_tooltip_markup(&go_end_button, Some("Opción no activa"));
+    gtk::WidgetExt::set_tooltip_markup(&go_end_button, Some("Ir al final de la partida"));
+    let board = board_display.clone();
+    let notacion = notation_tree.clone();
     go_end_button.connect_clicked(move |_btn| {
-        // llamar a go_end_button_click_callback
+        go_end_button_click_callback(&board, &notacion);
     });
     
     let go_next_button: gtk::ToolButton = builder.get_object("go_next_button").expect("No se puede crear el go_next_button");
-    gtk::WidgetExt::set_tooltip_markup(&go_next_button, Some("Opción no activa"));
+    gtk::WidgetExt::set_tooltip_markup(&go_next_button, Some("Jugada siguiente"));
+    let board = board_display.clone();
+    let notacion = notation_tree.clone();
     go_next_button.connect_clicked(move |_btn| {
-        // llamar a muestra_tablero::go_next_button_click_callback
+        go_next_button_click_callback(&board, &notacion);
     });
     
     let go_back_button: gtk::ToolButton = builder.get_object("go_back_button").expect("No se puede crear el go_back_button");
-    gtk::WidgetExt::set_tooltip_markup(&go_back_button, Some("Opción no activa"));
+    gtk::WidgetExt::set_tooltip_markup(&go_back_button, Some("Jugada anterior"));
+    let board = board_display.clone();
+    let notacion = notation_tree.clone();
     go_back_button.connect_clicked(move |_btn| {
-        // llamar a go_back_button_click_callback
+        go_back_button_click_callback(&board, &notacion);
     });
     
     let go_start_button: gtk::ToolButton = builder.get_object("go_start_button").expect("No se puede crear el go_start_button");
-    gtk::WidgetExt::set_tooltip_markup(&go_start_button, Some("Opción no activa"));
+    gtk::WidgetExt::set_tooltip_markup(&go_start_button, Some("Ir al principio de la partida"));
+    let board = board_display.clone();
+    let notacion = notation_tree.clone();
     go_start_button.connect_clicked(move |_btn| {
-        // llamar a go_start_button_click_callback
+        go_start_button_click_callback(&board, &notacion);
     });
     
-    let board_display: gtk::DrawingArea = builder.get_object("board_drawing_area").expect("No se puede crear el board_drawing_area");
-    gtk::WidgetExtManual::add_events(&board_display, 
-                gdk::EventMask::POINTER_MOTION_MASK | 
-                gdk::EventMask::BUTTON_PRESS_MASK | 
-                gdk::EventMask::BUTTON_RELEASE_MASK);
+    let promote_button: gtk::ToolButton = builder.get_object("promote_variation_button").expect("No se puede crear el promote_variation_button");
+    gtk::WidgetExt::set_tooltip_markup(&promote_button, Some("Convierte la variante en la linea principal"));
+    let notacion = notation_tree.clone();
+    promote_button.connect_clicked(move |_btn| {
+        {
+            let mut obj = TABLERO_G.write().unwrap();
+            obj.promueve_variante();
+        }
+        muestra_notacion(&notacion);
+    });
+    
+    let delete_button: gtk::ToolButton = builder.get_object("delete_variation_button").expect("No se puede crear el delete_variation_button");
+    gtk::WidgetExt::set_tooltip_markup(&delete_button, Some("Borra la variante"));
+    let board = board_display.clone();
+    let notacion = notation_tree.clone();
+    delete_button.connect_clicked(move |_btn| {
+        {
+            let mut obj = TABLERO_G.write().unwrap();
+            obj.borra_variante();
+        }
+        marca_ultima_jugada();
+        muestra_notacion(&notacion);
+        board.queue_draw();
+    });
     
+    // doble click en una jugada de la notacion para ir a esa posicion
+    let board = board_display.clone();
+    notation_tree.connect_row_activated(move |tree, path, _column| {
+        // jugando contra el motor el tablero se queda en la posicion de la partida
+        if JUEGO.read().unwrap().activo {
+            return;
+        }
+        let model = match tree.get_model() {
+            Some(model) => model,
+            None => return,
+        };
+        let iter = match model.get_iter(path) {
+            Some(iter) => iter,
+            None => return,
+        };
+        let nodo = match model.get_value(&iter, COL_NODO_NOTACION as i32).get::<u32>() {
+            Some(nodo) => nodo as usize,
+            None => return,
+        };
+        {
+            let mut obj = TABLERO_G.write().unwrap();
+            obj.ve_a(nodo);
+        }
+        marca_ultima_jugada();
+        muestra_notacion(tree);
+        board.queue_draw();
+    });
+    
+    // doble click en una partida de la lista para cargarla en el tablero
+    let weak_window = window.downgrade();
+    let board = board_display.clone();
+    let notacion = notation_tree.clone();
+    games_tree.connect_row_activated(move |tree, path, _column| {
+        let window = match weak_window.upgrade() {
+            Some(window) => window,
+            None => return,
+        };
+        if JUEGO.read().unwrap().activo {
+            dialogos::muestra_error(&window, "No se puede cargar otra partida mientras se juega contra el motor");
+            return;
+        }
+        let model = match tree.get_model() {
+            Some(model) => model,
+            None => return,
+        };
+        let iter = match model.get_iter(path) {
+            Some(iter) => iter,
+            None => return,
+        };
+        let num_partida = match model.get_value(&iter, COL_NUM_PARTIDA as i32).get::<u32>() {
+            Some(num) => num as usize,
+            None => return,
+        };
+        
+        let leida;
+        {
+            let base = BASE_DATOS.read().unwrap();
+            leida = match base.as_ref() {
+                Some(base) => base.carga_partida(num_partida),
+                None => return,
+            };
+        }
+        match leida {
+            Ok(partida) => carga_partida(partida),
+            Err(msg) => dialogos::muestra_error(&window, &msg),
+        }
+        muestra_notacion(&notacion);
+        board.queue_draw();
+    });
     
+    // la barra de estado dice a quien le toca o como ha terminado la partida.
+    // Se actualiza cada vez que se redibuja el tablero
+    let status_bar: gtk::Statusbar = builder.get_object("status_bar").expect("No se puede crear el status_bar");
     board_display.connect_draw(move |widget, ctx| {
         board_draw_callback(widget, ctx);
+        muestra_estado(&status_bar);
+        muestra_libro(&book_tree, &book_label);
         Inhibit(false)
     });
     
@@ -161,8 +869,15 @@ pub fn setup_gui(current_board: ajedrez::Tablero) {
         Inhibit(false)
     });
     
+    // eleccion de la pieza al coronar un peon
+    let promo_popover = crea_popover_promocion(&board_display, &notation_tree);
+    
+    let notacion = notation_tree.clone();
     board_display.connect_button_release_event(move |widget, event| {
-        board_mouse_up_callback(widget, event);
+        if board_mouse_up_callback(widget, event) {
+            muestra_notacion(&notacion);
+        }
+        muestra_popover_promocion(widget, &promo_popover);
         Inhibit(false)
     });
     
@@ -171,17 +886,827 @@ pub fn setup_gui(current_board: ajedrez::Tablero) {
         Inhibit(false)
     });
     
-    let flip_button: gtk::ToolButton = builder.get_object("flip_board_button").expect("No se puede crear el open_pgn_menu_item");
-    gtk::WidgetExt::set_tooltip_markup(&flip_button, Some("Da la vuelta al tablero"));
-    flip_button.connect_clicked(move |_btn| {
-        {
-            let mut var = VARIABLES.write().unwrap();
-            var.board_flipped = !var.board_flipped;
-        }
-        board_display.queue_draw();
+    let flip_button: gtk::ToolButton = builder.get_object("flip_board_button").expect("No se puede crear el open_pgn_menu_item");
+    gtk::WidgetExt::set_tooltip_markup(&flip_button, Some("Da la vuelta al tablero"));
+    flip_button.connect_clicked(move |_btn| {
+        {
+            let mut var = VARIABLES.write().unwrap();
+            var.board_flipped = !var.board_flipped;
+        }
+        board_display.queue_draw();
+    });
+    
+    if let Some(base) = base_datos {
+        if let Err(msg) = abre_base_datos(base, &games_store, &games_window) {
+            eprintln!("{}", msg);
+        }
+    }
+    muestra_notacion(&notation_tree);
+    
+    window.show_all();
+}
+
+
+// Lee los mensajes pendientes del motor y los reparte.
+// Si se esta analizando y la posicion del tablero ha cambiado, reinicia el analisis
+fn atiende_motor(window: &gtk::Window, widgets: &WidgetsMotor) {
+    let mensajes;
+    {
+        let motor = MOTOR.read().unwrap();
+        mensajes = match motor.as_ref() {
+            Some(motor) => motor.mensajes(),
+            None => return,
+        };
+    }
+    
+    let mut nuevas_lineas = false;
+    for mensaje in mensajes {
+        match mensaje {
+            MensajeMotor::Terminado => {
+                cierra_motor(window, widgets);
+                dialogos::muestra_error(window, "El motor ha terminado inesperadamente");
+                return;
+            },
+            MensajeMotor::Info(info) => {
+                let mut estado = ANALISIS.write().unwrap();
+                // las lineas de una busqueda ya parada no valen
+                if estado.activo && estado.busquedas == 1 {
+                    let n = info.multipv.max(1) as usize;
+                    if estado.lineas.len() < n {
+                        estado.lineas.resize(n, InfoMotor::default());
+                    }
+                    estado.lineas[n - 1] = info;
+                    nuevas_lineas = true;
+                }
+            },
+            MensajeMotor::MejorJugada { jugada, .. } => {
+                let ultima = {
+                    let mut estado = ANALISIS.write().unwrap();
+                    estado.busquedas = estado.busquedas.saturating_sub(1);
+                    estado.busquedas == 0
+                };
+                // solo vale la respuesta de la ultima busqueda pedida, y solo
+                // si el tablero sigue en la posicion que se mando al motor
+                let (pensando, clave) = {
+                    let juego = JUEGO.read().unwrap();
+                    (juego.pensando, juego.clave)
+                };
+                if ultima && pensando {
+                    JUEGO.write().unwrap().pensando = false;
+                    let misma = ajedrez::clave_zobrist(&TABLERO_G.read().unwrap().tablero_g) == clave;
+                    if misma {
+                        // "(none)": el motor no tiene jugada porque esta mate o ahogado
+                        if jugada == "(none)" || jugada == "0000" {
+                            motor_sin_jugada(window, widgets);
+                            return;
+                        }
+                        juega_motor(&jugada, widgets);
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+    
+    let (activo, posicion) = {
+        let estado = ANALISIS.read().unwrap();
+        (estado.activo, estado.posicion.clone())
+    };
+    if activo {
+        let tablero = TABLERO_G.read().unwrap().tablero_g.clone();
+        if orden_posicion(&tablero) != posicion {
+            reinicia_analisis(window, widgets, tablero);
+            nuevas_lineas = true;
+        }
+    }
+    if nuevas_lineas {
+        muestra_analisis(widgets);
+    }
+    
+    atiende_juego(window, widgets);
+}
+
+
+// empieza a jugar contra el motor desde la posicion del tablero. Las
+// jugadas se anaden al arbol de la partida a partir del nodo actual
+fn empieza_juego(window: &gtk::Window, widgets: &WidgetsMotor, config: dialogos::ConfigJuego) {
+    widgets.boton_analisis.set_active(false);
+    
+    let nombre_motor;
+    let preparado = {
+        let mut motor = MOTOR.write().unwrap();
+        match motor.as_mut() {
+            Some(motor) => {
+                nombre_motor = motor.nombre.clone();
+                motor.nueva_partida()
+            },
+            None => return,
+        }
+    };
+    if let Err(msg) = preparado {
+        dialogos::muestra_error(window, &msg);
+        return;
+    }
+    if let Some(fin) = TABLERO_G.read().unwrap().fin_partida() {
+        dialogos::muestra_error(window, &format!("La partida ya ha terminado: {}", fin.motivo()));
+        return;
+    }
+    
+    let (blancas, negras) = if config.color_humano == defs::WHITE_TO_MOVE {
+        ("Player".to_string(), nombre_motor)
+    }
+    else {
+        (nombre_motor, "Player".to_string())
+    };
+    let turno = {
+        let mut tab = TABLERO_G.write().unwrap();
+        tab.cabeceras.retain(|(nombre, _)| nombre != "White" && nombre != "Black");
+        tab.cabeceras.insert(0, ("Black".to_string(), negras));
+        tab.cabeceras.insert(0, ("White".to_string(), blancas));
+        tab.resultado = "*".to_string();
+        ajedrez::turno(&tab.tablero_g)
+    };
+    
+    {
+        let mut var = VARIABLES.write().unwrap();
+        var.board_flipped = config.color_humano == defs::BLACK_TO_MOVE;
+        var.set_drag_source(999);
+        var.seleccion = None;
+    }
+    {
+        let mut juego = JUEGO.write().unwrap();
+        let base = match config.limite {
+            LimiteJuego::Reloj { base, .. } => base as i64,
+            _ => 0,
+        };
+        *juego = EstadoJuego {
+            activo: true,
+            color_humano: config.color_humano,
+            limite: config.limite,
+            reloj: [base, base],
+            turno,
+            inicio_turno: Instant::now(),
+            pensando: false,
+            clave: 0,
+        };
+    }
+    widgets.jugar.set_sensitive(false);
+    widgets.dejar_jugar.set_sensitive(true);
+    // mientras se juega el tablero no se mueve de la posicion de la partida
+    for boton in &widgets.navegacion {
+        boton.set_sensitive(false);
+    }
+    muestra_notacion(&widgets.notacion);
+    widgets.tablero.queue_draw();
+}
+
+
+// Lleva la partida contra el motor: el reloj, el final de la partida
+// y pedir la jugada al motor cuando le toca
+fn atiende_juego(window: &gtk::Window, widgets: &WidgetsMotor) {
+    if !JUEGO.read().unwrap().activo {
+        return;
+    }
+    let mut tablero = TABLERO_G.read().unwrap().tablero_g.clone();
+    let turno = ajedrez::turno(&tablero);
+    
+    let mut sin_tiempo: Option<i16> = None;
+    let (color_humano, pensando) = {
+        let mut juego = JUEGO.write().unwrap();
+        // al cambiar el turno se descuenta el tiempo gastado y se suma el incremento
+        if turno != juego.turno {
+            let anterior = juego.turno;
+            let restante = juego.tiempo_restante(anterior);
+            let incremento = match juego.limite {
+                LimiteJuego::Reloj { incremento, .. } => incremento as i64,
+                _ => 0,
+            };
+            let indice = if anterior == defs::WHITE_TO_MOVE { 0 } else { 1 };
+            juego.reloj[indice] = restante + incremento;
+            juego.turno = turno;
+            juego.inicio_turno = Instant::now();
+        }
+        if let LimiteJuego::Reloj { .. } = juego.limite {
+            if juego.tiempo_restante(turno) <= 0 {
+                sin_tiempo = Some(turno);
+            }
+            widgets.reloj.set_text(&format!("{}  -  {}",
+                        texto_reloj(juego.tiempo_restante(defs::WHITE_TO_MOVE)),
+                        texto_reloj(juego.tiempo_restante(defs::BLACK_TO_MOVE))));
+        }
+        (juego.color_humano, juego.pensando)
+    };
+    
+    if let Some(bando) = sin_tiempo {
+        let resultado = if bando == defs::WHITE_TO_MOVE { "0-1" } else { "1-0" };
+        fin_juego(window, widgets, resultado, "Se ha acabado el tiempo");
+        return;
+    }
+    if let Some(fin) = ajedrez::resultado_partida(&mut tablero) {
+        fin_juego(window, widgets, fin.resultado(), fin.motivo());
+        return;
+    }
+    
+    if turno != color_humano && !pensando {
+        pide_jugada_motor(window, widgets, &tablero);
+    }
+}
+
+
+// Manda la posicion al motor y le pide que juegue.
+// Mientras la posicion este en el libro de aperturas se juega del libro
+fn pide_jugada_motor(window: &gtk::Window, widgets: &WidgetsMotor, tablero: &ajedrez::Tablero) {
+    let de_libro = match LIBRO.read().unwrap().as_ref() {
+        Some(libro) => libro.elige(tablero),
+        None => None,
+    };
+    if let Some(jugada) = de_libro {
+        juega_motor(&jugada.uci(), widgets);
+        return;
+    }
+    
+    let limite = {
+        let juego = JUEGO.read().unwrap();
+        match juego.limite {
+            LimiteJuego::Tiempo(ms) => Limite::Tiempo(ms),
+            LimiteJuego::Profundidad(prof) => Limite::Profundidad(prof),
+            LimiteJuego::Reloj { incremento, .. } => Limite::Reloj {
+                blancas: juego.tiempo_restante(defs::WHITE_TO_MOVE).max(0) as u64,
+                negras: juego.tiempo_restante(defs::BLACK_TO_MOVE).max(0) as u64,
+                inc_blancas: incremento,
+                inc_negras: incremento,
+            },
+        }
+    };
+    
+    let mut estado = ANALISIS.write().unwrap();
+    let enviado = {
+        let mut motor = MOTOR.write().unwrap();
+        match motor.as_mut() {
+            Some(motor) => {
+                let parado = if estado.busquedas > 0 { motor.para() } else { Ok(()) };
+                parado.and_then(|_| motor.opcion("MultiPV", "1"))
+                      .and_then(|_| motor.posicion(tablero))
+                      .and_then(|_| motor.busca(limite))
+            },
+            None => Err("No hay ningun motor cargado".to_string()),
+        }
+    };
+    match enviado {
+        Ok(_) => {
+            estado.busquedas += 1;
+            let mut juego = JUEGO.write().unwrap();
+            juego.pensando = true;
+            juego.clave = ajedrez::clave_zobrist(tablero);
+        },
+        Err(msg) => {
+            drop(estado);
+            termina_juego(widgets);
+            dialogos::muestra_error(window, &msg);
+        },
+    }
+}
+
+
+// hace en el tablero la jugada UCI que ha elegido el motor
+fn juega_motor(jugada: &str, widgets: &WidgetsMotor) {
+    if jugada.len() < 4 || !jugada.is_ascii() {
+        return;
+    }
+    let promo = jugada[4..].to_uppercase();
+    let promo = if promo.is_empty() { "Q" } else { promo.as_str() };
+    if juega_jugada(&jugada[0..2], &jugada[2..4], promo) {
+        muestra_notacion(&widgets.notacion);
+        widgets.tablero.queue_draw();
+    }
+}
+
+
+// el motor contesta sin jugada: la partida ha terminado en el tablero o,
+// si no, el motor no sabe seguir y se deja de jugar
+fn motor_sin_jugada(window: &gtk::Window, widgets: &WidgetsMotor) {
+    let mut tablero = TABLERO_G.read().unwrap().tablero_g.clone();
+    match ajedrez::resultado_partida(&mut tablero) {
+        Some(fin) => fin_juego(window, widgets, fin.resultado(), fin.motivo()),
+        None => {
+            termina_juego(widgets);
+            dialogos::muestra_error(window, "El motor no ha dado ninguna jugada");
+        },
+    }
+    widgets.tablero.queue_draw();
+}
+
+
+// la partida ha terminado: se anota el resultado y se avisa
+fn fin_juego(window: &gtk::Window, widgets: &WidgetsMotor, resultado: &str, motivo: &str) {
+    termina_juego(widgets);
+    {
+        let mut obj = TABLERO_G.write().unwrap();
+        obj.resultado = resultado.to_string();
+    }
+    dialogos::muestra_mensaje(window, &format!("{}: {}", motivo, resultado));
+}
+
+
+// deja de jugar contra el motor. La partida queda en el tablero
+fn termina_juego(widgets: &WidgetsMotor) {
+    let pensando = {
+        let mut juego = JUEGO.write().unwrap();
+        juego.activo = false;
+        let pensando = juego.pensando;
+        juego.pensando = false;
+        pensando
+    };
+    if pensando {
+        let mut motor = MOTOR.write().unwrap();
+        if let Some(motor) = motor.as_mut() {
+            let _ = motor.para();
+        }
+    }
+    widgets.jugar.set_sensitive(true);
+    widgets.dejar_jugar.set_sensitive(false);
+    for boton in &widgets.navegacion {
+        boton.set_sensitive(true);
+    }
+    widgets.reloj.set_text("");
+}
+
+
+// pone en la barra de estado a quien le toca mover, o el resultado
+// y el motivo si la partida ha terminado en la posicion del tablero,
+// y la apertura de la linea que lleva a ella
+fn muestra_estado(barra: &gtk::Statusbar) {
+    let texto = if editor::activo() {
+        match editor::valida() {
+            Ok(_) => "Editando la posicion".to_string(),
+            Err(err) => format!("Editando la posicion. {}", err),
+        }
+    }
+    else {
+        TABLERO_G.read().unwrap().estado.clone()
+    };
+    
+    // cambiar el texto redimensiona la barra, y eso vuelve a dibujar el tablero
+    let mut var = VARIABLES.write().unwrap();
+    if var.texto_estado == texto {
+        return;
+    }
+    let contexto = barra.get_context_id("partida");
+    barra.pop(contexto);
+    barra.push(contexto, &texto);
+    var.texto_estado = texto;
+}
+
+
+// columnas de la lista del libro: jugada, peso y porcentaje del total
+fn crea_lista_libro(tree: &gtk::TreeView) {
+    for (i, titulo) in ["Move", "Weight", "%"].iter().enumerate() {
+        let column = gtk::TreeViewColumn::new();
+        let cell = gtk::CellRendererText::new();
+        column.pack_start(&cell, true);
+        column.add_attribute(&cell, "text", i as i32);
+        column.set_title(titulo);
+        column.set_resizable(true);
+        tree.append_column(&column);
+    }
+}
+
+
+// Pone en la lista las jugadas del libro para la posicion del tablero.
+// Se llama al redibujar, asi que solo se rehace si la posicion ha cambiado
+fn muestra_libro(tree: &gtk::TreeView, etiqueta: &gtk::Label) {
+    let libro = LIBRO.read().unwrap();
+    let libro = match libro.as_ref() {
+        Some(libro) => libro,
+        None => return,
+    };
+    let clave = TABLERO_G.read().unwrap().clave_libro;
+    {
+        let mut var = VARIABLES.write().unwrap();
+        if var.clave_libro == Some(clave) {
+            return;
+        }
+        var.clave_libro = Some(clave);
+    }
+    let tablero = TABLERO_G.read().unwrap().tablero_g.clone();
+    
+    let jugadas = libro.jugadas(&tablero);
+    let total: u64 = jugadas.iter().map(|j| j.peso as u64).sum();
+    let store = gtk::ListStore::new(&[gtk::Type::String, gtk::Type::String, gtk::Type::String]);
+    for j in jugadas.iter() {
+        let san = j.jugada.san(&tablero).unwrap_or_else(|_| j.jugada.uci());
+        let porcentaje = if total > 0 {
+            format!("{:.1}", j.peso as f64 * 100.0 / total as f64)
+        }
+        else {
+            "".to_string()
+        };
+        store.insert_with_values(None, &[0, 1, 2], &[&san, &j.peso.to_string(), &porcentaje]);
+    }
+    tree.set_model(Some(&store));
+    
+    let nombre = match libro.ruta.file_name() {
+        Some(nombre) => nombre.to_string_lossy().to_string(),
+        None => "".to_string(),
+    };
+    if jugadas.is_empty() {
+        etiqueta.set_text(&format!("{}: fuera de libro", nombre));
+    }
+    else {
+        etiqueta.set_text(&nombre);
+    }
+}
+
+
+// el tiempo de un reloj como m:ss
+fn texto_reloj(ms: i64) -> String {
+    let segundos = ms.max(0) / 1000;
+    format!("{}:{:02}", segundos / 60, segundos % 60)
+}
+
+
+// para la busqueda anterior, si la hay, y analiza la posicion del tablero
+fn reinicia_analisis(window: &gtk::Window, widgets: &WidgetsMotor, tablero: ajedrez::Tablero) {
+    let multipv = widgets.multipv.get_value_as_int().to_string();
+    let mut estado = ANALISIS.write().unwrap();
+    let enviado = {
+        let mut motor = MOTOR.write().unwrap();
+        match motor.as_mut() {
+            Some(motor) => {
+                let parado = if estado.busquedas > 0 { motor.para() } else { Ok(()) };
+                parado.and_then(|_| motor.opcion("MultiPV", &multipv))
+                      .and_then(|_| motor.posicion(&tablero))
+                      .and_then(|_| motor.busca(Limite::Infinito))
+            },
+            None => Err("No hay ningun motor cargado".to_string()),
+        }
+    };
+    match enviado {
+        Ok(_) => {
+            estado.busquedas += 1;
+            estado.posicion = orden_posicion(&tablero);
+            estado.tablero = tablero;
+            estado.lineas.clear();
+        },
+        Err(msg) => {
+            estado.activo = false;
+            drop(estado);
+            widgets.boton_analisis.set_active(false);
+            dialogos::muestra_error(window, &msg);
+        },
+    }
+}
+
+
+fn empieza_analisis(widgets: &WidgetsMotor) {
+    {
+        let mut estado = ANALISIS.write().unwrap();
+        estado.activo = true;
+        estado.posicion.clear();
+        estado.lineas.clear();
+    }
+    // la caja tiene no_show_all en el .ui y show_all no la mostraria
+    widgets.caja.set_no_show_all(false);
+    widgets.caja.show_all();
+    muestra_analisis(widgets);
+}
+
+
+fn termina_analisis(widgets: &WidgetsMotor) {
+    {
+        let mut estado = ANALISIS.write().unwrap();
+        if estado.activo && estado.busquedas > 0 {
+            let mut motor = MOTOR.write().unwrap();
+            if let Some(motor) = motor.as_mut() {
+                let _ = motor.para();
+            }
+        }
+        estado.activo = false;
+    }
+    widgets.caja.hide();
+}
+
+
+// para el motor y lo quita de la ventana
+fn cierra_motor(window: &gtk::Window, widgets: &WidgetsMotor) {
+    {
+        let mut obj = MOTOR.write().unwrap();
+        *obj = None;
+    }
+    {
+        let mut estado = ANALISIS.write().unwrap();
+        estado.busquedas = 0;
+    }
+    widgets.boton_analisis.set_active(false);
+    termina_juego(widgets);
+    window.set_title("Tablero");
+    widgets.cerrar.set_sensitive(false);
+}
+
+
+// columnas de la lista de analisis: evaluacion, profundidad y variante
+fn crea_lista_analisis(tree: &gtk::TreeView) {
+    for (i, titulo) in ["Eval", "Depth", "Line"].iter().enumerate() {
+        let column = gtk::TreeViewColumn::new();
+        let cell = gtk::CellRendererText::new();
+        column.pack_start(&cell, true);
+        column.add_attribute(&cell, "text", i as i32);
+        column.set_title(titulo);
+        column.set_resizable(true);
+        tree.append_column(&column);
+    }
+}
+
+
+// muestra las lineas del motor, pasadas a SAN
+fn muestra_analisis(widgets: &WidgetsMotor) {
+    let store = gtk::ListStore::new(&[gtk::Type::String, gtk::Type::String, gtk::Type::String]);
+    let estado = ANALISIS.read().unwrap();
+    let mut nodos: u64 = 0;
+    let mut profundidad: u32 = 0;
+    for info in estado.lineas.iter().filter(|info| !info.pv.is_empty()) {
+        let eval = match info.puntuacion {
+            Some(puntuacion) => texto_puntuacion(&estado.tablero, puntuacion),
+            None => "".to_string(),
+        };
+        let prof = info.profundidad.unwrap_or(0);
+        let variante = texto_variante(&estado.tablero, &info.pv);
+        store.insert_with_values(None, &[0, 1, 2], &[&eval, &prof.to_string(), &variante]);
+        profundidad = profundidad.max(prof);
+        nodos = nodos.max(info.nodos.unwrap_or(0));
+    }
+    widgets.lista.set_model(Some(&store));
+    
+    let nombre = match MOTOR.read().unwrap().as_ref() {
+        Some(motor) => motor.nombre.clone(),
+        None => "".to_string(),
+    };
+    widgets.etiqueta.set_text(&format!("{}   depth {}   {} nodes", nombre, profundidad, nodos));
+}
+
+
+// crea el modelo y las columnas de la lista de partidas
+fn crea_lista_partidas(tree: &gtk::TreeView) -> gtk::ListStore {
+    let store = gtk::ListStore::new(&[
+                gtk::Type::String, gtk::Type::String, gtk::Type::String,
+                gtk::Type::String, gtk::Type::String, gtk::Type::String,
+                gtk::Type::U32]);
+    
+    for (i, titulo) in COLUMNAS_PARTIDAS.iter().enumerate() {
+        let column = gtk::TreeViewColumn::new();
+        let cell = gtk::CellRendererText::new();
+        column.pack_start(&cell, true);
+        column.add_attribute(&cell, "text", i as i32);
+        column.set_title(titulo);
+        column.set_resizable(true);
+        column.set_sort_column_id(i as i32);
+        tree.append_column(&column);
+    }
+    tree.set_model(Some(&store));
+    
+    store
+}
+
+
+// rellena la lista con las cabeceras de todas las partidas del fichero
+fn rellena_lista_partidas(store: &gtk::ListStore, base: &BaseDatos) {
+    store.clear();
+    let columnas: [u32; 7] = [0, 1, 2, 3, 4, 5, COL_NUM_PARTIDA];
+    for (num, entrada) in base.partidas.iter().enumerate() {
+        let valores: Vec<String> = COLUMNAS_PARTIDAS.iter()
+                    .map(|nombre| entrada.cabecera(nombre).to_string())
+                    .collect();
+        let num = num as u32;
+        store.insert_with_values(None, &columnas, &[
+                &valores[0], &valores[1], &valores[2],
+                &valores[3], &valores[4], &valores[5], &num]);
+    }
+}
+
+
+// Muestra las partidas de un fichero PGN y carga la primera en el tablero.
+// La lista solo se ve si el fichero tiene mas de una partida
+pub fn abre_base_datos(base: BaseDatos, store: &gtk::ListStore, 
+                        panel: &gtk::ScrolledWindow) -> Result<(), String> {
+    if base.is_empty() {
+        return Err(format!("{} no contiene ninguna partida", base.ruta.display()));
+    }
+    let primera = base.carga_partida(0);
+    
+    rellena_lista_partidas(store, &base);
+    if base.len() > 1 {
+        // el panel tiene no_show_all en el .ui y show_all no lo mostraria
+        panel.set_no_show_all(false);
+        panel.show_all();
+    }
+    else {
+        panel.hide();
+    }
+    {
+        let mut obj = BASE_DATOS.write().unwrap();
+        *obj = Some(base);
+    }
+    
+    carga_partida(primera?);
+    Ok(())
+}
+
+
+// Sustituye la partida del tablero grafico por una partida leida de un PGN
+// y marca la ultima jugada de la misma
+pub fn carga_partida(partida: Partida) {
+    {
+        let mut obj = TABLERO_G.write().unwrap();
+        obj.cabeceras = partida.cabeceras;
+        obj.resultado = partida.resultado;
+        obj.arbol = partida.arbol;
+        obj.tablero_g = partida.tablero;
+        obj.actualiza_ultima();
+    }
+    
+    {
+        let mut obj = VARIABLES.write().unwrap();
+        obj.set_drag_source(999);
+    }
+    marca_ultima_jugada();
+}
+
+
+// Empieza una partida nueva desde la posicion del tablero
+pub fn carga_posicion(mut tablero: ajedrez::Tablero) {
+    let arbol = ArbolPartida::nuevo(&mut tablero);
+    carga_partida(Partida {
+        cabeceras: Vec::new(),
+        arbol,
+        tablero,
+        resultado: "*".to_string(),
     });
+}
+
+
+// La posicion de un texto pegado: una FEN o una linea EPD (de la que
+// solo se usa la posicion). Si hay varias lineas vale la primera
+fn lee_posicion(texto: &str) -> Result<ajedrez::Tablero, ajedrez::ErrorAjedrez> {
+    let linea = texto.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+    let mut tablero = ajedrez::Tablero::init();
+    match ajedrez::set_fen(linea, &mut tablero) {
+        Ok(()) => Ok(tablero),
+        // con seis campos era una FEN y el error es el suyo
+        Err(err) if linea.split_whitespace().count() == 6 => Err(err),
+        Err(_) => Epd::lee(linea)?.tablero(),
+    }
+}
+
+
+// marca en el tablero grafico las casillas de la jugada que lleva al nodo actual
+fn marca_ultima_jugada() {
+    let (desde, hasta);
+    {
+        let tab = TABLERO_G.read().unwrap();
+        let casillas = tab.casillas_ultima();
+        desde = casillas.0;
+        hasta = casillas.1;
+    }
+    let mut obj = VARIABLES.write().unwrap();
+    obj.set_casilla_desde(desde);
+    obj.set_casilla_hasta(hasta);
+}
+
+
+// la unica columna del arbol de notacion
+fn crea_columna_notacion(tree: &gtk::TreeView) {
+    let column = gtk::TreeViewColumn::new();
+    let cell = gtk::CellRendererText::new();
+    column.pack_start(&cell, true);
+    column.add_attribute(&cell, "text", COL_TEXTO_NOTACION as i32);
+    tree.append_column(&column);
+}
+
+
+// Vuelve a rellenar el arbol de notacion con la partida del tablero grafico
+// y selecciona la jugada del nodo actual
+pub fn muestra_notacion(tree: &gtk::TreeView) {
+    let store = gtk::TreeStore::new(&[gtk::Type::String, gtk::Type::U32]);
+    let mut fila_actual: Option<gtk::TreeIter> = None;
+    {
+        let tab = TABLERO_G.read().unwrap();
+        let arbol = &tab.arbol;
+        if !arbol.nodos[0].comentario.is_empty() {
+            let texto = format!("{{{}}}", arbol.nodos[0].comentario);
+            store.insert_with_values(None, None, &[COL_TEXTO_NOTACION, COL_NODO_NOTACION], &[&texto, &0u32]);
+        }
+        if let Some(&primero) = arbol.nodos[0].hijos.first() {
+            rellena_linea_notacion(&store, None, arbol, primero, true, &mut fila_actual);
+        }
+    }
     
-    window.show_all();
+    tree.set_model(Some(&store));
+    tree.expand_all();
+    if let Some(fila) = fila_actual {
+        tree.get_selection().select_iter(&fila);
+        if let Some(path) = store.get_path(&fila) {
+            tree.scroll_to_cell(Some(&path), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+        }
+    }
+}
+
+
+// Añade al arbol de notacion la jugada del nodo y la linea principal que le sigue.
+// Las variantes de cada jugada cuelgan de la fila de esa jugada
+fn rellena_linea_notacion(store: &gtk::TreeStore, padre: Option<&gtk::TreeIter>, arbol: &ArbolPartida,
+                        primero: usize, con_alternativas: bool, fila_actual: &mut Option<gtk::TreeIter>) {
+    let columnas = [COL_TEXTO_NOTACION, COL_NODO_NOTACION];
+    let mut nodo = primero;
+    let mut con_alternativas = con_alternativas;
+    loop {
+        let num_nodo = nodo as u32;
+        let fila = store.insert_with_values(padre, None, &columnas, &[&texto_notacion(arbol, nodo), &num_nodo]);
+        if nodo == arbol.actual {
+            *fila_actual = Some(fila.clone());
+        }
+        
+        // la primera jugada de una variante no muestra las alternativas: ya lo hace su padre
+        if con_alternativas {
+            let hermanos = &arbol.nodos[arbol.nodos[nodo].padre].hijos;
+            for (i, &variante) in hermanos.iter().enumerate().skip(1) {
+                let num_variante = variante as u32;
+                let titulo = format!("Variante {}", i);
+                let fila_variante = store.insert_with_values(Some(&fila), None, &columnas, &[&titulo, &num_variante]);
+                rellena_linea_notacion(store, Some(&fila_variante), arbol, variante, false, fila_actual);
+            }
+        }
+        con_alternativas = true;
+        
+        nodo = match arbol.nodos[nodo].hijos.first() {
+            Some(&siguiente) => siguiente,
+            None => break,
+        };
+    }
+}
+
+
+// el texto de una jugada en el arbol de notacion: numero, SAN, NAGs y comentarios
+fn texto_notacion(arbol: &ArbolPartida, nodo: usize) -> String {
+    let n = &arbol.nodos[nodo];
+    let mut texto = "".to_string();
+    if !n.comentario_previo.is_empty() {
+        texto.push_str(&format!("{{{}}} ", n.comentario_previo));
+    }
+    if n.blancas() {
+        texto.push_str(&format!("{}. {}", n.num_jugada, n.san));
+    }
+    else {
+        texto.push_str(&format!("{}... {}", n.num_jugada, n.san));
+    }
+    for nag in n.nags.iter() {
+        texto.push_str(&nag_texto(*nag));
+    }
+    if !n.comentario.is_empty() {
+        texto.push_str(&format!(" {{{}}}", n.comentario));
+    }
+    texto
+}
+
+
+// ========= navegacion por las jugadas de la partida ================
+pub fn go_start_button_click_callback(board: &gtk::DrawingArea, notacion: &gtk::TreeView) {
+    {
+        let mut obj = TABLERO_G.write().unwrap();
+        while obj.atras() {}
+    }
+    marca_ultima_jugada();
+    muestra_notacion(notacion);
+    board.queue_draw();
+}
+
+pub fn go_back_button_click_callback(board: &gtk::DrawingArea, notacion: &gtk::TreeView) {
+    {
+        let mut obj = TABLERO_G.write().unwrap();
+        obj.atras();
+    }
+    marca_ultima_jugada();
+    muestra_notacion(notacion);
+    board.queue_draw();
+}
+
+pub fn go_next_button_click_callback(board: &gtk::DrawingArea, notacion: &gtk::TreeView) {
+    {
+        let mut obj = TABLERO_G.write().unwrap();
+        obj.adelante();
+    }
+    marca_ultima_jugada();
+    muestra_notacion(notacion);
+    board.queue_draw();
+}
+
+pub fn go_end_button_click_callback(board: &gtk::DrawingArea, notacion: &gtk::TreeView) {
+    {
+        let mut obj = TABLERO_G.write().unwrap();
+        while obj.adelante() {}
+    }
+    marca_ultima_jugada();
+    muestra_notacion(notacion);
+    board.queue_draw();
 }
 
 
@@ -204,6 +1729,23 @@ pub fn board_draw_callback(widget: &gtk::DrawingArea,
         tab = TABLERO_G.read().unwrap();
     }
     
+    // en el editor se dibuja la posicion que se esta montando
+    let editando = editor::activo();
+    let casillas = if editando { editor::casillas() } else { tab.tablero_g.board_array };
+    // los destinos de la pieza arrastrada o elegida y la que vuelve a su casilla
+    let elegida = if editando { None } else { casilla_elegida(&var, &tab.tablero_g) };
+    let arrastrando = (var.drag_source != 999 || elegida.is_some()) && !editando;
+    // el rey en jaque y las piezas que se lo dan
+    let jaques = if editando { Vec::new() } else { ajedrez::dan_jaque(&tab.tablero_g) };
+    let rey_jaque = if jaques.is_empty() {
+        None
+    }
+    else {
+        ajedrez::casilla_rey(&tab.tablero_g, Color::from_i16(ajedrez::turno(&tab.tablero_g)))
+                .map(|casilla| casilla.to_088() as i16)
+    };
+    let vuelta = var.vuelta.filter(|v| v.inicio.elapsed() < DURACION_VUELTA);
+    
     let piezas_svg = utils::load_svgs();
     let square_size = get_square_size(widget);
     let leftover_space = widget.get_allocated_width() - square_size * var.board_size;
@@ -253,36 +1795,70 @@ pub fn board_draw_callback(widget: &gtk::DrawingArea,
             
             if sq_orig != 999 && sq_dest != 999 {
                 let mov_tab = ((utils::DIBUJA_CASILLA[sq_orig as usize]) << 16) | (utils::DIBUJA_CASILLA[sq_dest as usize]);
-                if last_move.0 != "None" &&
+                if last_move.is_some() && !editando &&
                         ( s == (mov_tab) >> 16 || s == (mov_tab) & 0xFFFF ) {
-                    cairo::Context::set_source_rgb(ctx, 0.225, 0.26, 0.3505);
-                    cairo::Context::set_line_width(ctx, highlight_line_width);
-                    cairo::Context::translate(ctx, highlight_line_width / 2.0, highlight_line_width / 2.0);
-                    cairo::Context::rectangle(ctx, 0.0, 0.0, square_size as f64 - highlight_line_width,
-                            square_size as f64 - highlight_line_width);
-                    cairo::Context::stroke(ctx);
-
-                    cairo::Context::set_line_width(ctx, 1.0);
-                    cairo::Context::translate(ctx, -highlight_line_width / 2.0, -highlight_line_width / 2.0);
+                    dibuja_marco(ctx, square_size, highlight_line_width);
                 }
             }
             let clon_piezas = piezas_svg.clone();
-            let pieza_interna;
+            let casilla;
             if !flipped {
-                pieza_interna = tab.tablero_g.board_array[utils::COORDS_088[file as usize][rank as usize] as usize];
+                casilla = utils::COORDS_088[file as usize][rank as usize] as i16;
             }
             else {
-                pieza_interna = tab.tablero_g.board_array[utils::COORDS_088_FLIPPED[file as usize][rank as usize] as usize];
+                casilla = utils::COORDS_088_FLIPPED[file as usize][rank as usize] as i16;
+            }
+            // la pieza elegida con un clic se marca igual que la ultima jugada
+            if elegida == Some(casilla) {
+                dibuja_marco(ctx, square_size, highlight_line_width);
+            }
+            if rey_jaque == Some(casilla) {
+                dibuja_jaque(ctx, square_size);
+            }
+            // las letras en la fila de abajo y los numeros en la columna de la
+            // izquierda, sean cuales sean segun este girado el tablero
+            if var.muestra_coordenadas {
+                let oscura = (y + x) % 2 == 0;
+                if rank == 0 {
+                    let letra = (b'a' + (casilla & 7) as u8) as char;
+                    dibuja_coordenada(ctx, square_size, &letra.to_string(), true, oscura);
+                }
+                if file == 0 {
+                    let numero = (b'1' + (casilla >> 4) as u8) as char;
+                    dibuja_coordenada(ctx, square_size, &numero.to_string(), false, oscura);
+                }
             }
-            if pieza_interna != 0 {
+            let pieza_interna = casillas[casilla as usize];
+            let volviendo = vuelta.map_or(false, |v| v.casilla == casilla);
+            if pieza_interna != 0 && !volviendo {
                 draw_piece(&ctx, pieza_interna, square_size, clon_piezas);
             }
+            if arrastrando {
+                if let Some(&(_, captura)) = var.destinos.iter().find(|&&(c, _)| c == casilla) {
+                    dibuja_destino(ctx, square_size, captura);
+                }
+            }
             
             cairo::Context::translate(ctx, 0.0, square_size as f64);
         }
         
         cairo::Context::translate(ctx, square_size as f64, (-square_size * var.board_size) as f64);
     }
+    // las piezas que dan jaque, unidas con el rey
+    if let (Some(rey), true) = (rey_jaque, var.muestra_jaques) {
+        cairo::Context::identity_matrix(ctx);
+        cairo::Context::translate(ctx, padding as f64, padding as f64);
+        dibuja_jaques(ctx, square_size, rey, &jaques, flipped);
+    }
+    
+    // las flechas y circulos de la posicion, encima de las piezas
+    if !editando {
+        let nodo = &tab.arbol.nodos[tab.arbol.actual];
+        cairo::Context::identity_matrix(ctx);
+        cairo::Context::translate(ctx, padding as f64, padding as f64);
+        dibuja_marcas(ctx, square_size, &nodo.flechas, &nodo.circulos, flipped);
+    }
+    
     //drop(var);
     //{
     let var = VARIABLES.read().unwrap(); 
@@ -291,20 +1867,43 @@ pub fn board_draw_callback(widget: &gtk::DrawingArea,
         cairo::Context::translate(ctx, padding as f64 + var.mouse_x - (square_size / 2) as f64,
                 padding as f64 + var.mouse_y - (square_size / 2) as f64);
         
-        let pieza_interna = tab.tablero_g.board_array[var.drag_source as usize];
+        let pieza_interna = casillas[var.drag_source as usize];
         
         if pieza_interna != 0 {
-            draw_piece(ctx, pieza_interna, square_size, piezas_svg);
+            draw_piece(ctx, pieza_interna, square_size, piezas_svg.clone());
         }
     }
     //}
     
+    // la pieza que vuelve va desde donde se solto hasta el centro de su casilla,
+    // frenando al final
+    if let Some(v) = vuelta {
+        let t = v.inicio.elapsed().as_secs_f64() / DURACION_VUELTA.as_secs_f64();
+        let t = 1.0 - (1.0 - t) * (1.0 - t);
+        let (columna, fila) = columna_fila(v.casilla, flipped);
+        let centro_x = (columna as f64 + 0.5) * square_size as f64;
+        let centro_y = (fila as f64 + 0.5) * square_size as f64;
+        cairo::Context::identity_matrix(ctx);
+        cairo::Context::translate(ctx, padding as f64 + v.x + (centro_x - v.x) * t - (square_size / 2) as f64,
+                padding as f64 + v.y + (centro_y - v.y) * t - (square_size / 2) as f64);
+        let pieza_interna = casillas[v.casilla as usize];
+        if pieza_interna != 0 {
+            draw_piece(ctx, pieza_interna, square_size, piezas_svg);
+        }
+    }
+    
 }
 
 
 pub fn board_mouse_down_callback(widget: &gtk::DrawingArea, event: &gdk::EventButton) {
 	let e = event;
     
+    // en el editor las piezas se colocan en vez de jugarse
+    if editor::activo() {
+        editor_mouse_down(widget, event);
+        return;
+    }
+    
     let tab; 
     {
         tab = TABLERO_G.read().unwrap();   // method read is inmutable, method write is mutable
@@ -314,20 +1913,62 @@ pub fn board_mouse_down_callback(widget: &gtk::DrawingArea, event: &gdk::EventBu
         let (x, y) = event.get_position();
         let casilla_088 = board_coords_to_square(widget, x, y);
         
-        if tab.tablero_g.board_array[casilla_088 as usize] != 0 {   // hay alguna pieza
+        // con una pieza elegida, el clic en uno de sus destinos hace la jugada al soltar
+        {
+            let var = VARIABLES.read().unwrap();
+            if casilla_elegida(&var, &tab.tablero_g).is_some() &&
+                    var.destinos.iter().any(|&(c, _)| c == casilla_088 as i16) {
+                return;
+            }
+        }
+        
+        let pieza = tab.tablero_g.board_array[casilla_088 as usize];
+        // jugando contra el motor solo se mueven las piezas propias y en nuestro turno
+        // y cuando la partida ha terminado no se mueve nada
+        let bloqueada = {
+            let juego = JUEGO.read().unwrap();
+            (juego.activo && (juego.pensando || pieza.signum() != juego.color_humano)) ||
+                    tab.fin_partida().is_some()
+        };
+        
+        if pieza != 0 && !bloqueada {   // hay alguna pieza
             let mut var; 
             {
                 var = VARIABLES.write().unwrap();   // method read is inmutable, method write is mutable
             }
             var.drag_source = casilla_088 as i16;
+            var.destinos = destinos_legales(&tab.tablero_g, casilla_088 as i16);
+            var.vuelta = None;
+        }
+        if pieza == 0 || bloqueada || pieza.signum() != ajedrez::turno(&tab.tablero_g) {
+            VARIABLES.write().unwrap().seleccion = None;
+        }
+    }
+    else if e.get_button() == 3 {
+        // el boton derecho dibuja: la flecha o el circulo se ponen al soltarlo
+        let (x, y) = event.get_position();
+        if let Some(casilla) = casilla_bajo(widget, x, y) {
+            VARIABLES.write().unwrap().marca_desde = casilla;
         }
     }
 }
 
 
 // Intenta mover una pieza si actualmente estamos arrastrando una.
-pub fn board_mouse_up_callback(widget: &gtk::DrawingArea, event: &gdk::EventButton) {
+// Retorna true si se ha hecho una jugada
+pub fn board_mouse_up_callback(widget: &gtk::DrawingArea, event: &gdk::EventButton) -> bool {
 	let e = event;
+    let mut hecha = false;
+    
+    if editor::activo() {
+        editor_mouse_up(widget, event);
+        return false;
+    }
+    
+    if e.get_button() == 3 {
+        termina_marca(widget, event);
+        return false;
+    }
 
     if e.get_button() == 1 {
         let drag_origen: i16; 
@@ -337,36 +1978,24 @@ pub fn board_mouse_up_callback(widget: &gtk::DrawingArea, event: &gdk::EventButt
         }
         if drag_origen != 999 && drag_origen != -1{
             let (x, y) = e.get_position();
-            let drag_target = board_coords_to_square(widget, x, y);
             let drag_source = drag_origen;
             
-            // ahora necesitamos convertir las casillas 0x88 a algebraico "a2", "b5"...
-            let origen = utils::ALGEBRA[drag_source as usize];
-            let destino = utils::ALGEBRA[drag_target as usize];
-            
-            let mut posic_actual;
-            {
-                let tab = TABLERO_G.read().unwrap();
-                posic_actual = tab.tablero_g.clone();
-            }
-            let movim = (origen, destino, "Q"); // promote always to queen
-            let result = ajedrez::mueve_algebra(&mut posic_actual, movim);
-            if result.0 != "None" {
-                {
-                    let mut obj = TABLERO_G.write().unwrap();
-                    obj.tablero_g = posic_actual.clone();
-                    obj.last_move = result.clone();
-                    let pgn_txt = ajedrez::pgn(&mut posic_actual);
-                    println!("\n{}", pgn_txt);
-                }
-                
-                {
-                    let mut obj = VARIABLES.write().unwrap();
-                    obj.set_casilla_desde(drag_source);
-                    let destino = drag_target as i16;
-                    obj.set_casilla_hasta(destino);
-                }
-                
+            match casilla_bajo(widget, x, y) {
+                // soltada en su misma casilla es un clic: la pieza queda
+                // elegida, o deja de estarlo si ya lo estaba
+                Some(drag_target) if drag_target == drag_source => elige_pieza(drag_source),
+                Some(drag_target) => {
+                    let (jugada, pendiente) = suelta_pieza(drag_source, drag_target);
+                    hecha = jugada;
+                    if !hecha && !pendiente {
+                        empieza_vuelta(widget, drag_source);
+                    }
+                    VARIABLES.write().unwrap().seleccion = None;
+                },
+                None => {
+                    empieza_vuelta(widget, drag_source);
+                    VARIABLES.write().unwrap().seleccion = None;
+                },
             }
             
             {
@@ -376,7 +2005,334 @@ pub fn board_mouse_up_callback(widget: &gtk::DrawingArea, event: &gdk::EventButt
             
             widget.queue_draw();
         }
+        else {
+            // el segundo clic, en uno de los destinos de la pieza elegida
+            let (x, y) = e.get_position();
+            let elegida = {
+                let tab = TABLERO_G.read().unwrap();
+                casilla_elegida(&VARIABLES.read().unwrap(), &tab.tablero_g)
+            };
+            if let (Some(origen), Some(destino)) = (elegida, casilla_bajo(widget, x, y)) {
+                hecha = suelta_pieza(origen, destino).0;
+                VARIABLES.write().unwrap().seleccion = None;
+                widget.queue_draw();
+            }
+        }
+    }
+    hecha
+}
+
+
+// Al soltar el boton derecho en otra casilla se pone o quita una flecha y
+// en la misma un circulo, en la posicion del nodo actual. El color depende
+// de las teclas: verde sin ninguna, rojo con mayusculas, azul con control
+// (o alt) y amarillo con mayusculas y control
+fn termina_marca(widget: &gtk::DrawingArea, event: &gdk::EventButton) {
+    let desde = {
+        let mut obj = VARIABLES.write().unwrap();
+        let desde = obj.marca_desde;
+        obj.marca_desde = 999;
+        desde
+    };
+    let (x, y) = event.get_position();
+    let (desde, hasta) = match (ajedrez::Square::from_088(desde as usize), casilla_bajo(widget, x, y)) {
+        (Some(desde), Some(hasta)) => (desde, ajedrez::Square::from_088(hasta as usize).unwrap()),
+        _ => return,
+    };
+    let estado = event.get_state();
+    let mayusculas = estado.contains(gdk::ModifierType::SHIFT_MASK);
+    let control = estado.intersects(gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::MOD1_MASK);
+    let color = match (mayusculas, control) {
+        (false, false) => ColorMarca::Verde,
+        (true, false) => ColorMarca::Rojo,
+        (false, true) => ColorMarca::Azul,
+        (true, true) => ColorMarca::Amarillo,
+    };
+    {
+        let mut tab = TABLERO_G.write().unwrap();
+        let actual = tab.arbol.actual;
+        if desde == hasta {
+            tab.arbol.nodos[actual].cambia_circulo(Circulo { color, casilla: desde });
+        }
+        else {
+            tab.arbol.nodos[actual].cambia_flecha(Flecha { color, desde, hasta });
+        }
+    }
+    widget.queue_draw();
+}
+
+
+// Juega la pieza de origen en destino. Retorna si se ha hecho la jugada y
+// si queda pendiente de elegir la pieza de una coronacion
+fn suelta_pieza(drag_source: i16, drag_target: i16) -> (bool, bool) {
+    // ahora necesitamos convertir las casillas 0x88 a algebraico "a2", "b5"...
+    let origen = utils::ALGEBRA[drag_source as usize];
+    let destino = utils::ALGEBRA[drag_target as usize];
+    
+    if es_coronacion(origen, destino) {
+        // la jugada se hace cuando se elija la pieza
+        let mut obj = VARIABLES.write().unwrap();
+        obj.promo_desde = drag_source;
+        obj.promo_hasta = drag_target;
+        return (false, true);
+    }
+    (juega_jugada(origen, destino, "Q"), false)
+}
+
+
+// un clic en una pieza propia la elige para jugarla con otro clic en
+// su destino; un clic en la que ya esta elegida la deja
+fn elige_pieza(casilla: i16) {
+    let (propia, clave) = {
+        let tab = TABLERO_G.read().unwrap();
+        (tab.tablero_g.board_array[casilla as usize].signum() == ajedrez::turno(&tab.tablero_g),
+            ajedrez::clave_zobrist(&tab.tablero_g))
+    };
+    let mut obj = VARIABLES.write().unwrap();
+    obj.seleccion = if propia && obj.seleccion != Some((casilla, clave)) {
+        Some((casilla, clave))
+    }
+    else {
+        None
+    };
+}
+
+
+// la casilla de la pieza elegida con un clic, mientras el tablero siga en
+// la posicion en la que se eligio
+fn casilla_elegida(var: &Variables, tablero: &ajedrez::Tablero) -> Option<i16> {
+    var.seleccion
+        .filter(|&(_, clave)| clave == ajedrez::clave_zobrist(tablero))
+        .map(|(casilla, _)| casilla)
+}
+
+
+// En el editor, el boton izquierdo empieza a arrastrar la pieza de la casilla
+// o, si esta vacia, pone la de la paleta; el derecho la quita
+fn editor_mouse_down(widget: &gtk::DrawingArea, event: &gdk::EventButton) {
+    let (x, y) = event.get_position();
+    let casilla = match casilla_bajo(widget, x, y) {
+        Some(casilla) => casilla,
+        None => return,
+    };
+    match event.get_button() {
+        1 => {
+            if editor::pieza_en(casilla) != defs::EMPTY_SQUARE {
+                VARIABLES.write().unwrap().set_drag_source(casilla);
+            }
+            else {
+                editor::pon_pieza(casilla, editor::pieza_elegida());
+            }
+        },
+        3 => editor::pon_pieza(casilla, defs::EMPTY_SQUARE),
+        _ => return,
+    }
+    widget.queue_draw();
+}
+
+
+// Suelta la pieza arrastrada en el editor. Fuera del tablero se quita y
+// soltada en su misma casilla se cambia por la de la paleta
+fn editor_mouse_up(widget: &gtk::DrawingArea, event: &gdk::EventButton) {
+    let origen = VARIABLES.read().unwrap().get_drag_source();
+    if event.get_button() != 1 || origen == 999 {
+        return;
+    }
+    let (x, y) = event.get_position();
+    match casilla_bajo(widget, x, y) {
+        Some(destino) if destino == origen => editor::pon_pieza(origen, editor::pieza_elegida()),
+        Some(destino) => {
+            editor::pon_pieza(destino, editor::pieza_en(origen));
+            editor::pon_pieza(origen, defs::EMPTY_SQUARE);
+        },
+        None => editor::pon_pieza(origen, defs::EMPTY_SQUARE),
+    }
+    VARIABLES.write().unwrap().set_drag_source(999);
+    widget.queue_draw();
+}
+
+
+// Hace la jugada en el tablero grafico y marca sus casillas.
+// Retorna false si la jugada no es legal o la partida ya ha terminado
+fn juega_jugada(origen: &str, destino: &str, promo: &str) -> bool {
+    let mut posic_actual;
+    {
+        let tab = TABLERO_G.read().unwrap();
+        if tab.fin_partida().is_some() {
+            return false;
+        }
+        posic_actual = tab.tablero_g.clone();
+    }
+    let result = match ajedrez::mueve_algebra(&mut posic_actual, (origen, destino, promo)) {
+        Ok(result) => result,
+        Err(_) => return false,
+    };
+    {
+        let mut obj = TABLERO_G.write().unwrap();
+        obj.anade_jugada(posic_actual, result);
+    }
+    
+    {
+        let mut obj = VARIABLES.write().unwrap();
+        obj.set_casilla_desde(casilla_088(origen));
+        obj.set_casilla_hasta(casilla_088(destino));
+    }
+    true
+}
+
+
+// true si la jugada es legal y lleva un peon a la ultima fila
+fn es_coronacion(origen: &str, destino: &str) -> bool {
+    let mut posic;
+    {
+        let tab = TABLERO_G.read().unwrap();
+        posic = tab.tablero_g.clone();
+    }
+    let pieza = posic.board_array[casilla_088(origen) as usize];
+    let fila = casilla_088(destino) / 16;
+    if !((pieza == defs::W_PAWN && fila == 7) || (pieza == defs::B_PAWN && fila == 0)) {
+        return false;
+    }
+    ajedrez::mueve_algebra(&mut posic, (origen, destino, "Q")).is_ok()
+}
+
+
+// Las casillas a las que puede ir la pieza de la casilla origen y si en
+// ellas captura. Las cuatro coronaciones van a la misma casilla
+fn destinos_legales(tablero: &ajedrez::Tablero, origen: i16) -> Vec<(i16, bool)> {
+    let mut posic = tablero.clone();
+    let desde = utils::ALGEBRA[origen as usize];
+    let es_peon = posic.board_array[origen as usize].abs() == defs::W_PAWN;
+    let mut destinos: Vec<(i16, bool)> = Vec::new();
+    for uci in ajedrez::jugadas_posibles(&mut posic) {
+        if &uci[0..2] != desde {
+            continue;
+        }
+        let hasta = casilla_088(&uci[2..4]);
+        // la captura al paso llega a una casilla vacia
+        let captura = posic.board_array[hasta as usize] != defs::EMPTY_SQUARE ||
+                (es_peon && (hasta - origen) % 16 != 0);
+        if !destinos.iter().any(|&(c, _)| c == hasta) {
+            destinos.push((hasta, captura));
+        }
+    }
+    destinos
+}
+
+
+// la pieza soltada donde no puede ir vuelve a su casilla. Se redibuja el
+// tablero hasta que llega
+fn empieza_vuelta(widget: &gtk::DrawingArea, casilla: i16) {
+    {
+        let mut obj = VARIABLES.write().unwrap();
+        let (x, y) = (obj.mouse_x, obj.mouse_y);
+        obj.vuelta = Some(VueltaPieza { casilla, x, y, inicio: Instant::now() });
+    }
+    let board = widget.clone();
+    gtk::timeout_add(INTERVALO_ANIMACION, move || {
+        board.queue_draw();
+        let mut obj = VARIABLES.write().unwrap();
+        let sigue = obj.vuelta.map_or(false, |v| v.inicio.elapsed() < DURACION_VUELTA);
+        if !sigue {
+            obj.vuelta = None;
+        }
+        gtk::Continue(sigue)
+    });
+}
+
+
+// crea el popover con las cuatro piezas a las que puede coronar un peon
+fn crea_popover_promocion(board: &gtk::DrawingArea, notacion: &gtk::TreeView) -> gtk::Popover {
+    let popover = gtk::Popover::new(Some(board));
+    let caja = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+    
+    // la letra de la pieza para mueve_algebra y la pieza blanca para dibujarla
+    let piezas = [("Q", defs::W_QUEEN), ("R", defs::W_ROOK), ("B", defs::W_BISHOP), ("N", defs::W_KNIGHT)];
+    for &(letra, pieza) in piezas.iter() {
+        let boton = gtk::Button::new();
+        let dibujo = gtk::DrawingArea::new();
+        let size;
+        {
+            let var = VARIABLES.read().unwrap();
+            size = var.default_square_size;
+        }
+        dibujo.set_size_request(size, size);
+        dibujo.connect_draw(move |widget, ctx| {
+            // el color de la pieza es el del peon que corona
+            let color;
+            {
+                let var = VARIABLES.read().unwrap();
+                let tab = TABLERO_G.read().unwrap();
+                color = if var.promo_desde != 999 && tab.tablero_g.board_array[var.promo_desde as usize] < 0 { -1 } else { 1 };
+            }
+            draw_piece(ctx, pieza * color, widget.get_allocated_width(), utils::load_svgs());
+            Inhibit(false)
+        });
+        boton.add(&dibujo);
+        
+        let pop = popover.clone();
+        let board = board.clone();
+        let notacion = notacion.clone();
+        boton.connect_clicked(move |_btn| {
+            let (desde, hasta);
+            {
+                let var = VARIABLES.read().unwrap();
+                desde = var.promo_desde;
+                hasta = var.promo_hasta;
+            }
+            pop.hide();
+            if desde != 999 && hasta != 999 &&
+                        juega_jugada(utils::ALGEBRA[desde as usize], utils::ALGEBRA[hasta as usize], letra) {
+                muestra_notacion(&notacion);
+            }
+            board.queue_draw();
+        });
+        caja.pack_start(&boton, false, false, 0);
+    }
+    popover.add(&caja);
+    caja.show_all();
+    
+    // si se cierra sin elegir pieza no se hace la jugada
+    popover.connect_closed(|_pop| {
+        let mut var = VARIABLES.write().unwrap();
+        var.promo_desde = 999;
+        var.promo_hasta = 999;
+    });
+    
+    popover
+}
+
+
+// muestra el popover de coronacion sobre la casilla de destino si hay una pendiente
+fn muestra_popover_promocion(board: &gtk::DrawingArea, popover: &gtk::Popover) {
+    let (hasta, flipped, board_size);
+    {
+        let var = VARIABLES.read().unwrap();
+        hasta = var.promo_hasta;
+        flipped = var.board_flipped;
+        board_size = var.board_size;
+    }
+    if hasta == 999 {
+        return;
     }
+    
+    let square_size = get_square_size(board);
+    let padding = (board.get_allocated_width() - square_size * board_size) / 2;
+    let (columna, fila) = ((hasta % 16) as i32, (hasta / 16) as i32);
+    let (board_x, board_y) = if !flipped {
+        (columna, board_size - 1 - fila)
+    }
+    else {
+        (board_size - 1 - columna, fila)
+    };
+    let rect = gdk::Rectangle {
+        x: padding + board_x * square_size,
+        y: padding + board_y * square_size,
+        width: square_size,
+        height: square_size,
+    };
+    popover.set_pointing_to(&rect);
+    popover.show_all();
 }
 
 
@@ -462,6 +2418,187 @@ pub fn draw_piece(cr: &cairo::Context, pieza: i16, size: i32, piece_images: [[rs
 	cairo::Context::scale(cr, 1.0 / scale, 1.0 / scale);
 }
 
+// el marco de las casillas de la ultima jugada y de la pieza elegida
+fn dibuja_marco(ctx: &cairo::Context, square_size: i32, highlight_line_width: f64) {
+    cairo::Context::set_source_rgb(ctx, 0.225, 0.26, 0.3505);
+    cairo::Context::set_line_width(ctx, highlight_line_width);
+    cairo::Context::translate(ctx, highlight_line_width / 2.0, highlight_line_width / 2.0);
+    cairo::Context::rectangle(ctx, 0.0, 0.0, square_size as f64 - highlight_line_width,
+            square_size as f64 - highlight_line_width);
+    cairo::Context::stroke(ctx);
+
+    cairo::Context::set_line_width(ctx, 1.0);
+    cairo::Context::translate(ctx, -highlight_line_width / 2.0, -highlight_line_width / 2.0);
+}
+
+// La letra de una columna (abajo a la derecha de la casilla) o el numero
+// de una fila (arriba a la izquierda), del color de la otra casilla
+fn dibuja_coordenada(ctx: &cairo::Context, square_size: i32, texto: &str, es_columna: bool, oscura: bool) {
+    let layout = match pangocairo::functions::create_layout(ctx) {
+        Some(layout) => layout,
+        None => return,
+    };
+    let lado = square_size as f64;
+    let mut fuente = pango::FontDescription::new();
+    fuente.set_family("Sans");
+    fuente.set_weight(pango::Weight::Bold);
+    fuente.set_absolute_size((lado / 6.0).max(7.0) * pango::SCALE as f64);
+    layout.set_font_description(Some(&fuente));
+    layout.set_text(texto);
+    
+    let (ancho, alto) = layout.get_pixel_size();
+    let margen = (lado * 0.04).round();
+    let (x, y) = if es_columna {
+        (lado - ancho as f64 - margen, lado - alto as f64)
+    }
+    else {
+        (margen, 0.0)
+    };
+    if oscura {
+        cairo::Context::set_source_rgb(ctx, 0.952941, 0.952941, 0.952941);
+    }
+    else {
+        cairo::Context::set_source_rgb(ctx, 0.450980, 0.537255, 0.713725);
+    }
+    ctx.move_to(x, y);
+    pangocairo::functions::show_layout(ctx, &layout);
+    ctx.new_path();
+}
+
+// el color de las flechas y circulos
+fn color_marca(ctx: &cairo::Context, color: ColorMarca, alfa: f64) {
+    let (r, g, b) = match color {
+        ColorMarca::Verde => (0.08, 0.47, 0.11),
+        ColorMarca::Rojo => (0.53, 0.13, 0.13),
+        ColorMarca::Amarillo => (0.9, 0.56, 0.0),
+        ColorMarca::Azul => (0.0, 0.19, 0.53),
+    };
+    cairo::Context::set_source_rgba(ctx, r, g, b, alfa);
+}
+
+// Los circulos y las flechas dibujados con el boton derecho. Cada flecha es
+// un trazo desde el centro de su casilla y una punta que acaba en el centro
+// de la otra. El contexto tiene que estar en la esquina del tablero
+fn dibuja_marcas(ctx: &cairo::Context, square_size: i32, flechas: &[Flecha], circulos: &[Circulo], flipped: bool) {
+    let lado = square_size as f64;
+    let centro = |casilla: ajedrez::Square| {
+        let (columna, fila) = columna_fila(casilla.to_088() as i16, flipped);
+        ((columna as f64 + 0.5) * lado, (fila as f64 + 0.5) * lado)
+    };
+    
+    cairo::Context::set_line_width(ctx, lado * 0.07);
+    for circulo in circulos {
+        let (x, y) = centro(circulo.casilla);
+        color_marca(ctx, circulo.color, 0.8);
+        ctx.new_path();
+        ctx.arc(x, y, lado * 0.46, 0.0, 2.0 * std::f64::consts::PI);
+        ctx.stroke();
+    }
+    
+    let largo_punta = lado * 0.45;
+    let ancho_punta = lado * 0.25;
+    cairo::Context::set_line_width(ctx, lado * 0.16);
+    for flecha in flechas {
+        let (x0, y0) = centro(flecha.desde);
+        let (x1, y1) = centro(flecha.hasta);
+        let largo = ((x1 - x0) * (x1 - x0) + (y1 - y0) * (y1 - y0)).sqrt();
+        // la direccion de la flecha, de largo 1
+        let (dx, dy) = ((x1 - x0) / largo, (y1 - y0) / largo);
+        let (bx, by) = (x1 - dx * largo_punta, y1 - dy * largo_punta);
+        color_marca(ctx, flecha.color, 0.8);
+        ctx.new_path();
+        ctx.move_to(x0, y0);
+        ctx.line_to(bx, by);
+        ctx.stroke();
+        ctx.move_to(x1, y1);
+        ctx.line_to(bx - dy * ancho_punta, by + dx * ancho_punta);
+        ctx.line_to(bx + dy * ancho_punta, by - dx * ancho_punta);
+        ctx.close_path();
+        ctx.fill();
+    }
+    cairo::Context::set_line_width(ctx, 1.0);
+}
+
+// el degradado rojo bajo el rey en jaque
+fn dibuja_jaque(ctx: &cairo::Context, square_size: i32) {
+    let lado = square_size as f64;
+    let degradado = cairo::RadialGradient::new(lado / 2.0, lado / 2.0, 0.0,
+            lado / 2.0, lado / 2.0, lado * 0.7);
+    cairo::Gradient::add_color_stop_rgba(&degradado, 0.0, 1.0, 0.0, 0.0, 1.0);
+    cairo::Gradient::add_color_stop_rgba(&degradado, 0.25, 0.9, 0.0, 0.0, 1.0);
+    cairo::Gradient::add_color_stop_rgba(&degradado, 0.9, 0.66, 0.0, 0.0, 0.0);
+    ctx.set_source(&degradado);
+    ctx.rectangle(0.0, 0.0, lado, lado);
+    ctx.fill();
+}
+
+// Las casillas de las piezas que dan jaque, con una linea hasta el rey.
+// El contexto tiene que estar en la esquina del tablero
+fn dibuja_jaques(ctx: &cairo::Context, square_size: i32, rey: i16, jaques: &[ajedrez::Square], flipped: bool) {
+    let lado = square_size as f64;
+    let (columna_rey, fila_rey) = columna_fila(rey, flipped);
+    cairo::Context::set_line_width(ctx, lado * 0.08);
+    cairo::Context::set_line_cap(ctx, cairo::LineCap::Round);
+    for casilla in jaques {
+        let (columna, fila) = columna_fila(casilla.to_088() as i16, flipped);
+        cairo::Context::set_source_rgba(ctx, 0.9, 0.0, 0.0, 0.3);
+        ctx.rectangle(columna as f64 * lado, fila as f64 * lado, lado, lado);
+        ctx.fill();
+        cairo::Context::set_source_rgba(ctx, 0.9, 0.0, 0.0, 0.6);
+        ctx.move_to((columna as f64 + 0.5) * lado, (fila as f64 + 0.5) * lado);
+        ctx.line_to((columna_rey as f64 + 0.5) * lado, (fila_rey as f64 + 0.5) * lado);
+        ctx.stroke();
+    }
+    cairo::Context::set_line_cap(ctx, cairo::LineCap::Butt);
+    cairo::Context::set_line_width(ctx, 1.0);
+}
+
+// un punto en las casillas vacias a las que puede ir la pieza arrastrada
+// y un anillo en las que captura
+fn dibuja_destino(ctx: &cairo::Context, square_size: i32, captura: bool) {
+    let lado = square_size as f64;
+    ctx.new_path();
+    cairo::Context::set_source_rgba(ctx, 0.225, 0.26, 0.3505, 0.45);
+    if captura {
+        cairo::Context::set_line_width(ctx, lado * 0.08);
+        ctx.arc(lado / 2.0, lado / 2.0, lado * 0.44, 0.0, 2.0 * std::f64::consts::PI);
+        ctx.stroke();
+        cairo::Context::set_line_width(ctx, 1.0);
+    }
+    else {
+        ctx.arc(lado / 2.0, lado / 2.0, lado * 0.15, 0.0, 2.0 * std::f64::consts::PI);
+        ctx.fill();
+    }
+}
+
+// la columna y la fila en pantalla (desde arriba a la izquierda) de una casilla 0x88
+fn columna_fila(casilla: i16, flipped: bool) -> (i32, i32) {
+    let (columna, fila) = ((casilla & 7) as i32, (casilla >> 4) as i32);
+    if flipped {
+        (7 - columna, fila)
+    }
+    else {
+        (columna, 7 - fila)
+    }
+}
+
+// convierte una casilla algebraica "e4" al indice 0x88 del tablero interno
+pub fn casilla_088(casilla: &str) -> i16 {
+    match utils::ALGEBRA.iter().position(|&r| r == casilla) {
+        Some(indice) => indice as i16,
+        None => 999,
+    }
+}
+
+// la casilla 0x88 bajo el raton, o None si esta fuera del tablero
+fn casilla_bajo(drawing_area: &gtk::DrawingArea, x: f64, y: f64) -> Option<i16> {
+    let lado = (get_square_size(drawing_area) * VARIABLES.read().unwrap().board_size) as f64;
+    if x < 0.0 || y < 0.0 || x >= lado || y >= lado {
+        return None;
+    }
+    Some(board_coords_to_square(drawing_area, x, y) as i16)
+}
+
 pub fn board_coords_to_square(drawing_area: &gtk::DrawingArea, x: f64, y: f64) -> i32 {
     let var; 
     {
@@ -1,7 +1,8 @@
 mod utils;
 mod dialogos;
+mod editor;
 pub mod inicia_gui;
-#[allow(unused)]
-pub mod ajedrez;
+// la logica del ajedrez esta en su propio crate (ajedrez/), sin dependencias de GTK
+pub use ::ajedrez;
 
 //pub use self::inicia_gui;
\ No newline at end of file